@@ -146,6 +146,44 @@ pub enum Commands {
     /// Install drivers for ESP-IDF. This is only available on Windows platforms.
     InstallDrivers,
 
+    /// Show or prune the shared tool download cache
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommands,
+    },
+
+    /// Find and remove tool versions, archives and Python environments no installation uses anymore
+    Gc {
+        #[arg(short, long, help = "Remove the orphaned files without asking for confirmation")]
+        yes: bool,
+        #[arg(long, help = "Only print a JSON report of the orphaned files, nothing is removed")]
+        dry_run: bool,
+    },
+
+    /// Check that the tools, Python environment, activation script and checkout of an installation are intact
+    Verify {
+        #[arg(help = "ID, name or path of the IDF installation")]
+        identifier: Option<String>,
+        #[arg(long, help = "Print the report as JSON")]
+        json: bool,
+    },
+
+    /// Move an installed ESP-IDF release to a newer patch release in place, keeping its id and name
+    Update {
+        #[arg(help = "ID, name or path of the IDF installation")]
+        identifier: String,
+        #[arg(long, help = "Release to update to, e.g. v5.3.2. Defaults to the newest patch release of the installed version")]
+        to: Option<String>,
+        #[arg(long, help = "Remove the tool versions the new release no longer uses")]
+        remove_old_tools: bool,
+    },
+
+    /// Change the tools of an installed ESP-IDF
+    Tools {
+        #[command(subcommand)]
+        command: ToolsCommands,
+    },
+
     /// Generate shell completion script to stdout
     Completions {
         #[arg(help = "Shell for which to generate completion.", value_parser = value_parser!(Shell))]
@@ -156,6 +194,56 @@ pub enum Commands {
     HelpJson,
 }
 
+#[derive(Subcommand, Debug, Clone)]
+pub enum CacheCommands {
+    /// Show the location, size and content of the download cache
+    Info {
+        #[arg(long, help = "Directory of the download cache (default: download_cache_dir of the config file, else ~/.espressif/cache on POSIX, C:\\Espressif\\cache on Windows)")]
+        cache_dir: Option<PathBuf>,
+        #[arg(short, long, value_name = "FILE", help = "Config file to read download_cache_dir and download_cache_max_size_mb from")]
+        config: Option<PathBuf>,
+    },
+
+    /// Remove least recently used archives until the cache fits the size limit
+    Prune {
+        #[arg(long, help = "Directory of the download cache (default: download_cache_dir of the config file, else ~/.espressif/cache on POSIX, C:\\Espressif\\cache on Windows)")]
+        cache_dir: Option<PathBuf>,
+        #[arg(long, help = "Size limit in megabytes to prune the cache to. Use 0 to empty the cache. Default is download_cache_max_size_mb of the config file, else 10240.")]
+        max_size_mb: Option<u64>,
+        #[arg(short, long, value_name = "FILE", help = "Config file to read download_cache_dir and download_cache_max_size_mb from")]
+        config: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum ToolsCommands {
+    /// Install newer versions of outdated tools and regenerate the activation scripts
+    Upgrade {
+        #[arg(help = "ID, name or path of the IDF installation")]
+        identifier: String,
+        #[arg(help = "Tools to upgrade, all outdated tools if none are given")]
+        tools: Vec<String>,
+        #[arg(long, help = "Remove the versions replaced by the upgrade")]
+        remove_old: bool,
+    },
+
+    /// Install a tool into an installation and add it to its activation scripts
+    Add {
+        #[arg(help = "ID, name or path of the IDF installation")]
+        identifier: String,
+        #[arg(help = "Name of the tool as listed in tools.json, e.g. qemu-xtensa")]
+        tool: String,
+    },
+
+    /// Remove an optional tool from an installation and from its activation scripts
+    Remove {
+        #[arg(help = "ID, name or path of the IDF installation")]
+        identifier: String,
+        #[arg(help = "Name of the tool as listed in tools.json")]
+        tool: String,
+    },
+}
+
 #[derive(Parser, Debug, Clone, Default)]
 pub struct InstallArgs {
     #[arg(
@@ -181,7 +269,7 @@ pub struct InstallArgs {
     #[arg(
         short,
         long,
-        help = "you can provide multiple versions of ESP-IDF separated by comma, you can also specify exact commit hash"
+        help = "you can provide multiple versions of ESP-IDF separated by comma, you can also specify exact commit hash or a git source like git+https://host/org/esp-idf@branch, @<commit> or @refs/pull/123/head"
     )]
     pub idf_versions: Option<String>,
 
@@ -319,6 +407,144 @@ pub struct InstallArgs {
         help = "Whether to create a .bat activation script on Windows. This is useful for users who want to activate the ESP-IDF environment using a batch file instead of PowerShell. Default is false. This is for legacy compatibility reasons as the default activation method on Windows is now PowerShell script.",
     )]
     pub create_bat_activation_script: Option<bool>, // Whether to create a .bat activation script on Windows
+
+    #[arg(
+        long,
+        help = "Maximum number of tools to download in parallel. Default is 4. Use 1 to download the tools one by one.",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub max_concurrent_downloads: Option<u64>,
+
+    #[arg(
+        long,
+        help = "Directory of the shared tool download cache. Archives are stored by their sha256, so a tool used by several ESP-IDF versions or served from different mirrors is downloaded only once. The cache is off unless a directory is set, e.g. ~/.espressif/cache on POSIX, C:\\Espressif\\cache on Windows."
+    )]
+    pub download_cache_dir: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Maximum size of the download cache in megabytes. Least recently used archives are removed once the limit is exceeded. Default is 10240."
+    )]
+    pub download_cache_max_size_mb: Option<u64>,
+
+    #[arg(
+        long,
+        help = "Directory of the shared tool store. When set, every tool version is extracted there once and installations link to it instead of holding their own copy. A tool is removed from the store only when no installation uses it anymore. Disabled by default."
+    )]
+    pub tool_store_dir: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "How installations link to the tool store: 'symlink' (default) or 'hardlink'. Symlinks fall back to hard links where they can not be created.",
+        value_parser = ["symlink", "hardlink"]
+    )]
+    pub tool_store_link_mode: Option<String>,
+
+    #[arg(
+        long,
+        help = "Proxy used for all downloads, git operations and pip, e.g. http://proxy.example.com:3128 or socks5h://127.0.0.1:1080. Defaults to the HTTPS_PROXY, ALL_PROXY or HTTP_PROXY environment variable."
+    )]
+    pub proxy: Option<String>,
+
+    #[arg(
+        long,
+        help = "Comma separated list of hosts reached without the proxy. A host also matches its subdomains, '*' disables the proxy. Hosts in the NO_PROXY environment variable are bypassed as well."
+    )]
+    pub no_proxy: Option<String>,
+
+    #[arg(
+        long,
+        help = "PEM bundle of additional root certificates to trust, for networks that inspect TLS traffic. It is used by all downloads and handed to git and pip."
+    )]
+    pub ca_bundle: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Maximum download rate in KiB/s. The limit is shared by all parallel downloads, git clones and offline archive downloads. Unlimited by default."
+    )]
+    pub max_download_rate: Option<u64>,
+
+    #[arg(
+        long,
+        help = "Extract tool archives while they are downloaded instead of writing them to disk first. The archive is verified against its sha256 once it has been received and the extracted tool is removed again on a mismatch. Streamed archives are not kept in the download cache. Default is false."
+    )]
+    pub stream_tool_extraction: Option<bool>,
+
+    #[arg(
+        long,
+        help = "File in the tools.json format with additional tools, e.g. internal flashing or signing tools. Its tools are installed and exported like the ones of ESP-IDF, a tool with the name of an ESP-IDF tool replaces it.",
+        value_parser = |s: &str| -> Result<PathBuf, String> {
+            to_absolute_path(s).map(PathBuf::from).map_err(|e| e.to_string())
+        }
+    )]
+    pub tools_overlay: Option<PathBuf>,
+
+    #[arg(
+        long = "pin-tool",
+        value_name = "NAME=VERSION",
+        help = "Install this version of a tool instead of the one tools.json recommends, e.g. openocd-esp32=v0.12.0-esp32-20240318. The version must be listed in tools.json. Can be given several times.",
+        value_parser = parse_tool_pin
+    )]
+    pub tool_pins: Vec<(String, String)>,
+
+    #[arg(
+        long,
+        value_name = "PLATFORM",
+        help = "Download and extract the tools for another platform instead of this machine, e.g. to prepare a tools directory for linux-arm64 on an x86_64 CI runner. One of win32, win64, macos, macos-arm64, linux-i686, linux-amd64, linux-arm64, linux-armel or linux-armhf. Steps that run the tools, like version checks, the Python environment and the activation scripts, are skipped and the result is not registered as an installation.",
+        value_parser = idf_im_lib::idf_tools::parse_host_platform
+    )]
+    pub host_platform: Option<String>,
+
+    #[arg(
+        long,
+        help = "Share git objects between ESP-IDF clones through a bare repository in the eim data directory. Clones reference it as a git alternate, so a further ESP-IDF version only downloads the objects the store does not have yet. Clones made this way are full clones made with the system git and need the store to stay in place. Default is false."
+    )]
+    pub git_object_store: Option<bool>,
+
+    #[arg(
+        long,
+        help = "Make the shallow clone of ESP-IDF partial (filter=blob:none): git downloads the contents of checked out files only, so together with --idf-sparse-exclude the directories left out are never downloaded. Needs the system git. Default is false."
+    )]
+    pub idf_blobless_clone: Option<bool>,
+
+    #[arg(
+        long,
+        value_name = "PATHS",
+        help = "Comma separated directories of ESP-IDF to leave out of the checkout with a sparse checkout, e.g. docs,examples. Paths are relative to the ESP-IDF root; components, tools and the export scripts can not be left out.",
+        value_parser = parse_sparse_exclude
+    )]
+    pub idf_sparse_exclude: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "PATHS",
+        help = "Comma separated patch files (plain diffs or git format-patch mbox files) or directories of .patch, .diff and .mbox files to apply to ESP-IDF after it is checked out, in order. They are stored with the installation and applied again by `update` and `fix`.",
+        value_parser = parse_patch_paths
+    )]
+    pub idf_patches: Option<String>,
+}
+
+fn parse_tool_pin(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((name, version)) if !name.trim().is_empty() && !version.trim().is_empty() => {
+            Ok((name.trim().to_string(), version.trim().to_string()))
+        }
+        _ => Err(format!("expected NAME=VERSION, got '{}'", s)),
+    }
+}
+
+fn parse_sparse_exclude(s: &str) -> Result<String, String> {
+    let paths: Vec<String> = s.split(',').map(str::to_string).collect();
+    idf_im_lib::git_tools::validate_sparse_exclude(&paths).map(|paths| paths.join(","))
+}
+
+/// Makes the patch paths absolute, they are applied again later from another directory.
+fn parse_patch_paths(s: &str) -> Result<String, String> {
+    s.split(',')
+        .filter(|path| !path.trim().is_empty())
+        .map(|path| to_absolute_path(path.trim()).map_err(|e| e.to_string()))
+        .collect::<Result<Vec<String>, String>>()
+        .map(|paths| paths.join(","))
 }
 
 impl IntoIterator for InstallArgs {
@@ -426,6 +652,76 @@ impl IntoIterator for InstallArgs {
                 "create_bat_activation_script".to_string(),
                 self.create_bat_activation_script.map(Into::into),
             ),
+            (
+                "max_concurrent_downloads".to_string(),
+                self.max_concurrent_downloads.map(Into::into),
+            ),
+            (
+                "download_cache_dir".to_string(),
+                self.download_cache_dir.map(|p| p.to_str().unwrap().into()),
+            ),
+            (
+                "download_cache_max_size_mb".to_string(),
+                self.download_cache_max_size_mb.map(Into::into),
+            ),
+            (
+                "tool_store_dir".to_string(),
+                self.tool_store_dir.map(|p| p.to_str().unwrap().into()),
+            ),
+            (
+                "tool_store_link_mode".to_string(),
+                self.tool_store_link_mode.map(Into::into),
+            ),
+            ("proxy".to_string(), self.proxy.map(Into::into)),
+            ("no_proxy".to_string(), self.no_proxy.map(Into::into)),
+            (
+                "ca_bundle".to_string(),
+                self.ca_bundle.map(|p| p.to_str().unwrap().into()),
+            ),
+            (
+                "max_download_rate".to_string(),
+                self.max_download_rate.map(Into::into),
+            ),
+            (
+                "stream_tool_extraction".to_string(),
+                self.stream_tool_extraction.map(Into::into),
+            ),
+            (
+                "tools_overlay".to_string(),
+                self.tools_overlay.map(|p| p.to_str().unwrap().into()),
+            ),
+            (
+                "tool_pins".to_string(),
+                (!self.tool_pins.is_empty()).then(|| {
+                    self.tool_pins
+                        .into_iter()
+                        .map(|(name, version)| (name, config::Value::from(version)))
+                        .collect::<config::Map<String, config::Value>>()
+                        .into()
+                }),
+            ),
+            (
+                "host_platform".to_string(),
+                self.host_platform.map(Into::into),
+            ),
+            (
+                "git_object_store".to_string(),
+                self.git_object_store.map(Into::into),
+            ),
+            (
+                "idf_blobless_clone".to_string(),
+                self.idf_blobless_clone.map(Into::into),
+            ),
+            (
+                "idf_sparse_exclude".to_string(),
+                self.idf_sparse_exclude
+                    .map(|s| s.split(',').collect::<Vec<&str>>().into()),
+            ),
+            (
+                "idf_patches".to_string(),
+                self.idf_patches
+                    .map(|s| s.split(',').collect::<Vec<&str>>().into()),
+            ),
         ]
         .into_iter()
     }
@@ -3,18 +3,26 @@ use std::path::PathBuf;
 use anyhow::Context;
 use anyhow::anyhow;
 use cli_args::Cli;
+use cli_args::CacheCommands;
 use cli_args::Commands;
 use clap::CommandFactory;
 use clap_complete::generate;
 use cli_args::InstallArgs;
+use cli_args::ToolsCommands;
 use fern::Dispatch;
+use helpers::generic_confirm;
 use helpers::generic_input;
 use helpers::generic_select;
+use idf_im_lib::download_cache::{default_download_cache_dir, DownloadCache, DEFAULT_DOWNLOAD_CACHE_MAX_SIZE_MB};
+use idf_im_lib::gc::{remove_orphans, OrphanKind};
 use idf_im_lib::get_log_directory;
 use idf_im_lib::logging::formatter;
 use idf_im_lib::idf_config::IDF_CONFIG_FILE_NAME;
+use idf_im_lib::idf_update::update_installation;
 use idf_im_lib::settings::Settings;
+use idf_im_lib::tool_management::{add_tool, installation_settings, remove_tool, upgrade_tools};
 use idf_im_lib::utils::is_valid_idf_directory;
+use idf_im_lib::verify::{CheckKind, CheckStatus, VerifyReport};
 use idf_im_lib::version_manager::get_selected_version;
 use idf_im_lib::version_manager::prepare_settings_for_fix_idf_installation;
 use idf_im_lib::version_manager::remove_single_idf_version;
@@ -112,15 +120,21 @@ fn format_tool_list_report(report: &idf_im_lib::version_manager::ToolListReport)
     );
     println!();
     for entry in &report.tools {
+        let pinned_marker = entry
+            .pinned
+            .as_ref()
+            .map(|version| t!("list_tools.pinned_marker", version = version).to_string())
+            .unwrap_or_default();
         if entry.tool.install == "on_request" {
             println!(
-                "{}: {}{}",
+                "{}: {}{}{}",
                 entry.tool.name,
                 entry.tool.description,
-                t!("list_tools.optional_marker")
+                t!("list_tools.optional_marker"),
+                pinned_marker
             );
         } else {
-            println!("{}: {}", entry.tool.name, entry.tool.description);
+            println!("{}: {}{}", entry.tool.name, entry.tool.description, pinned_marker);
         }
         for vi in &entry.version_inspections {
             if !vi.has_platform_download {
@@ -164,6 +178,23 @@ fn format_tool_list_report(report: &idf_im_lib::version_manager::ToolListReport)
     }
 }
 
+fn format_size(bytes: u64) -> String {
+    const MB: f64 = 1_048_576.0;
+    const GB: f64 = 1_073_741_824.0;
+    let b = bytes as f64;
+    if b >= GB {
+        format!("{:.2} GB", b / GB)
+    } else {
+        format!("{:.1} MB", b / MB)
+    }
+}
+
+/// Settings of the cache commands, read from `config` when given.
+fn cache_settings(config: Option<PathBuf>) -> anyhow::Result<Settings> {
+    Settings::new(config, Vec::new())
+        .map_err(|err| anyhow::anyhow!(t!("cache.error", error = err.to_string())))
+}
+
 fn format_feature_list_report(report: &idf_im_lib::version_manager::FeatureListReport) {
     println!(
         "{}",
@@ -193,6 +224,57 @@ fn format_feature_list_report(report: &idf_im_lib::version_manager::FeatureListR
     }
 }
 
+/// Reports tool downloads outside of the wizard as log lines.
+fn log_tool_progress(progress: idf_im_lib::DownloadProgress) {
+    use idf_im_lib::DownloadProgress;
+    let file_name = |url: &str| {
+        std::path::Path::new(url)
+            .file_name()
+            .map(|f| f.to_string_lossy().into_owned())
+            .unwrap_or_else(|| url.to_string())
+    };
+    match progress {
+        DownloadProgress::Downloaded(url) => {
+            info!("{}", t!("wizard.tool.download.success", filename = file_name(&url)));
+        }
+        DownloadProgress::Extracted(url, dest) => {
+            info!("{}", t!("wizard.tool.extract.success", filename = file_name(&url), dest = dest));
+        }
+        DownloadProgress::Error(err) => error!("{}", err),
+        _ => {}
+    }
+}
+
+fn format_verify_report(report: &VerifyReport) {
+    println!(
+        "{}",
+        t!("verify.title", name = report.name, path = report.path)
+    );
+    println!();
+    for check in &report.checks {
+        let mark = match check.status {
+            CheckStatus::Pass => "✓",
+            CheckStatus::Fail => "✗",
+            CheckStatus::Skipped => "-",
+        };
+        let kind = match check.kind {
+            CheckKind::Tool => t!("verify.kind.tool"),
+            CheckKind::ToolFiles => t!("verify.kind.tool_files"),
+            CheckKind::ExportPath => t!("verify.kind.export_path"),
+            CheckKind::PythonInterpreter => t!("verify.kind.python_interpreter"),
+            CheckKind::PythonPackages => t!("verify.kind.python_packages"),
+            CheckKind::ActivationScript => t!("verify.kind.activation_script"),
+            CheckKind::GitCheckout => t!("verify.kind.git_checkout"),
+            CheckKind::Patch => t!("verify.kind.patch"),
+        };
+        match &check.detail {
+            Some(detail) => println!("{} [{}] {}: {}", mark, kind, check.subject, detail),
+            None => println!("{} [{}] {}", mark, kind, check.subject),
+        }
+    }
+    println!();
+}
+
 pub async fn run_cli(cli: Cli) -> anyhow::Result<()> {
   let do_not_track = cli.do_not_track;
     // Initial tracking of CLI start
@@ -325,6 +407,9 @@ pub async fn run_cli(cli: Cli) -> anyhow::Result<()> {
                     Ok(_) => debug!("ESP-IDF JSON initialized at configured path."),
                     Err(e) => warn!("Failed to initialize ESP-IDF JSON: {}. IDE integration may not work correctly.", e),
                   }
+                  idf_im_lib::apply_network_settings(&settings)?;
+                  idf_im_lib::idf_tools::set_host_platform(settings.host_platform.as_deref())
+                    .map_err(|e| anyhow::anyhow!(e))?;
                   debug!("Settings after adjustments: {:?}", settings);
                   // Check if the provided path is already an installed IDF
                   if let Some(ref path) = settings.path {
@@ -398,6 +483,10 @@ pub async fn run_cli(cli: Cli) -> anyhow::Result<()> {
                             } else {
                                 println!("{}", t!("list.version", name = version.name, path = version.path));
                             }
+                            let patches = idf_im_lib::idf_patches::applied_patches(std::path::Path::new(&version.path));
+                            if !patches.is_empty() {
+                                println!("{}", t!("list.patches", patches = patches.join(", ")));
+                            }
                         }
                         Ok(())
                     }
@@ -781,6 +870,7 @@ pub async fn run_cli(cli: Cli) -> anyhow::Result<()> {
                       Ok(_) => debug!("ESP-IDF JSON initialized at configured path."),
                       Err(e) => warn!("Failed to initialize ESP-IDF JSON: {}. IDE integration may not work correctly.", e),
                     }
+                    idf_im_lib::apply_network_settings(&settings)?;
                     let time = std::time::SystemTime::now();
                     if !do_not_track {
                       track_cli_event("CLI wizard started", Some(json!({}))).await;
@@ -855,6 +945,7 @@ pub async fn run_cli(cli: Cli) -> anyhow::Result<()> {
           // passed to `fix` override those preserved values (and the defaults).
           let mut settings = prepare_settings_for_fix_idf_installation(path_to_fix.clone(), config_path.as_ref()).await?;
           settings.apply_cli_overrides(install_args.into_iter())?;
+          idf_im_lib::apply_network_settings(&settings)?;
           let result = wizard::run_wizzard_run(settings).await;
           match result {
             Ok(r) => {
@@ -894,6 +985,316 @@ pub async fn run_cli(cli: Cli) -> anyhow::Result<()> {
             gui::run(settings, Some(log_level), do_not_track);
             Ok(())
         }
+        Commands::Cache { command } => match command {
+            CacheCommands::Info { cache_dir, config } => {
+                let settings = cache_settings(config)?;
+                let cache = DownloadCache::new(
+                    cache_dir
+                        .or(settings.download_cache_dir)
+                        .unwrap_or_else(default_download_cache_dir),
+                    None,
+                );
+                match cache.report() {
+                    Ok(report) => {
+                        println!("{}", t!("cache.info.location", path = report.root.display()));
+                        if report.entries.is_empty() {
+                            println!("{}", t!("cache.info.empty"));
+                        } else {
+                            for entry in &report.entries {
+                                println!(
+                                    "{}",
+                                    t!(
+                                        "cache.info.entry",
+                                        filename = entry.filename,
+                                        size = format_size(entry.size),
+                                        sha256 = entry.sha256
+                                    )
+                                );
+                            }
+                        }
+                        println!(
+                            "{}",
+                            t!(
+                                "cache.info.total",
+                                count = report.entries.len(),
+                                size = format_size(report.total_size)
+                            )
+                        );
+                        Ok(())
+                    }
+                    Err(err) => Err(anyhow::anyhow!(t!("cache.error", error = err.to_string()))),
+                }
+            }
+            CacheCommands::Prune {
+                cache_dir,
+                max_size_mb,
+                config,
+            } => {
+                let settings = cache_settings(config)?;
+                let cache = DownloadCache::new(
+                    cache_dir
+                        .or(settings.download_cache_dir)
+                        .unwrap_or_else(default_download_cache_dir),
+                    None,
+                );
+                let max_size_mb = max_size_mb
+                    .or(settings.download_cache_max_size_mb)
+                    .unwrap_or(DEFAULT_DOWNLOAD_CACHE_MAX_SIZE_MB);
+                match cache.prune(max_size_mb.saturating_mul(1024 * 1024)) {
+                    Ok(report) => {
+                        for entry in &report.removed {
+                            info!(
+                                "{}",
+                                t!(
+                                    "cache.prune.removed",
+                                    filename = entry.filename,
+                                    size = format_size(entry.size)
+                                )
+                            );
+                        }
+                        println!(
+                            "{}",
+                            t!(
+                                "cache.prune.summary",
+                                count = report.removed.len(),
+                                freed = format_size(report.freed_bytes),
+                                remaining = format_size(report.remaining_bytes)
+                            )
+                        );
+                        Ok(())
+                    }
+                    Err(err) => Err(anyhow::anyhow!(t!("cache.error", error = err.to_string()))),
+                }
+            }
+        },
+        Commands::Gc { yes, dry_run } => {
+            let report = idf_im_lib::gc::find_orphans(config_path.as_ref())
+                .map_err(|err| anyhow::anyhow!(t!("gc.error", error = err.to_string())))?;
+            if dry_run {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+                return Ok(());
+            }
+            for dir in &report.skipped {
+                warn!("{}", t!("gc.skipped", path = dir.display()));
+            }
+            // Repacking keeps every object the clones may borrow, so it needs no confirmation
+            if let Some(store) = idf_im_lib::git_object_store::GitObjectStore::existing() {
+                match store.repack() {
+                    Ok(()) => println!("{}", t!("gc.git_object_store.repacked", path = store.root().display())),
+                    Err(err) => warn!(
+                        "{}",
+                        t!(
+                            "gc.git_object_store.repack_failed",
+                            path = store.root().display(),
+                            error = err.to_string()
+                        )
+                    ),
+                }
+            }
+            if report.orphans.is_empty() {
+                println!("{}", t!("gc.nothing_to_remove"));
+                return Ok(());
+            }
+            for orphan in &report.orphans {
+                let kind = match orphan.kind {
+                    OrphanKind::ToolVersion => t!("gc.kind.tool_version"),
+                    OrphanKind::Archive => t!("gc.kind.archive"),
+                    OrphanKind::PythonEnv => t!("gc.kind.python_env"),
+                };
+                println!(
+                    "{}",
+                    t!(
+                        "gc.entry",
+                        kind = kind,
+                        path = orphan.path.display(),
+                        size = format_size(orphan.size)
+                    )
+                );
+            }
+            println!(
+                "{}",
+                t!(
+                    "gc.total",
+                    count = report.orphans.len(),
+                    size = format_size(report.total_size)
+                )
+            );
+            if !yes && !generic_confirm("gc.confirm").map_err(|err| anyhow::anyhow!(err))? {
+                println!("{}", t!("gc.aborted"));
+                return Ok(());
+            }
+            let result = remove_orphans(&report.orphans);
+            for (path, err) in &result.failed {
+                error!("{}", t!("gc.remove_failed", path = path.display(), error = err));
+            }
+            println!(
+                "{}",
+                t!(
+                    "gc.summary",
+                    count = result.removed.len(),
+                    freed = format_size(result.freed_bytes)
+                )
+            );
+            if result.failed.is_empty() {
+                Ok(())
+            } else {
+                Err(anyhow::anyhow!(t!("gc.some_failed", count = result.failed.len())))
+            }
+        }
+        Commands::Verify { identifier, json } => {
+            let identifier = if let Some(id) = identifier {
+                id
+            } else {
+                match idf_im_lib::version_manager::list_installed_versions(config_path.as_ref()) {
+                    Ok(versions) if versions.is_empty() => {
+                        warn!("{}", t!("list.no_versions"));
+                        return Ok(());
+                    }
+                    Ok(versions) => {
+                        let options: Vec<String> =
+                            versions.iter().map(|v| v.name.clone()).collect();
+                        match generic_select(&t!("verify.idf_prompt"), &options) {
+                            Ok(selected) => selected,
+                            Err(err) => return Err(anyhow::anyhow!(err)),
+                        }
+                    }
+                    Err(err) => {
+                        debug!("Error: {}", err);
+                        warn!("{}", t!("list.no_versions"));
+                        info!("{}", t!("cli.hint.custom_json_path"));
+                        return Ok(());
+                    }
+                }
+            };
+
+            let report = idf_im_lib::verify::verify_installation(&identifier, config_path.as_ref())
+                .map_err(|err| anyhow::anyhow!(t!("verify.error", error = err)))?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                format_verify_report(&report);
+            }
+            if report.passed {
+                if !json {
+                    println!("{}", t!("verify.passed"));
+                }
+                Ok(())
+            } else {
+                Err(anyhow::anyhow!(t!("verify.failed", count = report.failures().count())))
+            }
+        }
+        Commands::Update {
+            identifier,
+            to,
+            remove_old_tools,
+        } => {
+            let settings = installation_settings(&identifier, config_path.as_ref())
+                .map_err(|err| anyhow::anyhow!(t!("update.error", error = err)))?;
+            idf_im_lib::apply_network_settings(&settings)?;
+
+            let report = update_installation(
+                &identifier,
+                to.as_deref(),
+                remove_old_tools,
+                config_path.as_ref(),
+                false,
+                log_tool_progress,
+            )
+            .await
+            .map_err(|err| anyhow::anyhow!(t!("update.error", error = err)))?;
+            if !report.is_updated() {
+                println!("{}", t!("update.up_to_date", name = report.idf.name, version = report.from));
+                return Ok(());
+            }
+            for upgrade in &report.tools {
+                println!(
+                    "{}",
+                    t!("tools.upgrade.upgraded", name = upgrade.name, from = upgrade.from, to = upgrade.to)
+                );
+                for version in &upgrade.removed {
+                    println!("{}", t!("tools.upgrade.removed", name = upgrade.name, version = version));
+                }
+            }
+            for (name, version) in &report.added_tools {
+                println!("{}", t!("update.tool_added", name = name, version = version));
+            }
+            if !report.patches.is_empty() {
+                println!("{}", t!("update.patches", patches = report.patches.join(", ")));
+            }
+            println!(
+                "{}",
+                t!("update.updated", name = report.idf.name, from = report.from, to = report.to)
+            );
+            Ok(())
+        }
+        Commands::Tools { command } => match command {
+            ToolsCommands::Upgrade {
+                identifier,
+                tools,
+                remove_old,
+            } => {
+                let settings = installation_settings(&identifier, config_path.as_ref())
+                    .map_err(|err| anyhow::anyhow!(t!("tools.error", error = err)))?;
+                idf_im_lib::apply_network_settings(&settings)?;
+
+                let report = upgrade_tools(
+                    &identifier,
+                    &tools,
+                    remove_old,
+                    config_path.as_ref(),
+                    false,
+                    log_tool_progress,
+                )
+                .await
+                .map_err(|err| anyhow::anyhow!(t!("tools.error", error = err)))?;
+                for upgrade in &report.upgraded {
+                    println!(
+                        "{}",
+                        t!("tools.upgrade.upgraded", name = upgrade.name, from = upgrade.from, to = upgrade.to)
+                    );
+                    for version in &upgrade.removed {
+                        println!("{}", t!("tools.upgrade.removed", name = upgrade.name, version = version));
+                    }
+                }
+                for name in &report.up_to_date {
+                    println!("{}", t!("tools.upgrade.up_to_date", name = name));
+                }
+                for (name, version) in &report.pinned {
+                    println!("{}", t!("tools.upgrade.pinned", name = name, version = version));
+                }
+                if report.upgraded.is_empty() && tools.is_empty() {
+                    println!("{}", t!("tools.upgrade.nothing", name = report.idf.name));
+                }
+                Ok(())
+            }
+            ToolsCommands::Add { identifier, tool } => {
+                let settings = installation_settings(&identifier, config_path.as_ref())
+                    .map_err(|err| anyhow::anyhow!(t!("tools.error", error = err)))?;
+                idf_im_lib::apply_network_settings(&settings)?;
+
+                let change = add_tool(&identifier, &tool, config_path.as_ref(), false, log_tool_progress)
+                    .await
+                    .map_err(|err| anyhow::anyhow!(t!("tools.error", error = err)))?;
+                for version in &change.versions {
+                    println!("{}", t!("tools.add.installed", name = change.tool, version = version, idf = change.idf.name));
+                }
+                for version in &change.kept {
+                    println!("{}", t!("tools.add.present", name = change.tool, version = version, idf = change.idf.name));
+                }
+                Ok(())
+            }
+            ToolsCommands::Remove { identifier, tool } => {
+                let change = remove_tool(&identifier, &tool, config_path.as_ref(), false)
+                    .map_err(|err| anyhow::anyhow!(t!("tools.error", error = err)))?;
+                for version in &change.versions {
+                    println!("{}", t!("tools.remove.removed", name = change.tool, version = version, idf = change.idf.name));
+                }
+                for version in &change.kept {
+                    println!("{}", t!("tools.remove.kept", name = change.tool, version = version));
+                }
+                Ok(())
+            }
+        },
         Commands::InstallDrivers => {
           match std::env::consts::OS {
             "windows" => {
@@ -1,31 +1,38 @@
 use anyhow::anyhow;
 use anyhow::Result;
 use dialoguer::FolderSelect;
+use idf_im_lib::git_object_store::GitObjectStore;
+use idf_im_lib::git_tools::IdfCloneMode;
 use idf_im_lib::idf_features::FeatureInfo;
 use idf_im_lib::idf_features::get_requirements_json_url;
 use idf_im_lib::idf_features::RequirementsMetadata;
 use idf_im_lib::idf_tools::Tool;
 use idf_im_lib::idf_tools::ToolsFile;
+use idf_im_lib::idf_tools::ToolSetupOptions;
+use idf_im_lib::idf_tools::ToolSource;
 use idf_im_lib::offline_installer::copy_components_from_offline_archive;
 use idf_im_lib::offline_installer::copy_idf_from_offline_archive;
 use idf_im_lib::offline_installer::install_prerequisites_offline;
 use idf_im_lib::offline_installer::merge_requirements_files;
 use idf_im_lib::offline_installer::use_offline_archive;
 use idf_im_lib::settings::Settings;
+use idf_im_lib::settings::VersionPaths;
 use idf_im_lib::tool_selection::fetch_tools_file;
 use idf_im_lib::tool_selection::get_required_tool_names;
 use idf_im_lib::tool_selection::get_tool_names;
 use idf_im_lib::tool_selection::get_tools_json_url;
 use idf_im_lib::utils::copy_dir_contents;
+use idf_im_lib::utils::MirrorFailover;
 use idf_im_lib::utils::extract_zst_archive;
 use idf_im_lib::{ensure_path, DownloadProgress};
 use idf_im_lib::git_tools::ProgressMessage;
-use indicatif::{ProgressBar, ProgressState, ProgressStyle};
+use indicatif::{MultiProgress, ProgressBar, ProgressState, ProgressStyle};
 use log::{debug, error, info, warn};
 use rust_i18n::t;
 use serde::de;
 use std::collections::HashMap;
 use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
 use std::thread;
 use std::{
     env,
@@ -117,6 +124,9 @@ pub struct DownloadConfig {
     pub idf_mirror: Option<String>,
     pub recurse_submodules: Option<bool>,
     pub non_interactive: Option<bool>,
+    pub object_store: Option<GitObjectStore>,
+    pub clone_mode: IdfCloneMode,
+    pub patches: Vec<String>,
 }
 
 pub enum DownloadError {
@@ -138,7 +148,10 @@ fn handle_download_error(err: String) -> Result<(), DownloadError> {
     }
 }
 
-pub fn download_idf(config: DownloadConfig) -> Result<(), DownloadError> {
+/// Clones ESP-IDF, failing over to the other IDF mirrors when the selected one fails.
+///
+/// Returns the mirror that served the clone, or `None` when an existing checkout was kept.
+pub async fn download_idf(config: DownloadConfig) -> Result<Option<String>, DownloadError> {
     idf_im_lib::ensure_path(&config.idf_path)
         .map_err(|err| DownloadError::PathCreationFailed(err.to_string()))?;
 
@@ -183,15 +196,24 @@ pub fn download_idf(config: DownloadConfig) -> Result<(), DownloadError> {
 
     info!("{}", t!("wizard.idf.cloning"));
 
-    match idf_im_lib::git_tools::get_esp_idf(
+    let failover = MirrorFailover::new(
+        config.idf_mirror.as_deref(),
+        idf_im_lib::get_idf_mirrors_list(),
+    );
+    match idf_im_lib::git_tools::get_esp_idf_with_failover(
         &config.idf_path,
         config.repo_stub.as_deref(),
         &config.idf_version,
-        config.idf_mirror.as_deref(),
+        &failover,
         config.recurse_submodules.unwrap_or_default(),
+        config.object_store.as_ref(),
+        &config.clone_mode,
+        &config.patches,
         tx,
-    ) {
-        Ok(_) => {
+    )
+    .await
+    {
+        Ok((_, mirror)) => {
             debug!("{}", t!("wizard.idf.success"));
             match handle.join() {
                 Ok(_) => {
@@ -201,7 +223,7 @@ pub fn download_idf(config: DownloadConfig) -> Result<(), DownloadError> {
                     error!("{}", t!("wizard.idf.progress_bar.error"));
                 }
             }
-            Ok(())
+            Ok(Some(mirror))
         }
         Err(err) => {
             if config.non_interactive == Some(true) {
@@ -213,12 +235,12 @@ pub fn download_idf(config: DownloadConfig) -> Result<(), DownloadError> {
                     || err.contains("not empty")
                 {
                     warn!("IDF path already present; continuing (non-interactive): {}", err);
-                    Ok(())
+                    Ok(None)
                 } else {
                     Err(DownloadError::DownloadFailed(err))
                 }
             } else {
-                handle_download_error(err)
+                handle_download_error(err).map(|_| None)
             }
         }
     }
@@ -275,52 +297,77 @@ async fn download_and_extract_tools(
     tools: &ToolsFile,
     download_dir: &PathBuf,
     install_dir: &PathBuf,
-) -> anyhow::Result<HashMap<String, (String, idf_im_lib::idf_tools::Download)>> {
+    idf_path: &Path,
+) -> anyhow::Result<(
+    HashMap<String, (String, idf_im_lib::idf_tools::Download)>,
+    HashMap<String, ToolSource>,
+)> {
     info!(
         "{}: {:?}",
         t!("wizard.tools_download.progress"),
         download_dir.display()
     );
-    let progress_bar = ProgressBar::new(0);
-    progress_bar.set_style(ProgressStyle::with_template("{spinner:.green} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {bytes}/{total_bytes} ({eta})").unwrap()
+    // Tools are downloaded in parallel, so every archive gets its own bar keyed by its URL
+    let multi_progress = MultiProgress::new();
+    let bar_style = ProgressStyle::with_template("{spinner:.green} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {bytes}/{total_bytes} ({eta}) {msg}").unwrap()
         .with_key("eta", |state: &ProgressState, w: &mut dyn Write| write!(w, "{:.1}s", state.eta().as_secs_f64()).unwrap())
-        .progress_chars("#>-"));
-
-    let progress_callback = move |progress: DownloadProgress| match progress {
-        DownloadProgress::Progress(current, total) => {
-            progress_bar.set_length(total);
-            progress_bar.set_position(current);
-        }
-        DownloadProgress::Indeterminate(current) => {
-            progress_bar.set_length(current * 2); // Set a length to allow the bar to move
-            progress_bar.set_position(current);
-        }
-        DownloadProgress::Complete => {
-            progress_bar.finish();
-        }
-        DownloadProgress::Error(err) => {
-            progress_bar.abandon_with_message(format!("Error: {}", err));
-        }
-        DownloadProgress::Start(_) => {
-            progress_bar.set_position(0);
-        }
-        DownloadProgress::Downloaded(url) => {
-            if let Some(filename) = Path::new(&url).file_name().and_then(|f| f.to_str()) {
-                info!("{}", t!("wizard.tool.download.success", filename = filename));
+        .progress_chars("#>-");
+    let progress_bars: Arc<Mutex<HashMap<String, ProgressBar>>> = Arc::new(Mutex::new(HashMap::new()));
+
+    let progress_callback = move |progress: DownloadProgress| {
+        let bar_for_url = |url: &str| -> ProgressBar {
+            progress_bars
+                .lock()
+                .unwrap()
+                .entry(url.to_string())
+                .or_insert_with(|| {
+                    let bar = multi_progress.add(ProgressBar::new(0));
+                    bar.set_style(bar_style.clone());
+                    if let Some(filename) = Path::new(url).file_name().and_then(|f| f.to_str()) {
+                        bar.set_message(filename.to_string());
+                    }
+                    bar
+                })
+                .clone()
+        };
+        match progress {
+            DownloadProgress::ToolProgress(url, current, total) => {
+                let bar = bar_for_url(&url);
+                bar.set_length(total);
+                bar.set_position(current);
             }
-        }
-        DownloadProgress::Verified(url) => {
-            if let Some(filename) = Path::new(&url).file_name().and_then(|f| f.to_str()) {
-                info!("{}", t!("wizard.tool.verified", filename = filename));
+            DownloadProgress::Start(url) => {
+                bar_for_url(&url).set_position(0);
             }
-        }
-        DownloadProgress::Extracted(url, dest) => {
-            if let Some(filename) = Path::new(&url).file_name().and_then(|f| f.to_str()) {
-                info!(
-                    "{}",
-                    t!("wizard.tool.extract.success", filename = filename, dest = dest)
-                );
+            DownloadProgress::Downloaded(url) => {
+                if let Some(bar) = progress_bars.lock().unwrap().remove(&url) {
+                    bar.finish();
+                }
+                if let Some(filename) = Path::new(&url).file_name().and_then(|f| f.to_str()) {
+                    info!("{}", t!("wizard.tool.download.success", filename = filename));
+                }
             }
+            DownloadProgress::Verified(url) => {
+                if let Some(filename) = Path::new(&url).file_name().and_then(|f| f.to_str()) {
+                    info!("{}", t!("wizard.tool.verified", filename = filename));
+                }
+            }
+            DownloadProgress::Extracted(url, dest) => {
+                if let Some(filename) = Path::new(&url).file_name().and_then(|f| f.to_str()) {
+                    info!(
+                        "{}",
+                        t!("wizard.tool.extract.success", filename = filename, dest = dest)
+                    );
+                }
+            }
+            DownloadProgress::Error(err) => {
+                error!("{}", err);
+            }
+            // setup_tools reports byte progress per URL and completion per tool,
+            // the single download events carry nothing more
+            DownloadProgress::Progress(_, _)
+            | DownloadProgress::Indeterminate(_)
+            | DownloadProgress::Complete => {}
         }
     };
 
@@ -329,12 +376,80 @@ async fn download_and_extract_tools(
         config.target.clone().unwrap(),
         download_dir,
         install_dir,
-        config.mirror.as_deref(),
+        &ToolSetupOptions::from_settings(config, idf_path.to_string_lossy()),
         progress_callback,
     )
     .await
 }
 
+/// Describes where a tool was taken from for the summary.
+fn tool_source_label(source: &ToolSource) -> String {
+    match source {
+        ToolSource::Downloaded(url) => url.clone(),
+        ToolSource::DownloadCache(path) => {
+            t!("wizard.tool_source.download_cache", path = path.display()).to_string()
+        }
+        ToolSource::DownloadFolder(path) => {
+            t!("wizard.tool_source.download_folder", path = path.display()).to_string()
+        }
+        ToolSource::ToolStore => t!("wizard.tool_source.tool_store").to_string(),
+        ToolSource::AlreadyInstalled => t!("wizard.tool_source.already_installed").to_string(),
+    }
+}
+
+/// Prints where each artifact was taken from, for downloads the mirror that finally served it.
+fn print_mirror_summary(mirror_summary: &[(String, String)]) {
+    if !mirror_summary.is_empty() {
+        println!("{}", t!("wizard.mirrors.summary"));
+        for (artifact, source) in mirror_summary {
+            println!("  {}: {}", artifact, source);
+        }
+    }
+}
+
+/// Clones ESP-IDF `idf_version` to `paths.idf_path`, noting the mirror that served it in
+/// `mirror_summary`.
+async fn download_idf_version(
+    config: &Settings,
+    paths: &VersionPaths,
+    idf_version: &str,
+    mirror_summary: &mut Vec<(String, String)>,
+) -> Result<(), String> {
+    let download_config = DownloadConfig {
+        idf_path: paths.idf_path.to_str().unwrap().to_string(),
+        repo_stub: config.repo_stub.clone(),
+        idf_version: idf_version.to_string(),
+        idf_mirror: config.idf_mirror.clone(),
+        recurse_submodules: config.recurse_submodules,
+        non_interactive: config.non_interactive,
+        object_store: GitObjectStore::from_settings(config),
+        clone_mode: IdfCloneMode::from_settings(config),
+        patches: config.idf_patches.clone().unwrap_or_default(),
+    };
+
+    match download_idf(download_config).await {
+        Ok(served_by) => {
+            debug!("{}", t!("wizard.idf.success"));
+            if let Some(mirror) = served_by {
+                mirror_summary.push((format!("ESP-IDF {}", idf_version), mirror));
+            }
+            Ok(())
+        }
+        Err(DownloadError::PathCreationFailed(err)) => {
+            error!("{} {:?}", t!("wizard.idf.path_creation_failure"), err);
+            Err(err)
+        }
+        Err(DownloadError::DownloadFailed(err)) => {
+            error!("{} {:?}", t!("wizard.idf.failure"), err);
+            Err(err)
+        }
+        Err(DownloadError::UserCancelled) => {
+            error!("{}", t!("wizard.idf.user_cancelled"));
+            Err("User cancelled the operation".to_string())
+        }
+    }
+}
+
 pub async fn run_wizzard_run(mut config: Settings) -> Result<(), String> {
     debug!(
         "{}",
@@ -345,6 +460,8 @@ pub async fn run_wizzard_run(mut config: Settings) -> Result<(), String> {
     );
 
     let offline_mode = config.use_local_archive.is_some();
+    // Tools prepared for another platform are only downloaded and extracted, never run
+    let foreign_platform = idf_im_lib::idf_tools::is_foreign_host_platform();
     let offline_archive_dir = if offline_mode {
         Some(TempDir::new().expect(&t!("wizard.error.create_temp_dir")))
     } else {
@@ -363,7 +480,7 @@ pub async fn run_wizzard_run(mut config: Settings) -> Result<(), String> {
             }
         };
         // install prerequisites offline
-        if std::env::consts::OS == "windows" {
+        if std::env::consts::OS == "windows" && !foreign_platform {
             match install_prerequisites_offline(&archive_dir, tool_install_directory.clone()).await {
                 Ok(_) => {
                     info!("{}", t!("wizard.prerequisites.offline_install.success"));
@@ -381,6 +498,8 @@ pub async fn run_wizzard_run(mut config: Settings) -> Result<(), String> {
 
     if config.skip_prerequisites_check.unwrap_or(false) {
         info!("{}", t!("wizard.prerequisites.skip_check"));
+    } else if foreign_platform {
+        info!("{}", t!("wizard.host_platform.skip_prerequisites"));
     } else {
         // Check prerequisites
         check_and_install_prerequisites(
@@ -392,14 +511,16 @@ pub async fn run_wizzard_run(mut config: Settings) -> Result<(), String> {
     }
 
     // Python sanity check
-    check_and_install_python(
-        config.non_interactive.unwrap_or_default(),
-        config.install_all_prerequisites.unwrap_or_default(),
-        config.python_version_override.clone(),
-        offline_mode,
-        tool_install_directory,
-    )
-    .await?;
+    if !foreign_platform {
+        check_and_install_python(
+            config.non_interactive.unwrap_or_default(),
+            config.install_all_prerequisites.unwrap_or_default(),
+            config.python_version_override.clone(),
+            offline_mode,
+            tool_install_directory,
+        )
+        .await?;
+    }
 
     if offline_mode {
         let archive_dir = offline_archive_dir.as_ref().unwrap();
@@ -433,6 +554,8 @@ pub async fn run_wizzard_run(mut config: Settings) -> Result<(), String> {
     }
     // Multiple version starts here
     let mut using_existing_idf = false;
+    // Which mirror finally served each downloaded artifact, for the summary at the end
+    let mut mirror_summary: Vec<(String, String)> = Vec::new();
     for idf_version in config.idf_versions.clone().unwrap() {
         let paths = config.get_version_paths(&idf_version).map_err(|err| {
             error!("Failed to get version paths: {}", err);
@@ -443,6 +566,15 @@ pub async fn run_wizzard_run(mut config: Settings) -> Result<(), String> {
         config.idf_path = Some(paths.idf_path.clone());
         idf_im_lib::add_path_to_path(paths.idf_path.to_str().unwrap());
 
+        // A source spec brings its own tools.json and requirements.json, so it is cloned first and
+        // both are read from the checkout
+        let from_source = idf_im_lib::idf_source::is_idf_source(&idf_version);
+        let mut idf_downloaded = false;
+        if from_source && !offline_mode && !using_existing_idf {
+            download_idf_version(&config, &paths, &idf_version, &mut mirror_summary).await?;
+            idf_downloaded = true;
+        }
+
         let requirements_files = if offline_mode {
             // Merge requirements
             let requirements_dir = paths.idf_path.join("tools").join("requirements");
@@ -462,6 +594,18 @@ pub async fn run_wizzard_run(mut config: Settings) -> Result<(), String> {
                 version: 1,
                 features: vec![feature_info],
             }
+        } else if from_source {
+            let requirements_json = paths.idf_path.join("tools").join("requirements.json");
+            match RequirementsMetadata::from_file(&requirements_json) {
+                Ok(files) => files,
+                Err(err) => {
+                    warn!("{}: {}. {}", t!("wizard.requirements.read_failure"), err, t!("wizard.features.selection_unavailable"));
+                    RequirementsMetadata {
+                        version: 1,
+                        features: vec![],
+                    }
+                }
+            }
         } else {
             let req_url = get_requirements_json_url(config.repo_stub.clone().as_deref(), &idf_version.to_string(), config.idf_mirror.clone().as_deref());
             debug!("repo url: {} ", req_url);
@@ -514,6 +658,15 @@ pub async fn run_wizzard_run(mut config: Settings) -> Result<(), String> {
         }
         let remote_tools_file = if offline_mode {
             None
+        } else if from_source {
+            let tools_json = paths.idf_path.join("tools").join("tools.json");
+            match idf_im_lib::idf_tools::read_and_parse_tools_file(&tools_json.to_string_lossy(), config.tools_overlay.as_deref()) {
+                Ok(file) => Some(file),
+                Err(err) => {
+                    warn!("{}: {}. {}", t!("wizard.tools.read_failure"), err, t!("wizard.tools.selection_unavailable"));
+                    None
+                }
+            }
         } else {
           let tools_url = get_tools_json_url(
               config.repo_stub.clone().as_deref(),
@@ -522,7 +675,13 @@ pub async fn run_wizzard_run(mut config: Settings) -> Result<(), String> {
           );
 
           // Try to fetch tools.json from remote first (for selection before download)
-          match fetch_tools_file(&tools_url) {
+          let fetched = fetch_tools_file(&tools_url)
+              .map_err(|e| e.to_string())
+              .and_then(|file| {
+                  idf_im_lib::idf_tools::apply_tools_overlay(file, config.tools_overlay.as_deref())
+                      .map_err(|e| e.to_string())
+              });
+          match fetched {
               Ok(file) => Some(file),
               Err(err) => {
                   warn!("{}: {}. {}",
@@ -580,7 +739,7 @@ pub async fn run_wizzard_run(mut config: Settings) -> Result<(), String> {
                 .collect::<Vec<String>>()
                 .join(", ")
         );
-        if selected_tools.iter().any(|t| t.name.contains("qemu")) {
+        if !foreign_platform && selected_tools.iter().any(|t| t.name.contains("qemu")) {
           let qemu_prereqs = idf_im_lib::system_dependencies::check_qemu_prerequisites();
             match qemu_prereqs {
             Ok(prereqs) if !prereqs.is_empty() => {
@@ -618,33 +777,21 @@ pub async fn run_wizzard_run(mut config: Settings) -> Result<(), String> {
         }
         if offline_mode && !using_existing_idf {
           error!("THIS SHOULD NOT HAPPEN: offline mode should be using existing IDF copied from offline archive, but it seems like the IDF is not present at the expected location. This likely means that the copy from offline archive failed. Please check previous logs for any errors related to copying IDF from offline archive.");
-        } else if !using_existing_idf {
-            // download idf
-            let download_config = DownloadConfig {
-                idf_path: paths.idf_path.to_str().unwrap().to_string(),
-                repo_stub: config.repo_stub.clone(),
-                idf_version: idf_version.to_string(),
-                idf_mirror: config.idf_mirror.clone(),
-                recurse_submodules: config.recurse_submodules,
-                non_interactive: config.non_interactive,
-            };
-
-            match download_idf(download_config) {
-                Ok(_) => {
-                    debug!("{}", t!("wizard.idf.success"));
-                }
-                Err(DownloadError::PathCreationFailed(err)) => {
-                    error!("{} {:?}", t!("wizard.idf.path_creation_failure"), err);
-                    return Err(err);
+        } else if !using_existing_idf && !idf_downloaded {
+            download_idf_version(&config, &paths, &idf_version, &mut mirror_summary).await?;
+        }
+        if using_existing_idf {
+            // Fixing an installation applies its patch queue again
+            let patches = config.idf_patches.clone().unwrap_or_default();
+            match idf_im_lib::idf_patches::apply_patches(&paths.idf_path, &patches) {
+                Ok(applied) if !applied.is_empty() => {
+                    info!("{}", t!("wizard.idf.patches_applied", patches = applied.join(", ")));
                 }
-                Err(DownloadError::DownloadFailed(err)) => {
-                    error!("{} {:?}", t!("wizard.idf.failure"), err);
+                Ok(_) => {}
+                Err(err) => {
+                    error!("{}", t!("wizard.idf.patches_failed", error = err));
                     return Err(err);
                 }
-                Err(DownloadError::UserCancelled) => {
-                    error!("{}", t!("wizard.idf.user_cancelled"));
-                    return Err("User cancelled the operation".to_string());
-                }
             }
         }
         // setup tool directories
@@ -690,8 +837,11 @@ pub async fn run_wizzard_run(mut config: Settings) -> Result<(), String> {
             )
         );
 
-        let mut tools = idf_im_lib::idf_tools::read_and_parse_tools_file(&validated_file)
+        let tools = idf_im_lib::idf_tools::read_and_parse_tools_file(&validated_file, config.tools_overlay.as_deref())
             .map_err(|err| format!("{}: {}", t!("wizard.tools_json.unparsable"), err))?;
+        idf_im_lib::idf_tools::check_tool_pins(&tools, &config.tool_pins.clone().unwrap_or_default())?;
+        // Pinned tool versions replace the recommended ones for download, checks and export paths
+        let mut tools = idf_im_lib::idf_tools::apply_tool_versions(tools, &config.chosen_tool_versions());
         ////////////////////// IMPORTANT MODIFY CLANG TOOL TO ALWAYS BE INSTALLED /////////////////////
         /// This is needed because the IDEs expect clang to be always installed                     ///
         /// ninja is included as without it the users are not able to actually build the projects   ///
@@ -736,7 +886,7 @@ pub async fn run_wizzard_run(mut config: Settings) -> Result<(), String> {
             .collect();
         }
 
-        if tools.tools.iter().find(|&x| x.name.contains("qemu")).is_some() {
+        if !foreign_platform && tools.tools.iter().find(|&x| x.name.contains("qemu")).is_some() {
             let qemu_prereqs = idf_im_lib::system_dependencies::check_qemu_prerequisites();
             match qemu_prereqs {
             Ok(prereqs) if !prereqs.is_empty() => {
@@ -774,15 +924,25 @@ pub async fn run_wizzard_run(mut config: Settings) -> Result<(), String> {
             &tools,
             &tool_download_directory,
             &tool_install_directory,
+            &paths.idf_path,
         )
         .await
         {
-            Ok(list) => {
+            Ok((list, sources)) => {
                 info!(
                     "{}: {}",
                     t!("wizard.tools.downloaded"),
                     tools_json_file.display()
                 );
+                let mut served: Vec<(String, String)> = list
+                    .iter()
+                    .filter_map(|(name, (version, _))| {
+                        let source = sources.get(name)?;
+                        Some((format!("{} {}", name, version), tool_source_label(source)))
+                    })
+                    .collect();
+                served.sort();
+                mirror_summary.extend(served);
                 list
             }
             Err(err) => {
@@ -811,6 +971,18 @@ pub async fn run_wizzard_run(mut config: Settings) -> Result<(), String> {
             }
         }
 
+        if foreign_platform {
+            info!(
+                "{}",
+                t!(
+                    "wizard.host_platform.tools_prepared",
+                    platform = config.host_platform.clone().unwrap_or_default(),
+                    path = tool_install_directory.display()
+                )
+            );
+            continue;
+        }
+
         match idf_im_lib::python_utils::install_python_env(
             &paths,
             &paths.actual_version,
@@ -823,11 +995,18 @@ pub async fn run_wizzard_run(mut config: Settings) -> Result<(), String> {
                 None
             },
             &config.pypi_mirror,
+            Some(&MirrorFailover::new(
+                config.pypi_mirror.as_deref(),
+                idf_im_lib::get_pypi_mirrors_list(),
+            )),
         )
         .await
         {
-            Ok(_) => {
+            Ok(served_by) => {
                 info!("{}", t!("wizard.python.env_installed"));
+                if let Some(mirror) = served_by {
+                    mirror_summary.push((format!("Python packages ({})", idf_version), mirror));
+                }
             }
             Err(err) => {
                 error!("Failed to install Python environment: {}", err);
@@ -872,6 +1051,11 @@ pub async fn run_wizzard_run(mut config: Settings) -> Result<(), String> {
         )
     }
     save_config_if_desired(&config)?;
+    if foreign_platform {
+        print_mirror_summary(&mirror_summary);
+        info!("{}", t!("wizard.host_platform.not_registered"));
+        return Ok(());
+    }
     let ide_conf_path_tmp = PathBuf::from(&config.esp_idf_json_path.clone().unwrap_or_default());
     debug!(
         "{}",
@@ -895,6 +1079,8 @@ pub async fn run_wizzard_run(mut config: Settings) -> Result<(), String> {
         }
     };
 
+    print_mirror_summary(&mirror_summary);
+
     match std::env::consts::OS {
         "windows" => {
             println!("{}", t!("wizard.windows.finish_steps.line_1"));
@@ -150,6 +150,9 @@ where
     })?;
     updater(&mut settings);
     log::debug!("Settings after update: {:?}", settings);
+    if let Err(e) = idf_im_lib::apply_network_settings(&settings) {
+        error!("Failed to apply network settings: {}", e);
+    }
     Ok(())
 }
 
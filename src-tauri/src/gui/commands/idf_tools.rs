@@ -1,4 +1,4 @@
-use crate::gui::{app_state::{get_settings_non_blocking, update_settings}, ui::{InstallationProgress, InstallationStage, MessageLevel, ProgressBar, emit_installation_event, emit_log_message, send_message, send_tools_message}, utils::{get_mirror_to_use, MirrorType}};
+use crate::gui::{app_state::{get_settings_non_blocking, update_settings}, ui::{InstallationProgress, InstallationStage, MessageLevel, ProgressBar, emit_installation_event, emit_log_message, send_message, send_tools_message}, utils::{get_mirror_failover, get_mirror_to_use, MirrorType}};
 use anyhow::{anyhow, Context, Result};
 
 use idf_im_lib::{
@@ -121,7 +121,7 @@ pub async fn setup_tools(
         .map_err(|e| anyhow!("Failed to validate tools.json: {}", e))?;
 
     // Parse tools.json and get list of tools to download
-    let mut tools = idf_tools::read_and_parse_tools_file(&tool_setup.tools_json_path)
+    let mut tools = idf_tools::read_and_parse_tools_file(&tool_setup.tools_json_path, settings.tools_overlay.as_deref())
         .map_err(|e| {
             emit_log_message(
                 app_handle,
@@ -130,6 +130,12 @@ pub async fn setup_tools(
             );
             anyhow!(t!("gui.setup_tools.tools_json_parse_failed", error = e.to_string()).to_string())
         })?;
+    idf_tools::check_tool_pins(&tools, &settings.tool_pins.clone().unwrap_or_default()).map_err(|e| {
+        emit_log_message(app_handle, MessageLevel::Error, e.clone());
+        anyhow!(e)
+    })?;
+    // Pinned tool versions replace the recommended ones for download, checks and export paths
+    tools = idf_tools::apply_tool_versions(tools, &settings.chosen_tool_versions());
     ////////////////////// IMPORTANT MODIFY CLANG TOOL TO ALWAYS BE INSTALLED /////////////////////
     /// This is needed because the IDEs expect clang to be always installed                     ///
     /// ninja is included as without it the users are not able to actually build the projects   ///
@@ -217,7 +223,6 @@ pub async fn setup_tools(
     // Progress tracking with interior mutability
     let total_tools = tools.tools.len() as f32;
     let completed_tools = Arc::new(Mutex::new(0u32));
-    let current_tool_name = Arc::new(Mutex::new(String::new()));
     let base_percentage = 65u32; // Tools start at 65%
     let tools_range = 25u32; // Tools take 65-90% (25% range)
 
@@ -225,15 +230,14 @@ pub async fn setup_tools(
     let app_handle_clone = app_handle.clone();
     let idf_version_clone = idf_version.to_string();
     let completed_tools_clone = completed_tools.clone();
-    let current_tool_name_clone = current_tool_name.clone();
 
     let progress_callback = move |progress: DownloadProgress| {
         match progress {
-            DownloadProgress::Progress(current, total) => {
+            DownloadProgress::ToolProgress(url, current, total) => {
                 if total > 0 {
                     let tool_progress = current * 100 / total;
                     let completed = *completed_tools_clone.lock().unwrap();
-                    let tool_name = current_tool_name_clone.lock().unwrap().clone();
+                    let tool_name = tool_name_from_url(&url);
 
                     let overall_tool_progress = (completed as f32 / total_tools) * tools_range as f32;
                     let current_tool_contribution = (tool_progress as f32 / 100.0) * (tools_range as f32 / total_tools);
@@ -259,17 +263,16 @@ pub async fn setup_tools(
 
             DownloadProgress::Indeterminate(current) => {
                 let completed = *completed_tools_clone.lock().unwrap();
-                let tool_name = current_tool_name_clone.lock().unwrap().clone();
 
                 let overall_tool_progress = (completed as f32 / total_tools) * tools_range as f32;
 
+                // Several tools download at once, so a progress without a URL can not be
+                // attributed to one of them
                 emit_installation_event(&app_handle_clone, InstallationProgress {
                     stage: InstallationStage::Tools,
                     percentage: overall_tool_progress as u32,
                     message: t!("gui.setup_tools.downloading",
-                        tool_name = tool_name.split('/').last()
-                            .unwrap_or(&tool_name)
-                            .replace("-", " ")
+                        tool_name = t!("gui.setup_tools.unknown_tool")
                     ).to_string(),
                     detail: Some(t!("gui.setup_tools.tool_progress_indeterminate",
                         bytes = current,
@@ -279,22 +282,8 @@ pub async fn setup_tools(
             }
 
             DownloadProgress::Start(url) => {
-                // Extract tool name from URL
-                let tool_name = if let Some(filename) = Path::new(&url).file_name().and_then(|f| f.to_str()) {
-                    // Try to extract tool name from filename (remove version/platform info)
-                    let clean_name = filename
-                        .split('-')
-                        .take(3) // Take first few parts before version numbers
-                        .collect::<Vec<_>>()
-                        .join("-")
-                        .replace(".tar", "")
-                        .replace(".zip", "");
-                    clean_name
-                } else {
-                    t!("gui.setup_tools.unknown_tool").to_string()
-                };
-
-                *current_tool_name_clone.lock().unwrap() = tool_name.clone();
+                let tool_name = tool_name_from_url(&url);
+
                 let completed = *completed_tools_clone.lock().unwrap();
                 let overall_percentage = base_percentage + ((completed as f32 / total_tools) * tools_range as f32) as u32;
 
@@ -317,7 +306,7 @@ pub async fn setup_tools(
 
             DownloadProgress::Downloaded(url) => {
                 let completed = *completed_tools_clone.lock().unwrap();
-                let tool_name = current_tool_name_clone.lock().unwrap().clone();
+                let tool_name = tool_name_from_url(&url);
 
                 emit_installation_event(&app_handle_clone, InstallationProgress {
                     stage: InstallationStage::Tools,
@@ -338,7 +327,7 @@ pub async fn setup_tools(
 
             DownloadProgress::Verified(url) => {
                 let completed = *completed_tools_clone.lock().unwrap();
-                let tool_name = current_tool_name_clone.lock().unwrap().clone();
+                let tool_name = tool_name_from_url(&url);
 
                 emit_installation_event(&app_handle_clone, InstallationProgress {
                     stage: InstallationStage::Tools,
@@ -361,7 +350,7 @@ pub async fn setup_tools(
                 let mut completed = completed_tools_clone.lock().unwrap();
                 *completed += 1;
                 let completed_count = *completed;
-                let tool_name = current_tool_name_clone.lock().unwrap().clone();
+                let tool_name = tool_name_from_url(&url);
 
                 let overall_percentage = base_percentage + ((completed_count as f32 / total_tools) * tools_range as f32) as u32;
 
@@ -386,6 +375,10 @@ pub async fn setup_tools(
                     ).to_string());
             }
 
+            DownloadProgress::Progress(_, _) => {
+                // setup_tools reports byte progress per tool through ToolProgress
+            }
+
             DownloadProgress::Complete => {
                 emit_installation_event(&app_handle_clone, InstallationProgress {
                     stage: InstallationStage::Tools,
@@ -397,12 +390,11 @@ pub async fn setup_tools(
             }
 
             DownloadProgress::Error(err) => {
-                let tool_name = current_tool_name_clone.lock().unwrap().clone();
-
+                // The error names the failed tool
                 emit_installation_event(&app_handle_clone, InstallationProgress {
                     stage: InstallationStage::Error,
                     percentage: 0,
-                    message: t!("gui.setup_tools.tool_failed", tool_name = tool_name).to_string(),
+                    message: t!("gui.setup_tools.setup_failed").to_string(),
                     detail: Some(err.to_string()),
                     version: Some(idf_version_clone.clone()),
                 });
@@ -417,12 +409,16 @@ pub async fn setup_tools(
     let download_folder = PathBuf::from(&tool_setup.download_dir);
     let install_folder = PathBuf::from(&tool_setup.install_dir);
     // Use the library's setup_tools function
-    let installed_tools_list = idf_tools::setup_tools(
+    let (installed_tools_list, sources) = idf_tools::setup_tools(
         &tools,
         settings.target.clone().unwrap_or_default(),
         &download_folder,
         &install_folder,
-        Some(&tools_mirror_to_use),
+        &idf_tools::ToolSetupOptions {
+            mirror_failover: Some(get_mirror_failover(app_handle, MirrorType::IDFTools, &tools_mirror_to_use)),
+            mirror: Some(tools_mirror_to_use),
+            ..idf_tools::ToolSetupOptions::from_settings(settings, idf_path.to_string_lossy())
+        },
         progress_callback,
     )
     .await
@@ -437,6 +433,30 @@ pub async fn setup_tools(
         anyhow!("Failed to setup tools: {}", e)
     })?;
 
+    let mut served_by: Vec<_> = installed_tools_list.iter().collect();
+    served_by.sort_by(|a, b| a.0.cmp(b.0));
+    for (tool_name, (version, _)) in served_by {
+        let artifact = format!("{} {}", tool_name, version);
+        let message = match sources.get(tool_name) {
+            Some(idf_tools::ToolSource::Downloaded(url)) => {
+                t!("gui.installation.served_by_mirror", artifact = artifact, mirror = url.as_str())
+            }
+            Some(idf_tools::ToolSource::DownloadCache(path)) => t!("gui.installation.tool_source",
+                artifact = artifact,
+                source = t!("wizard.tool_source.download_cache", path = path.display())),
+            Some(idf_tools::ToolSource::DownloadFolder(path)) => t!("gui.installation.tool_source",
+                artifact = artifact,
+                source = t!("wizard.tool_source.download_folder", path = path.display())),
+            Some(idf_tools::ToolSource::ToolStore) => t!("gui.installation.tool_source",
+                artifact = artifact,
+                source = t!("wizard.tool_source.tool_store")),
+            Some(idf_tools::ToolSource::AlreadyInstalled) | None => {
+                t!("gui.installation.already_installed", artifact = artifact)
+            }
+        };
+        emit_log_message(app_handle, MessageLevel::Info, message.to_string());
+    }
+
     let tools_install_folder = &install_folder;
 
     info!("Setting up tools... to directory: {}", tools_install_folder.display());
@@ -490,10 +510,17 @@ pub async fn setup_tools(
         true, //TODO: actually read from config
         &features_for_version,
         offline_archive_dir, // Offline archive directory
-        &Some(pypi_mirror_to_use), // PyPI mirror
+        &Some(pypi_mirror_to_use.clone()), // PyPI mirror
+        Some(&get_mirror_failover(&app_handle, MirrorType::PyPI, &pypi_mirror_to_use)),
     ).await {
-        Ok(_) => {
+        Ok(served_by) => {
             info!("Python environment installed");
+            if let Some(mirror) = served_by {
+                emit_log_message(app_handle, MessageLevel::Info,
+                    t!("gui.installation.served_by_mirror",
+                        artifact = format!("Python packages ({})", idf_version),
+                        mirror = mirror).to_string());
+            }
             emit_installation_event(app_handle, InstallationProgress {
                 stage: InstallationStage::Python,
                 percentage: 93,
@@ -555,6 +582,21 @@ pub async fn setup_tools(
     Ok((export_paths,export_vars))
 }
 
+/// Derives a human readable tool name from its download URL (drops version and platform info).
+fn tool_name_from_url(url: &str) -> String {
+    if let Some(filename) = Path::new(url).file_name().and_then(|f| f.to_str()) {
+        filename
+            .split('-')
+            .take(3) // Take first few parts before version numbers
+            .collect::<Vec<_>>()
+            .join("-")
+            .replace(".tar", "")
+            .replace(".zip", "")
+    } else {
+        t!("gui.setup_tools.unknown_tool").to_string()
+    }
+}
+
 #[tauri::command]
 pub async fn get_features_list_all_versions(
     app_handle: AppHandle,
@@ -573,6 +615,11 @@ pub async fn get_features_list_all_versions(
     let mut result = Vec::new();
 
     for version in versions {
+        if idf_im_lib::idf_source::is_idf_source(&version) {
+            // Only known once the source is checked out, the core requirements of the checkout are installed
+            info!("Feature selection is not available for {}", version);
+            continue;
+        }
         let req_url = get_requirements_json_url(
             settings.repo_stub.clone().as_deref(),
             &version,
@@ -661,6 +708,11 @@ pub async fn get_tools_list_all_versions(
     let mut result = Vec::new();
 
     for version in versions {
+        if idf_im_lib::idf_source::is_idf_source(&version) {
+            // Only known once the source is checked out, its tools.json is used as it is
+            info!("Tool selection is not available for {}", version);
+            continue;
+        }
         let tools_url = idf_im_lib::tool_selection::get_tools_json_url(
             settings.repo_stub.clone().as_deref(),
             &version,
@@ -669,7 +721,14 @@ pub async fn get_tools_list_all_versions(
 
         info!("Fetching tools for version {} from: {}", version, tools_url);
 
-        let mut tools_file = match fetch_tools_file_async(&tools_url).await {
+        let fetched = fetch_tools_file_async(&tools_url)
+            .await
+            .map_err(|e| e.to_string())
+            .and_then(|file| {
+                idf_tools::apply_tools_overlay(file, settings.tools_overlay.as_deref())
+                    .map_err(|e| e.to_string())
+            });
+        let mut tools_file = match fetched {
             Ok(file) => file,
             Err(err) => {
                 warn!(
@@ -12,6 +12,7 @@ use crate::gui::{
         MessageLevel,
     },
     utils::{
+      get_mirror_failover,
       get_mirror_to_use,
       is_path_empty_or_nonexistent,
       format_bytes,
@@ -173,28 +174,31 @@ async fn download_idf(
             mirror = mirror_to_use.as_str()).to_string(),
     );
 
-    // Clone values needed for the blocking task
-    let idf_path_str = idf_path.to_str().unwrap().to_string();
-    let repo_stub = settings.repo_stub.clone();
-    let version_owned = version.to_string();
-    let recurse_submodules = settings.recurse_submodules.unwrap_or_default();
-
-    let result = match std::thread::spawn(move || {
-      idf_im_lib::git_tools::get_esp_idf(
-        &idf_path_str,
-        repo_stub.as_deref(),
-        &version_owned,
-        Some(&mirror_to_use),
-        recurse_submodules,
+    // Other mirrors are tried in latency order when the clone fails on the selected one
+    let failover = get_mirror_failover(app_handle, MirrorType::IDF, &mirror_to_use);
+    let result = idf_im_lib::git_tools::get_esp_idf_with_failover(
+        idf_path.to_str().unwrap(),
+        settings.repo_stub.as_deref(),
+        version,
+        &failover,
+        settings.recurse_submodules.unwrap_or_default(),
+        idf_im_lib::git_object_store::GitObjectStore::from_settings(settings).as_ref(),
+        &idf_im_lib::git_tools::IdfCloneMode::from_settings(settings),
+        settings.idf_patches.as_deref().unwrap_or_default(),
         tx,
-      )
-    }).join(){
-        Ok(res) => res,
-        Err(e) => Err(rust_i18n::t!("gui.installation.thread_panic", error = format!("{:?}", e)).to_string()),
-    };
+    )
+    .await;
 
     match result {
-        Ok(_) => {
+        Ok((_, served_by)) => {
+            emit_log_message(
+                app_handle,
+                MessageLevel::Info,
+                rust_i18n::t!("gui.installation.served_by_mirror",
+                    artifact = format!("ESP-IDF {}", version),
+                    mirror = served_by.as_str()).to_string(),
+            );
+
             emit_installation_event(app_handle, InstallationProgress {
                 stage: InstallationStage::Extract,
                 percentage: 70,
@@ -248,6 +252,16 @@ pub async fn install_single_version(
     );
 
     debug!("Using IDF version: {}", paths.actual_version);
+    // Fixing an installation applies its patch queue again
+    let patches = settings.idf_patches.clone().unwrap_or_default();
+    let applied = idf_im_lib::idf_patches::apply_patches(&paths.idf_path, &patches)?;
+    if !applied.is_empty() {
+      send_message(
+          &app_handle,
+          format!("Applied the patches {}", applied.join(", ")),
+          "info".to_string(),
+      );
+    }
   } else {
     download_idf(&app_handle, settings, &version, &paths.idf_path).await?;
   }
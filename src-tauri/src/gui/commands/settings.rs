@@ -544,6 +544,9 @@ pub fn reset_settings_to_default(app_handle: AppHandle) -> Result<(), String> {
 
     *settings = Settings::default();
     log::info!("Settings reset to default values");
+    if let Err(e) = idf_im_lib::apply_network_settings(&settings) {
+        log::error!("Failed to apply network settings: {}", e);
+    }
 
     Ok(())
 }
@@ -631,3 +634,31 @@ pub fn set_cleanup(app_handle: AppHandle, cleanup: bool) -> Result<(), String> {
     );
     Ok(())
 }
+
+/// Gets the download rate limit in KiB/s (0 means unlimited)
+#[tauri::command]
+pub fn get_max_download_rate(app_handle: AppHandle) -> u64 {
+    let settings = match get_settings_non_blocking(&app_handle) {
+        Ok(s) => s,
+        Err(e) => {
+            send_message(&app_handle, e, "error".to_string());
+            return 0;
+        }
+    };
+    settings.max_download_rate.unwrap_or(0)
+}
+
+/// Sets the download rate limit in KiB/s (0 removes the limit)
+#[tauri::command]
+pub fn set_max_download_rate(app_handle: AppHandle, rate: u64) -> Result<(), String> {
+    info!("Setting max download rate: {} KiB/s", rate);
+    update_settings(&app_handle, |settings| {
+        settings.max_download_rate = if rate > 0 { Some(rate) } else { None };
+    })?;
+    send_message(
+        &app_handle,
+        t!("gui.settings.max_download_rate_updated").to_string(),
+        "info".to_string(),
+    );
+    Ok(())
+}
@@ -18,7 +18,11 @@ const EIM_VERSION: &str = env!("CARGO_PKG_VERSION");
 
 #[tauri::command]
 pub async fn fetch_json_from_url(url: String) -> Result<Value, String> {
-    let response = reqwest::get(&url)
+    let client = idf_im_lib::proxy::client_builder()
+        .build()
+        .map_err(|e| e.to_string())?;
+    let response = client.get(&url)
+        .send()
         .await
         .map_err(|e| e.to_string())?;
 
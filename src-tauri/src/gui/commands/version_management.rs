@@ -1,7 +1,10 @@
 use std::path::PathBuf;
 
 use idf_im_lib::idf_config::{IdfInstallation, IDF_CONFIG_FILE_NAME};
+use idf_im_lib::idf_update::IdfUpdateReport;
 use idf_im_lib::settings::Settings;
+use idf_im_lib::tool_management::{ToolChange, ToolUpgradeReport};
+use idf_im_lib::verify::VerifyReport;
 use idf_im_lib::version_manager::{FeatureListReport, ToolListReport};
 use log::{debug, error, info};
 use tauri::{AppHandle, Manager};
@@ -144,7 +147,7 @@ pub fn generate_installation_config_for_version(app_handle: AppHandle, id: Strin
     }
   };
 
-  let settings = match bincode::deserialize::<Settings>(config_bytes.as_slice()) {
+  let settings = match Settings::from_installation_config(config_bytes.as_slice()) {
     Ok(settings) => settings,
     Err(e) => {
       error!("Failed to deserialize settings from binary: {}", e);
@@ -206,3 +209,122 @@ pub fn list_idf_features(app_handle: AppHandle, id: String) -> Option<FeatureLis
     }
   }
 }
+
+#[tauri::command]
+pub async fn verify_installation(app_handle: AppHandle, id: String) -> Result<VerifyReport, String> {
+  debug!("Verifying installation {}", id);
+  let config_path = get_config_path_from_state(&app_handle);
+
+  // Runs every tool's version command, so keep it off the async runtime
+  let report = tokio::task::spawn_blocking(move || {
+    idf_im_lib::verify::verify_installation(&id, config_path.as_ref())
+  })
+  .await
+  .map_err(|e| e.to_string())?;
+  match &report {
+    Ok(report) => info!(
+      "Verification of {} finished, {} checks failed",
+      report.name,
+      report.failures().count()
+    ),
+    Err(e) => error!("Failed to verify installation: {}", e),
+  }
+  report
+}
+
+#[tauri::command]
+pub async fn upgrade_idf_tools(
+  app_handle: AppHandle,
+  id: String,
+  tools: Vec<String>,
+  remove_old: bool,
+) -> Result<ToolUpgradeReport, String> {
+  debug!("Upgrading tools {:?} of installation {}", tools, id);
+  let config_path = get_config_path_from_state(&app_handle);
+
+  let progress_callback = |progress: idf_im_lib::DownloadProgress| match progress {
+    idf_im_lib::DownloadProgress::Extracted(url, dest) => info!("Extracted {} to {}", url, dest),
+    idf_im_lib::DownloadProgress::Error(err) => error!("{}", err),
+    _ => {}
+  };
+  let report = idf_im_lib::tool_management::upgrade_tools(
+    &id,
+    &tools,
+    remove_old,
+    config_path.as_ref(),
+    true,
+    progress_callback,
+  )
+  .await;
+  match &report {
+    Ok(report) => info!("Upgraded {} tools of {}", report.upgraded.len(), report.idf.name),
+    Err(e) => error!("Failed to upgrade tools: {}", e),
+  }
+  report
+}
+
+#[tauri::command]
+pub async fn update_idf_installation(
+  app_handle: AppHandle,
+  id: String,
+  to: Option<String>,
+  remove_old_tools: bool,
+) -> Result<IdfUpdateReport, String> {
+  debug!("Updating installation {} to {:?}", id, to);
+  let config_path = get_config_path_from_state(&app_handle);
+
+  let progress_callback = |progress: idf_im_lib::DownloadProgress| match progress {
+    idf_im_lib::DownloadProgress::Extracted(url, dest) => info!("Extracted {} to {}", url, dest),
+    idf_im_lib::DownloadProgress::Error(err) => error!("{}", err),
+    _ => {}
+  };
+  let report = idf_im_lib::idf_update::update_installation(
+    &id,
+    to.as_deref(),
+    remove_old_tools,
+    config_path.as_ref(),
+    true,
+    progress_callback,
+  )
+  .await;
+  match &report {
+    Ok(report) if report.is_updated() => info!("Updated {} from {} to {}", report.idf.name, report.from, report.to),
+    Ok(report) => info!("{} is at the newest patch release {}", report.idf.name, report.from),
+    Err(e) => error!("Failed to update installation: {}", e),
+  }
+  report
+}
+
+#[tauri::command]
+pub async fn add_idf_tool(app_handle: AppHandle, id: String, tool: String) -> Result<ToolChange, String> {
+  debug!("Adding tool {} to installation {}", tool, id);
+  let config_path = get_config_path_from_state(&app_handle);
+
+  let progress_callback = |progress: idf_im_lib::DownloadProgress| match progress {
+    idf_im_lib::DownloadProgress::Extracted(url, dest) => info!("Extracted {} to {}", url, dest),
+    idf_im_lib::DownloadProgress::Error(err) => error!("{}", err),
+    _ => {}
+  };
+  let change =
+    idf_im_lib::tool_management::add_tool(&id, &tool, config_path.as_ref(), true, progress_callback).await;
+  if let Err(e) = &change {
+    error!("Failed to add tool {}: {}", tool, e);
+  }
+  change
+}
+
+#[tauri::command]
+pub async fn remove_idf_tool(app_handle: AppHandle, id: String, tool: String) -> Result<ToolChange, String> {
+  debug!("Removing tool {} from installation {}", tool, id);
+  let config_path = get_config_path_from_state(&app_handle);
+
+  let change = tokio::task::spawn_blocking(move || {
+    idf_im_lib::tool_management::remove_tool(&id, &tool, config_path.as_ref(), true)
+  })
+  .await
+  .map_err(|e| e.to_string())?;
+  if let Err(e) = &change {
+    error!("Failed to remove tool: {}", e);
+  }
+  change
+}
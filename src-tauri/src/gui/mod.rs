@@ -66,6 +66,9 @@ async fn download_idf(
       version,
       settings.idf_mirror.as_deref(),
       settings.recurse_submodules.unwrap_or_default(),
+      idf_im_lib::git_object_store::GitObjectStore::from_settings(settings).as_ref(),
+      &idf_im_lib::git_tools::IdfCloneMode::from_settings(settings),
+      settings.idf_patches.as_deref().unwrap_or_default(),
       tx,
     ) {
         Ok(_) => {
@@ -264,6 +267,9 @@ pub fn run(
                 Ok(_) => log::debug!("ESP-IDF JSON initialized at configured path."),
                 Err(e) => log::warn!("Failed to initialize ESP-IDF JSON: {}. IDE integration may not work correctly.", e),
               }
+              if let Err(e) = idf_im_lib::apply_network_settings(&settings) {
+                log::warn!("Failed to apply network settings: {}", e);
+              }
             }
             Err(e) => log::warn!("Failed to lock settings: {}", e),
           }
@@ -350,6 +356,11 @@ pub fn run(
             generate_installation_config_for_version,
             list_idf_tools,
             list_idf_features,
+            verify_installation,
+            upgrade_idf_tools,
+            update_idf_installation,
+            add_idf_tool,
+            remove_idf_tool,
             write_text_file,
             get_tool_download_folder_name,
             set_tool_download_folder_name,
@@ -357,6 +368,8 @@ pub fn run(
             set_tool_install_folder_name,
             get_cleanup,
             set_cleanup,
+            get_max_download_rate,
+            set_max_download_rate,
             get_offline_archives,
             get_available_drives,
             start_simple_offline_setup,
@@ -2,7 +2,7 @@ use std::{ fs, path::{ Path, PathBuf } };
 
 use idf_im_lib::settings::Settings;
 use tauri::AppHandle;
-use idf_im_lib::utils::MirrorEntry;
+use idf_im_lib::utils::{MirrorEntry, MirrorFailover};
 use crate::gui::app_state;
 
 pub enum MirrorType {
@@ -32,7 +32,7 @@ pub fn is_path_empty_or_nonexistent(path: &str, versions: &[String]) -> bool {
           Ok(_entries) => {
               // Check if any version directories exist
               for v in versions {
-                  let new_path = path.join(v);
+                  let new_path = path.join(idf_im_lib::idf_source::installation_name(v));
                   if new_path.exists() {
                       return false;
                   }
@@ -106,6 +106,20 @@ pub async fn get_mirror_to_use(app_handle: &AppHandle, mirror_type: MirrorType,
     }
 }
 
+/// Mirrors to fail over to when `selected` fails, ranked with the cached latency entries when
+/// the mirrors were already measured.
+pub fn get_mirror_failover(app_handle: &AppHandle, mirror_type: MirrorType, selected: &str) -> MirrorFailover {
+    let (cached_latency_entries, mirrors_list) = match mirror_type {
+        MirrorType::IDF => (app_state::get_idf_mirror_latency_entries(app_handle), idf_im_lib::get_idf_mirrors_list()),
+        MirrorType::IDFTools => (app_state::get_tools_mirror_latency_entries(app_handle), idf_im_lib::get_idf_tools_mirrors_list()),
+        MirrorType::PyPI => (app_state::get_pypi_mirror_latency_entries(app_handle), idf_im_lib::get_pypi_mirrors_list()),
+    };
+    match cached_latency_entries {
+        Some(entries) if !entries.is_empty() => MirrorFailover::with_latency_entries(Some(selected), entries),
+        _ => MirrorFailover::new(Some(selected), mirrors_list),
+    }
+}
+
 pub fn parse_version(v: &str) -> Vec<u32> {
     v.trim_start_matches('v')
         .split('.')
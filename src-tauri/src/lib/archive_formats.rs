@@ -0,0 +1,545 @@
+//! Archive format detection and the registry of decoders behind [`crate::decompress_archive`].
+//!
+//! Formats are recognised from their magic bytes; the file extension is only consulted when no
+//! signature matches, e.g. for old tar archives without the `ustar` marker. A compression format
+//! wraps another stream, which is detected again, so `foo.tar.gz`, a gzip compressed tar saved
+//! under an unrelated name and a plain `foo.gz` are all handled. Supporting a new format only
+//! takes an entry in [`FORMATS`].
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Cursor, Read, Write};
+use std::path::Path;
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+
+use bzip2::read::BzDecoder;
+use flate2::read::GzDecoder;
+use log::warn;
+use zip::ZipArchive;
+
+use crate::safe_extract::{Guard, MAX_EXTRACTED_SIZE};
+use crate::DecompressionError;
+
+/// Number of bytes inspected to detect a format. tar keeps its marker at offset 257.
+const HEADER_LEN: usize = 512;
+
+/// Number of decoded chunks buffered ahead of the reader of a decoder running on its own thread.
+const DECODED_CHUNKS: usize = 16;
+
+pub type BoxedReader = Box<dyn Read + Send>;
+
+/// How a registered format is unpacked.
+#[derive(Clone, Copy)]
+pub enum FormatKind {
+    /// A compression layer. The decoded stream is detected again, usually it is a tar archive.
+    Compression(fn(BoxedReader) -> io::Result<BoxedReader>),
+    /// An archive which is read sequentially and can be unpacked from any stream.
+    StreamArchive(fn(BoxedReader, &Path) -> Result<(), DecompressionError>),
+    /// An archive which needs random access and is unpacked from a file.
+    FileArchive(fn(&Path, &Path) -> Result<(), DecompressionError>),
+}
+
+/// A format known to [`crate::decompress_archive`].
+pub struct ArchiveFormat {
+    /// Short name used in log messages.
+    pub name: &'static str,
+    /// Lower case file extensions, without the leading dot.
+    pub extensions: &'static [&'static str],
+    /// Magic bytes identifying the format, any of them matches.
+    pub signatures: &'static [&'static [u8]],
+    /// Offset of the signature from the start of the file.
+    pub signature_offset: usize,
+    /// How the format is unpacked.
+    pub kind: FormatKind,
+}
+
+/// All supported formats, in the order they are tried.
+pub static FORMATS: &[ArchiveFormat] = &[
+    ArchiveFormat {
+        name: "zip",
+        extensions: &["zip"],
+        signatures: &[b"PK\x03\x04", b"PK\x05\x06", b"PK\x07\x08"],
+        signature_offset: 0,
+        kind: FormatKind::FileArchive(unpack_zip),
+    },
+    ArchiveFormat {
+        name: "tar",
+        extensions: &["tar"],
+        signatures: &[b"ustar"],
+        signature_offset: 257,
+        kind: FormatKind::StreamArchive(unpack_tar),
+    },
+    ArchiveFormat {
+        name: "gzip",
+        extensions: &["gz", "tgz"],
+        signatures: &[b"\x1f\x8b"],
+        signature_offset: 0,
+        kind: FormatKind::Compression(open_gzip),
+    },
+    ArchiveFormat {
+        name: "xz",
+        extensions: &["xz", "txz"],
+        signatures: &[b"\xfd7zXZ\x00"],
+        signature_offset: 0,
+        kind: FormatKind::Compression(open_xz),
+    },
+    ArchiveFormat {
+        name: "bzip2",
+        extensions: &["bz2", "tbz2", "tbz"],
+        signatures: &[b"BZh"],
+        signature_offset: 0,
+        kind: FormatKind::Compression(open_bzip2),
+    },
+    ArchiveFormat {
+        name: "zstd",
+        extensions: &["zst", "tzst"],
+        signatures: &[b"\x28\xb5\x2f\xfd"],
+        signature_offset: 0,
+        kind: FormatKind::Compression(open_zstd),
+    },
+    ArchiveFormat {
+        name: "lzma",
+        extensions: &["lzma", "tlz"],
+        signatures: &[b"\x5d\x00\x00"],
+        signature_offset: 0,
+        kind: FormatKind::Compression(open_lzma),
+    },
+];
+
+/// Returns the format whose signature matches `header`, the first bytes of a file.
+pub fn detect(header: &[u8]) -> Option<&'static ArchiveFormat> {
+    FORMATS.iter().find(|format| {
+        format.signatures.iter().any(|signature| {
+            header.get(format.signature_offset..format.signature_offset + signature.len())
+                == Some(&signature[..])
+        })
+    })
+}
+
+/// Returns the format registered for the extension of `filename`.
+pub fn from_extension(filename: &str) -> Option<&'static ArchiveFormat> {
+    let extension = filename.rsplit_once('.')?.1.to_ascii_lowercase();
+    FORMATS
+        .iter()
+        .find(|format| format.extensions.contains(&extension.as_str()))
+}
+
+/// Unpacks the archive at `archive_path` into `destination`.
+pub fn unpack_file(archive_path: &Path, destination: &Path) -> Result<(), DecompressionError> {
+    let mut file = File::open(archive_path)?;
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    (&mut file).take(HEADER_LEN as u64).read_to_end(&mut header)?;
+    let name = file_name(archive_path);
+    let format = detect(&header)
+        .or_else(|| from_extension(&name))
+        .ok_or(DecompressionError::UnsupportedFormat)?;
+    log::info!(
+        "Decompressing {} ({}) to {}",
+        archive_path.display(),
+        format.name,
+        destination.display()
+    );
+
+    match format.kind {
+        // Archives needing random access are read from the file directly
+        FormatKind::FileArchive(unpack) => unpack(archive_path, destination),
+        _ => {
+            let reader: BoxedReader = Box::new(Cursor::new(header).chain(file));
+            unpack_with(format, reader, &name, destination)
+        }
+    }
+}
+
+/// Unpacks the archive read from `reader` into `destination`. `name` is the file name of the
+/// archive; it is used when no signature matches.
+pub fn unpack_reader(
+    reader: BoxedReader,
+    name: &str,
+    destination: &Path,
+) -> Result<(), DecompressionError> {
+    unpack_stream(reader, name, destination, false)
+}
+
+fn unpack_stream(
+    reader: BoxedReader,
+    name: &str,
+    destination: &Path,
+    decoded: bool,
+) -> Result<(), DecompressionError> {
+    let (header, mut reader) = peek(reader)?;
+    match detect(&header).or_else(|| from_extension(name)) {
+        Some(format) => unpack_with(format, reader, name, destination),
+        // A compressed single file, e.g. `foo.gz`, is written out as `foo`
+        None if decoded => {
+            let mut guard = Guard::new(MAX_EXTRACTED_SIZE);
+            write_decoded_file(&mut reader, &destination.join(name), &mut guard)
+        }
+        None => Err(DecompressionError::UnsupportedFormat),
+    }
+}
+
+/// Writes a decompressed single file to `path`, up to the size left in `guard`, which is charged
+/// with the bytes actually written.
+fn write_decoded_file(
+    reader: &mut impl Read,
+    path: &Path,
+    guard: &mut Guard,
+) -> Result<(), DecompressionError> {
+    let mut output = File::create(path)?;
+    // One byte past the budget is enough to tell that the limit is exceeded.
+    let limit = guard.remaining().saturating_add(1);
+    let written = io::copy(&mut reader.take(limit), &mut output)?;
+    guard.add_size(written)
+}
+
+fn unpack_with(
+    format: &ArchiveFormat,
+    mut reader: BoxedReader,
+    name: &str,
+    destination: &Path,
+) -> Result<(), DecompressionError> {
+    match format.kind {
+        FormatKind::Compression(open) => {
+            let inner_name = decoded_name(name, format);
+            let result = unpack_stream(open(reader)?, &inner_name, destination, true);
+            // Kept from the former tar.bz2 decoder: MSYS archives contain special files such as
+            // dev/fd which can not be created on Windows
+            match result {
+                Err(DecompressionError::Io(e))
+                    if format.name == "bzip2" && std::env::consts::OS == "windows" =>
+                {
+                    warn!("tar unpack had issues on Windows (likely special files): {}. Continuing anyway.", e);
+                    Ok(())
+                }
+                result => result,
+            }
+        }
+        FormatKind::StreamArchive(unpack) => unpack(reader, destination),
+        FormatKind::FileArchive(unpack) => {
+            // e.g. a compressed zip, spool it to disk first
+            let mut spooled = tempfile::NamedTempFile::new()?;
+            io::copy(&mut reader, spooled.as_file_mut())?;
+            unpack(spooled.path(), destination)
+        }
+    }
+}
+
+/// Reads the first bytes of `reader` and returns them together with a reader yielding the whole
+/// stream again.
+fn peek(mut reader: BoxedReader) -> io::Result<(Vec<u8>, BoxedReader)> {
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    (&mut reader).take(HEADER_LEN as u64).read_to_end(&mut header)?;
+    let rest: BoxedReader = Box::new(Cursor::new(header.clone()).chain(reader));
+    Ok((header, rest))
+}
+
+/// Name of the content of the compressed file `name`: `foo.tar.gz` gives `foo.tar` and
+/// `foo.tgz` gives `foo.tar`.
+fn decoded_name(name: &str, format: &ArchiveFormat) -> String {
+    let lower = name.to_ascii_lowercase();
+    for extension in format.extensions {
+        if let Some(stem) = lower
+            .strip_suffix(extension)
+            .and_then(|stem| stem.strip_suffix('.'))
+        {
+            let stem = &name[..stem.len()];
+            return if extension.starts_with('t') {
+                format!("{}.tar", stem)
+            } else {
+                stem.to_string()
+            };
+        }
+    }
+    name.to_string()
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+fn open_gzip(reader: BoxedReader) -> io::Result<BoxedReader> {
+    Ok(Box::new(GzDecoder::new(reader)))
+}
+
+fn open_bzip2(reader: BoxedReader) -> io::Result<BoxedReader> {
+    Ok(Box::new(BzDecoder::new(reader)))
+}
+
+fn open_zstd(reader: BoxedReader) -> io::Result<BoxedReader> {
+    Ok(Box::new(zstd::stream::read::Decoder::new(reader)?))
+}
+
+fn open_xz(reader: BoxedReader) -> io::Result<BoxedReader> {
+    Ok(decode_in_thread(reader, |input, output| {
+        lzma_rs::xz_decompress(input, output)
+    }))
+}
+
+fn open_lzma(reader: BoxedReader) -> io::Result<BoxedReader> {
+    Ok(decode_in_thread(reader, |input, output| {
+        lzma_rs::lzma_decompress(input, output)
+    }))
+}
+
+/// Runs a decoder which can only write its output (lzma-rs) on its own thread and returns a
+/// reader for the decoded stream.
+fn decode_in_thread<E: std::fmt::Display>(
+    reader: BoxedReader,
+    decode: impl FnOnce(&mut BufReader<BoxedReader>, &mut BufWriter<ChannelWriter>) -> Result<(), E>
+        + Send
+        + 'static,
+) -> BoxedReader {
+    let (tx, rx) = sync_channel(DECODED_CHUNKS);
+    let errors = tx.clone();
+    std::thread::spawn(move || {
+        let mut input = BufReader::new(reader);
+        let mut output = BufWriter::with_capacity(64 * 1024, ChannelWriter(tx));
+        let result = decode(&mut input, &mut output)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+            .and_then(|_| output.flush());
+        // Fails only if the reader is gone, and then nobody is interested in the error
+        if let Err(e) = result {
+            let _ = errors.send(Err(e));
+        }
+    });
+    Box::new(ChannelReader {
+        rx,
+        chunk: Vec::new(),
+        pos: 0,
+    })
+}
+
+struct ChannelWriter(SyncSender<io::Result<Vec<u8>>>);
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0
+            .send(Ok(buf.to_vec()))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "decoded stream closed"))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+struct ChannelReader {
+    rx: Receiver<io::Result<Vec<u8>>>,
+    chunk: Vec<u8>,
+    pos: usize,
+}
+
+impl Read for ChannelReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos >= self.chunk.len() {
+            match self.rx.recv() {
+                Ok(chunk) => {
+                    self.chunk = chunk?;
+                    self.pos = 0;
+                }
+                // The decoder finished
+                Err(_) => return Ok(0),
+            }
+        }
+        let read = buf.len().min(self.chunk.len() - self.pos);
+        buf[..read].copy_from_slice(&self.chunk[self.pos..self.pos + read]);
+        self.pos += read;
+        Ok(read)
+    }
+}
+
+fn unpack_tar(reader: BoxedReader, destination: &Path) -> Result<(), DecompressionError> {
+    crate::safe_extract::unpack_tar(reader, destination)
+}
+
+/// Extracts a zip archive with `ZipArchive`. The sizes recorded in the archive are not
+/// trusted: every entry is copied only up to the size left in `guard`, which is charged with
+/// the bytes actually written.
+fn extract_zip(
+    archive_path: &Path,
+    destination_path: &Path,
+    guard: &mut Guard,
+) -> Result<(), DecompressionError> {
+    let file = File::open(archive_path)?;
+    let mut archive = ZipArchive::new(file)?;
+
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        let outpath = match guard.check_path(Path::new(file.name())) {
+            Some(path) => destination_path.join(path),
+            None => continue,
+        };
+
+        if file.name().ends_with('/') {
+            std::fs::create_dir_all(&outpath)?;
+        } else {
+            if let Some(p) = outpath.parent() {
+                if !p.exists() {
+                    std::fs::create_dir_all(p)?;
+                }
+            }
+            let mut outfile = File::create(&outpath)?;
+            // One byte past the budget is enough to tell that the limit is exceeded.
+            let limit = guard.remaining().saturating_add(1);
+            let written = io::copy(&mut (&mut file).take(limit), &mut outfile)?;
+            guard.add_size(written)?;
+        }
+    }
+    Ok(())
+}
+
+fn unpack_zip(archive_path: &Path, destination_path: &Path) -> Result<(), DecompressionError> {
+    if !Path::new(archive_path).exists() {
+        log::error!("File does not exist.");
+        return Err(DecompressionError::Io(io::Error::new(
+            io::ErrorKind::NotFound,
+            "Archive file not found",
+        )));
+    }
+
+    // First, try using ZipArchive for all platforms
+    let mut guard = Guard::new(MAX_EXTRACTED_SIZE);
+    let zip_result = extract_zip(archive_path, destination_path, &mut guard);
+    let zip_result = zip_result.and_then(|_| guard.finish());
+
+    // If ZipArchive failed to read the archive and we're on Windows, fall back to PowerShell.
+    // Archives rejected by the extraction rules are not handed to it.
+    if let Err(err) = zip_result {
+        let unreadable = matches!(err, DecompressionError::Io(_) | DecompressionError::Zip(_));
+        if std::env::consts::OS == "windows" && unreadable {
+            log::warn!(
+                "ZipArchive decompression failed: {}. Falling back to PowerShell approach.",
+                err
+            );
+
+            let executor = crate::command_executor::get_executor();
+            let archive_path_str = archive_path.to_string_lossy().to_string();
+            let destination_path_str = destination_path.to_string_lossy().to_string();
+
+            // Create a separate thread to run the PowerShell command
+            let handle = std::thread::spawn(move || {
+                let script = format!(
+                    "Expand-Archive -Path '{}' -DestinationPath '{}' -Force",
+                    archive_path_str, destination_path_str
+                );
+
+                executor.run_script_from_string(&script)
+            });
+
+            // Wait for the thread to complete
+            match handle.join() {
+                Ok(result) => match result {
+                    Ok(output) => {
+                        if !output.status.success() {
+                            let error_message = String::from_utf8_lossy(&output.stderr);
+                            log::error!("PowerShell decompression failed: {}", error_message);
+                            return Err(DecompressionError::Io(io::Error::new(
+                                io::ErrorKind::Other,
+                                format!("PowerShell decompression failed: {}", error_message),
+                            )));
+                        }
+                        Ok(())
+                    }
+                    Err(e) => {
+                        log::error!("Failed to execute PowerShell command: {}", e);
+                        Err(DecompressionError::Io(e))
+                    }
+                },
+                Err(e) => {
+                    log::error!("Thread panicked: {:?}", e);
+                    Err(DecompressionError::Io(io::Error::new(
+                        io::ErrorKind::Other,
+                        "Thread panicked during decompression",
+                    )))
+                }
+            }
+        } else {
+            // On non-Windows platforms, or for rejected archives, return the original error
+            Err(err)
+        }
+    } else {
+        // ZipArchive succeeded
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::tar_with;
+
+    #[test]
+    fn test_detects_formats_from_magic_bytes() {
+        let tar = tar_with("bin/tool", b"tool");
+        assert_eq!(detect(&tar).unwrap().name, "tar");
+        assert_eq!(detect(b"PK\x03\x04rest").unwrap().name, "zip");
+        assert_eq!(detect(b"\x1f\x8b\x08").unwrap().name, "gzip");
+        assert_eq!(detect(b"\xfd7zXZ\x00\x00").unwrap().name, "xz");
+        assert_eq!(detect(b"BZh91AY").unwrap().name, "bzip2");
+        assert_eq!(detect(b"\x28\xb5\x2f\xfd\x00").unwrap().name, "zstd");
+        assert!(detect(b"plain text").is_none());
+        assert_eq!(from_extension("tool.TGZ").unwrap().name, "gzip");
+    }
+
+    #[test]
+    fn test_misnamed_compressed_tar_is_unpacked() {
+        let dir = tempfile::TempDir::new().unwrap();
+        // A zstd compressed tar saved under a name that tells nothing about its format
+        let archive_path = dir.path().join("tool.download");
+        let compressed = zstd::encode_all(&tar_with("bin/tool", b"tool")[..], 0).unwrap();
+        File::create(&archive_path).unwrap().write_all(&compressed).unwrap();
+
+        let destination = dir.path().join("out");
+        std::fs::create_dir_all(&destination).unwrap();
+        unpack_file(&archive_path, &destination).unwrap();
+        assert_eq!(std::fs::read_to_string(destination.join("bin/tool")).unwrap(), "tool");
+
+        assert_eq!(decoded_name("xtensa.tar.gz", &FORMATS[2]), "xtensa.tar");
+        assert_eq!(decoded_name("xtensa.tgz", &FORMATS[2]), "xtensa.tar");
+    }
+
+    #[test]
+    fn test_zip_with_understated_sizes_is_stopped_at_the_limit() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = zip::write::FileOptions::<()>::default()
+            .compression_method(zip::CompressionMethod::Deflated);
+        zip.start_file("bomb.bin", options).unwrap();
+        zip.write_all(&vec![0u8; 100_000]).unwrap();
+        let mut bytes = zip.finish().unwrap().into_inner();
+
+        // Make the local header and the central directory claim the entry is 10 bytes large.
+        let lie = 10u32.to_le_bytes();
+        bytes[22..26].copy_from_slice(&lie);
+        let central = bytes
+            .windows(4)
+            .position(|w| w == b"PK\x01\x02")
+            .unwrap();
+        bytes[central + 24..central + 28].copy_from_slice(&lie);
+        let archive_path = dir.path().join("bomb.zip");
+        std::fs::write(&archive_path, &bytes).unwrap();
+
+        let destination = dir.path().join("out");
+        let mut guard = Guard::new(1000);
+        let result = extract_zip(&archive_path, &destination, &mut guard);
+
+        assert!(matches!(result, Err(DecompressionError::SizeLimitExceeded(1000))));
+        assert!(std::fs::metadata(destination.join("bomb.bin")).unwrap().len() <= 1001);
+    }
+
+    #[test]
+    fn test_compressed_single_file_is_stopped_at_the_limit() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let compressed = zstd::encode_all(&vec![0u8; 100_000][..], 0).unwrap();
+        let mut decoded = open_zstd(Box::new(Cursor::new(compressed))).unwrap();
+
+        let output = dir.path().join("bomb.bin");
+        let mut guard = Guard::new(1000);
+        let result = write_decoded_file(&mut decoded, &output, &mut guard);
+
+        assert!(matches!(result, Err(DecompressionError::SizeLimitExceeded(1000))));
+        assert!(std::fs::metadata(&output).unwrap().len() <= 1001);
+    }
+}
@@ -28,6 +28,20 @@ pub trait CommandExecutor: Send {
         args: &[&str],
         dir: &str,
     ) -> std::io::Result<Child>;
+    fn execute_with_dir_and_env(
+        &self,
+        command: &str,
+        args: &[&str],
+        dir: &str,
+        env: Vec<(&str, &str)>,
+    ) -> std::io::Result<Output>;
+    fn spawn_with_dir_and_env(
+        &self,
+        command: &str,
+        args: &[&str],
+        dir: &str,
+        env: Vec<(&str, &str)>,
+    ) -> std::io::Result<Child>;
     fn run_script_from_string(&self, script: &str) -> std::io::Result<Output>;
     fn run_script_from_string_streaming(&self, script: &str) -> std::io::Result<std::process::ExitStatus>;
     fn run_script_from_string_streaming_headless(&self, script: &str) -> std::io::Result<std::process::ExitStatus>;
@@ -78,6 +92,33 @@ impl CommandExecutor for DefaultExecutor {
             .stderr(std::process::Stdio::piped())
             .spawn()
     }
+    fn execute_with_dir_and_env(
+        &self,
+        command: &str,
+        args: &[&str],
+        dir: &str,
+        env: Vec<(&str, &str)>,
+    ) -> std::io::Result<Output> {
+        Command::new(command)
+            .args(args)
+            .current_dir(dir)
+            .envs(env)
+            .output()
+    }
+    fn spawn_with_dir_and_env(
+        &self,
+        command: &str,
+        args: &[&str],
+        dir: &str,
+        env: Vec<(&str, &str)>,
+    ) -> std::io::Result<Child> {
+        Command::new(command)
+            .args(args)
+            .current_dir(dir)
+            .envs(env)
+            .stderr(std::process::Stdio::piped())
+            .spawn()
+    }
     fn run_script_from_string(&self, script: &str) -> std::io::Result<Output> {
         Command::new("bash")
             .args(["-c", script])
@@ -304,6 +345,37 @@ impl CommandExecutor for WindowsExecutor {
             .spawn()
     }
 
+    fn execute_with_dir_and_env(
+        &self,
+        command: &str,
+        args: &[&str],
+        dir: &str,
+        env: Vec<(&str, &str)>,
+    ) -> std::io::Result<Output> {
+        Command::new(command)
+            .args(args)
+            .current_dir(dir)
+            .envs(env)
+            .creation_flags(CREATE_NO_WINDOW)
+            .output()
+    }
+
+    fn spawn_with_dir_and_env(
+        &self,
+        command: &str,
+        args: &[&str],
+        dir: &str,
+        env: Vec<(&str, &str)>,
+    ) -> std::io::Result<Child> {
+        Command::new(command)
+            .args(args)
+            .current_dir(dir)
+            .envs(env)
+            .creation_flags(CREATE_NO_WINDOW)
+            .stderr(std::process::Stdio::piped())
+            .spawn()
+    }
+
     fn run_script_from_string(&self, script: &str) -> std::io::Result<Output> {
 
       let (script_path, mut cmd) = self.prepare_powershell_script(script)?;
@@ -436,6 +508,26 @@ pub fn spawn_with_dir(
     executor.spawn_with_dir(command, args, dir)
 }
 
+pub fn execute_command_with_dir_and_env(
+    command: &str,
+    args: &[&str],
+    dir: &str,
+    env: Vec<(&str, &str)>,
+) -> std::io::Result<Output> {
+    let executor = get_executor();
+    executor.execute_with_dir_and_env(command, args, dir, env)
+}
+
+pub fn spawn_with_dir_and_env(
+    command: &str,
+    args: &[&str],
+    dir: &str,
+    env: Vec<(&str, &str)>,
+) -> std::io::Result<Child> {
+    let executor = get_executor();
+    executor.spawn_with_dir_and_env(command, args, dir, env)
+}
+
 pub fn run_script_from_string_with_dir(
     script: &str,
     dir: &str,
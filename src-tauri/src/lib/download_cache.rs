@@ -0,0 +1,386 @@
+//! Content-addressed cache of downloaded tool archives.
+//!
+//! Archives are stored by the sha256 published in tools.json, so a toolchain shared by several
+//! ESP-IDF versions, or served from different mirrors, is downloaded and verified only once.
+//! The layout is `<root>/<first two hex digits>/<sha256>/<original filename>`; the original
+//! filename is kept because extraction picks the decoder from the file extension.
+//!
+//! The modification time of a cached archive records when it was last used, which is what
+//! [`DownloadCache::prune`] relies on to evict the least recently used entries first.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use filetime::{set_file_mtime, FileTime};
+use log::{debug, info, warn};
+use serde::Serialize;
+
+use crate::verify_file_checksum;
+
+/// Default size limit of the cache in megabytes.
+pub const DEFAULT_DOWNLOAD_CACHE_MAX_SIZE_MB: u64 = 10 * 1024;
+
+/// Returns the default location of the download cache, next to the default tools directory.
+pub fn default_download_cache_dir() -> PathBuf {
+    match std::env::consts::OS {
+        "windows" => PathBuf::from("C:\\Espressif\\cache"),
+        _ => dirs::home_dir()
+            .unwrap_or_default()
+            .join(".espressif")
+            .join("cache"),
+    }
+}
+
+/// A single archive stored in the cache.
+#[derive(Debug, Clone, Serialize)]
+pub struct CacheEntry {
+    pub sha256: String,
+    pub filename: String,
+    pub path: PathBuf,
+    pub size: u64,
+    pub last_used: SystemTime,
+}
+
+/// Size report of the cache.
+#[derive(Debug, Clone, Serialize)]
+pub struct CacheReport {
+    pub root: PathBuf,
+    pub entries: Vec<CacheEntry>,
+    pub total_size: u64,
+}
+
+/// Result of pruning the cache.
+#[derive(Debug, Clone, Serialize)]
+pub struct PruneReport {
+    pub removed: Vec<CacheEntry>,
+    pub freed_bytes: u64,
+    pub remaining_bytes: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct DownloadCache {
+    root: PathBuf,
+    max_size: Option<u64>,
+}
+
+impl DownloadCache {
+    /// Creates a cache rooted at `root`. `max_size` (in bytes) is the limit enforced by
+    /// [`DownloadCache::enforce_limit`]; `None` means the cache is never pruned automatically.
+    pub fn new(root: impl Into<PathBuf>, max_size: Option<u64>) -> Self {
+        Self {
+            root: root.into(),
+            max_size,
+        }
+    }
+
+    /// Builds the cache configured in `settings`, or `None` when no cache directory is set.
+    pub fn from_settings(settings: &crate::settings::Settings) -> Option<Self> {
+        let root = settings.download_cache_dir.clone()?;
+        if root.as_os_str().is_empty() {
+            return None;
+        }
+        let max_size = settings
+            .download_cache_max_size_mb
+            .map(|mb| mb.saturating_mul(1024 * 1024));
+        Some(Self::new(root, max_size))
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Returns the directory of the entry for `sha256`, or `None` when it is not a sha256 hex
+    /// digest. The checksum comes from tools.json or a user overlay and must never name a path.
+    fn entry_dir(&self, sha256: &str) -> Option<PathBuf> {
+        if sha256.len() != 64 || !sha256.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+        let sha256 = sha256.to_ascii_lowercase();
+        Some(self.root.join(&sha256[..2]).join(&sha256))
+    }
+
+    /// Returns the cached archive with the given checksum and marks it as recently used.
+    ///
+    /// The archive is verified first; one that no longer matches its checksum, e.g. because it
+    /// was corrupted on disk, is removed and treated as a miss.
+    pub fn lookup(&self, sha256: &str) -> Option<PathBuf> {
+        let entry_dir = self.entry_dir(sha256)?;
+        let sha256 = &sha256.to_ascii_lowercase();
+        let path = fs::read_dir(&entry_dir)
+            .ok()?
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .find(|p| p.is_file() && p.extension().map_or(true, |ext| ext != "tmp"))?;
+        let intact = path
+            .to_str()
+            .is_some_and(|p| verify_file_checksum(sha256, p).unwrap_or(false));
+        if !intact {
+            warn!("Cached {} does not match checksum {}, removing it", path.display(), sha256);
+            if let Err(e) = fs::remove_file(&path) {
+                warn!("Failed to remove {}: {}", path.display(), e);
+            }
+            return None;
+        }
+        self.touch(&path);
+        debug!("Download cache hit for {}: {}", sha256, path.display());
+        Some(path)
+    }
+
+    /// Verifies `file` against `sha256` and moves it into the cache.
+    ///
+    /// Returns the path of the cached copy. A file that does not match its checksum is
+    /// rejected and left where it was.
+    pub fn insert(&self, sha256: &str, file: &Path) -> io::Result<PathBuf> {
+        let entry_dir = self.entry_dir(sha256).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is not a sha256 checksum", sha256),
+            )
+        })?;
+        let sha256 = &sha256.to_ascii_lowercase();
+        let file_str = file.to_str().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "Invalid UTF-8 in file path")
+        })?;
+        if !verify_file_checksum(sha256, file_str)? {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} does not match checksum {}", file.display(), sha256),
+            ));
+        }
+        let filename = file.file_name().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "Path has no file name")
+        })?;
+
+        fs::create_dir_all(&entry_dir)?;
+        let target = entry_dir.join(filename);
+        if target.exists() {
+            // Another installation cached it in the meantime
+            fs::remove_file(file)?;
+            self.touch(&target);
+            return Ok(target);
+        }
+
+        if fs::rename(file, &target).is_err() {
+            // Different file systems, copy through a temporary file so readers never see a partial archive
+            let mut tmp_name = filename.to_os_string();
+            tmp_name.push(".tmp");
+            let tmp = entry_dir.join(tmp_name);
+            fs::copy(file, &tmp)?;
+            fs::rename(&tmp, &target)?;
+            fs::remove_file(file)?;
+        }
+        self.touch(&target);
+        info!("Stored {} in the download cache", target.display());
+        Ok(target)
+    }
+
+    fn touch(&self, path: &Path) {
+        if let Err(e) = set_file_mtime(path, FileTime::now()) {
+            debug!("Failed to update last use of {}: {}", path.display(), e);
+        }
+    }
+
+    /// Lists all cached archives, most recently used first.
+    pub fn report(&self) -> io::Result<CacheReport> {
+        let mut entries = Vec::new();
+        if self.root.exists() {
+            for prefix in fs::read_dir(&self.root)? {
+                let prefix = prefix?.path();
+                if !prefix.is_dir() {
+                    continue;
+                }
+                for entry_dir in fs::read_dir(&prefix)? {
+                    let entry_dir = entry_dir?.path();
+                    if !entry_dir.is_dir() {
+                        continue;
+                    }
+                    let sha256 = entry_dir
+                        .file_name()
+                        .and_then(|n| n.to_str())
+                        .unwrap_or_default()
+                        .to_string();
+                    for file in fs::read_dir(&entry_dir)? {
+                        let path = file?.path();
+                        let metadata = fs::metadata(&path)?;
+                        if !metadata.is_file() {
+                            continue;
+                        }
+                        entries.push(CacheEntry {
+                            sha256: sha256.clone(),
+                            filename: path
+                                .file_name()
+                                .and_then(|n| n.to_str())
+                                .unwrap_or_default()
+                                .to_string(),
+                            size: metadata.len(),
+                            last_used: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+                            path,
+                        });
+                    }
+                }
+            }
+        }
+        entries.sort_by(|a, b| b.last_used.cmp(&a.last_used));
+        let total_size = entries.iter().map(|e| e.size).sum();
+        Ok(CacheReport {
+            root: self.root.clone(),
+            entries,
+            total_size,
+        })
+    }
+
+    /// Removes least recently used archives until the cache holds at most `max_size` bytes.
+    pub fn prune(&self, max_size: u64) -> io::Result<PruneReport> {
+        let report = self.report()?;
+        let mut remaining_bytes = report.total_size;
+        let mut removed = Vec::new();
+
+        // report() is sorted most recently used first, so evict from the back
+        for entry in report.entries.into_iter().rev() {
+            if remaining_bytes <= max_size {
+                break;
+            }
+            match fs::remove_file(&entry.path) {
+                Ok(_) => {
+                    remaining_bytes -= entry.size;
+                    if let Some(dir) = entry.path.parent() {
+                        let _ = fs::remove_dir(dir);
+                        if let Some(prefix) = dir.parent() {
+                            let _ = fs::remove_dir(prefix);
+                        }
+                    }
+                    debug!("Pruned {} from the download cache", entry.path.display());
+                    removed.push(entry);
+                }
+                Err(e) => warn!("Failed to prune {}: {}", entry.path.display(), e),
+            }
+        }
+
+        let freed_bytes = removed.iter().map(|e| e.size).sum();
+        Ok(PruneReport {
+            removed,
+            freed_bytes,
+            remaining_bytes,
+        })
+    }
+
+    /// Prunes the cache down to its configured size limit, if there is one.
+    pub fn enforce_limit(&self) {
+        let Some(max_size) = self.max_size else {
+            return;
+        };
+        match self.prune(max_size) {
+            Ok(report) if !report.removed.is_empty() => info!(
+                "Pruned {} archives ({} bytes) from the download cache",
+                report.removed.len(),
+                report.freed_bytes
+            ),
+            Ok(_) => {}
+            Err(e) => warn!("Failed to prune the download cache: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sha2::{Digest, Sha256};
+    use tempfile::TempDir;
+
+    fn write_file(dir: &Path, name: &str, content: &[u8]) -> (PathBuf, String) {
+        let path = dir.join(name);
+        fs::write(&path, content).unwrap();
+        (path, format!("{:x}", Sha256::digest(content)))
+    }
+
+    #[test]
+    fn test_insert_and_lookup() {
+        let cache_dir = TempDir::new().unwrap();
+        let download_dir = TempDir::new().unwrap();
+        let cache = DownloadCache::new(cache_dir.path(), None);
+        let (file, sha) = write_file(download_dir.path(), "tool.tar.gz", b"archive");
+
+        assert!(cache.lookup(&sha).is_none());
+        let cached = cache.insert(&sha, &file).unwrap();
+
+        assert!(!file.exists());
+        assert_eq!(cached.file_name().unwrap(), "tool.tar.gz");
+        assert_eq!(cache.lookup(&sha), Some(cached.clone()));
+        assert_eq!(fs::read(cached).unwrap(), b"archive");
+    }
+
+    #[test]
+    fn test_insert_rejects_checksum_mismatch() {
+        let cache_dir = TempDir::new().unwrap();
+        let download_dir = TempDir::new().unwrap();
+        let cache = DownloadCache::new(cache_dir.path(), None);
+        let (file, _) = write_file(download_dir.path(), "tool.zip", b"archive");
+        let wrong_sha = format!("{:x}", Sha256::digest(b"something else"));
+
+        assert!(cache.insert(&wrong_sha, &file).is_err());
+        assert!(file.exists());
+        assert!(cache.lookup(&wrong_sha).is_none());
+    }
+
+    #[test]
+    fn test_checksums_that_are_not_hex_digests_are_misses() {
+        let cache_dir = TempDir::new().unwrap();
+        let download_dir = TempDir::new().unwrap();
+        let cache = DownloadCache::new(cache_dir.path().join("cache"), None);
+        let (file, sha) = write_file(download_dir.path(), "tool.zip", b"archive");
+
+        let escaping = format!("../../{}", &sha[6..]);
+        assert!(cache.lookup(&escaping).is_none());
+        assert!(cache.lookup(&sha[..63]).is_none());
+        assert!(cache.insert(&escaping, &file).is_err());
+        assert!(file.exists());
+        assert_eq!(fs::read_dir(cache_dir.path()).unwrap().count(), 0);
+
+        // Upper case digests name the same entry as lower case ones
+        let cached = cache.insert(&sha.to_uppercase(), &file).unwrap();
+        assert_eq!(cache.lookup(&sha), Some(cached));
+    }
+
+    #[test]
+    fn test_lookup_drops_corrupted_archive() {
+        let cache_dir = TempDir::new().unwrap();
+        let download_dir = TempDir::new().unwrap();
+        let cache = DownloadCache::new(cache_dir.path(), None);
+        let (file, sha) = write_file(download_dir.path(), "tool.tar.xz", b"archive");
+        let cached = cache.insert(&sha, &file).unwrap();
+
+        fs::write(&cached, b"corrupted").unwrap();
+        assert!(cache.lookup(&sha).is_none());
+        assert!(!cached.exists());
+    }
+
+    #[test]
+    fn test_prune_removes_least_recently_used_first() {
+        let cache_dir = TempDir::new().unwrap();
+        let download_dir = TempDir::new().unwrap();
+        let cache = DownloadCache::new(cache_dir.path(), None);
+
+        let mut cached = Vec::new();
+        for (i, name) in ["old.zip", "middle.zip", "new.zip"].iter().enumerate() {
+            let (file, sha) = write_file(download_dir.path(), name, &vec![i as u8; 100]);
+            let path = cache.insert(&sha, &file).unwrap();
+            set_file_mtime(&path, FileTime::from_unix_time(1_000_000 + i as i64 * 1000, 0)).unwrap();
+            cached.push(path);
+        }
+
+        let report = cache.report().unwrap();
+        assert_eq!(report.total_size, 300);
+        assert_eq!(report.entries[0].filename, "new.zip");
+
+        let pruned = cache.prune(200).unwrap();
+        assert_eq!(pruned.removed.len(), 1);
+        assert_eq!(pruned.removed[0].filename, "old.zip");
+        assert_eq!(pruned.freed_bytes, 100);
+        assert_eq!(pruned.remaining_bytes, 200);
+        assert!(!cached[0].exists());
+        assert!(cached[1].exists());
+        assert!(cached[2].exists());
+    }
+}
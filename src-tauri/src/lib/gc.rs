@@ -0,0 +1,569 @@
+//! Garbage collection of tool versions, archives and Python environments.
+//!
+//! `remove_single_idf_version` only deletes what the removed installation knows about, and failed
+//! installations leave their files behind. This module compares the content of the tools,
+//! download and Python environment directories with every installation recorded in
+//! `eim_idf.json` and the `tools.json` each of them references. Everything no installation
+//! uses is reported as orphaned.
+//!
+//! Directories shared with an installation whose `tools.json` can not be read are never
+//! scanned, since there is no way to tell what that installation still needs. Neither are the
+//! Git and Python eim installs into the tools directory as prerequisites on Windows.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use log::{debug, warn};
+use serde::Serialize;
+
+use crate::idf_config::{IdfConfig, IdfInstallation};
+use crate::idf_tools::{read_and_parse_tools_file, ToolsFile};
+use crate::settings::Settings;
+use crate::system_dependencies::is_prerequisite_install_dir;
+use crate::tool_store::ToolStore;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrphanKind {
+    /// `<tools>/<tool name>/<version>`
+    ToolVersion,
+    /// A file in the tool download directory
+    Archive,
+    /// `<tools>/<python env folder>/<version>`
+    PythonEnv,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Orphan {
+    pub kind: OrphanKind,
+    pub path: PathBuf,
+    pub size: u64,
+    /// Tool stores of the installations sharing the tools directory, one of them may hold the
+    /// reference of a linked tool version
+    #[serde(skip)]
+    tool_stores: Vec<ToolStore>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct GcReport {
+    pub orphans: Vec<Orphan>,
+    pub total_size: u64,
+    /// Directories left out because an installation using them has no readable tools.json
+    pub skipped: Vec<PathBuf>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct GcResult {
+    pub removed: Vec<Orphan>,
+    pub freed_bytes: u64,
+    pub failed: Vec<(PathBuf, String)>,
+}
+
+/// Locations an installation uses, as recorded in eim_idf.json and its installation settings.
+struct InstallationUsage {
+    tools_dir: PathBuf,
+    download_dir: PathBuf,
+    python_env_dir: PathBuf,
+    python: PathBuf,
+    tools_file: Option<ToolsFile>,
+    tool_store: Option<ToolStore>,
+}
+
+impl InstallationUsage {
+    fn new(installation: &IdfInstallation) -> Self {
+        let settings = installation
+            .installation_config
+            .as_ref()
+            .and_then(|bytes| Settings::from_installation_config(bytes.as_slice()).ok())
+            .unwrap_or_default();
+        let defaults = Settings::default();
+        let tool_store = ToolStore::from_settings(&settings);
+
+        let idf_path = PathBuf::from(&installation.path);
+        let tools_dir = PathBuf::from(&installation.idf_tools_path);
+        // Download folder names are relative to the version folder unless they are absolute
+        let version_dir = idf_path.parent().map(Path::to_path_buf).unwrap_or_default();
+        let download_dir = version_dir.join(
+            settings
+                .tool_download_folder_name
+                .or(defaults.tool_download_folder_name)
+                .unwrap_or_default(),
+        );
+        let python_env_dir = tools_dir.join(
+            settings
+                .python_env_folder_name
+                .or(defaults.python_env_folder_name)
+                .unwrap_or_default(),
+        );
+        let tools_json = idf_path.join(
+            settings
+                .tools_json_file
+                .or(defaults.tools_json_file)
+                .unwrap_or_default(),
+        );
+        let tools_file = match read_and_parse_tools_file(&tools_json.to_string_lossy(), settings.tools_overlay.as_deref()) {
+            Ok(tools_file) => Some(tools_file),
+            Err(e) => {
+                warn!(
+                    "Failed to read {} of installation {}: {}",
+                    tools_json.display(),
+                    installation.name,
+                    e
+                );
+                None
+            }
+        };
+
+        Self {
+            tools_dir,
+            download_dir,
+            python_env_dir,
+            python: PathBuf::from(&installation.python),
+            tools_file,
+            tool_store,
+        }
+    }
+}
+
+/// Finds tool versions, archives and Python environments not used by any installation listed in
+/// the eim_idf.json at `config_path` (the default location when `None`).
+///
+/// The default tools and download directories are scanned as well, even when no installation
+/// uses them anymore, but only while at least one installation is recorded: with none there is
+/// no way to tell whether they belong to installations eim does not know about.
+pub fn find_orphans(config_path: Option<&PathBuf>) -> Result<GcReport> {
+    let config_path = config_path
+        .cloned()
+        .unwrap_or_else(crate::version_manager::get_default_config_path);
+    // Without the list of installations everything would look orphaned
+    let config = IdfConfig::from_file(&config_path)
+        .map_err(|e| anyhow!("Failed to read {}: {}", config_path.display(), e))?;
+
+    let defaults = Settings::default();
+    let default_tools_dir = defaults.tool_install_folder_name.map(PathBuf::from);
+    let default_download_dir = defaults.tool_download_folder_name.map(PathBuf::from);
+    Ok(scan(
+        &config.idf_installed,
+        default_tools_dir.into_iter().collect(),
+        default_download_dir.into_iter().collect(),
+    ))
+}
+
+fn scan(
+    installations: &[IdfInstallation],
+    extra_tools_dirs: Vec<PathBuf>,
+    extra_download_dirs: Vec<PathBuf>,
+) -> GcReport {
+    let usages: Vec<InstallationUsage> = installations.iter().map(InstallationUsage::new).collect();
+    let mut report = GcReport::default();
+
+    // Without installations nothing references the files of the shared directories, all of
+    // them would look orphaned
+    if installations.is_empty() {
+        report.skipped = extra_tools_dirs.into_iter().chain(extra_download_dirs).collect();
+        report.skipped.sort();
+        return report;
+    }
+
+    let mut python_env_names: HashSet<String> = usages
+        .iter()
+        .filter_map(|u| u.python_env_dir.file_name())
+        .map(|n| n.to_string_lossy().into_owned())
+        .collect();
+    if let Some(name) = Settings::default().python_env_folder_name {
+        python_env_names.insert(name);
+    }
+
+    // Tool versions, per tools directory
+    let mut tools_dirs: HashMap<PathBuf, Vec<&InstallationUsage>> = HashMap::new();
+    for dir in extra_tools_dirs {
+        tools_dirs.entry(dir).or_default();
+    }
+    for usage in &usages {
+        tools_dirs.entry(usage.tools_dir.clone()).or_default().push(usage);
+    }
+    for (tools_dir, users) in &tools_dirs {
+        let Some(referenced) = referenced_tool_versions(users) else {
+            report.skipped.push(tools_dir.clone());
+            continue;
+        };
+        let tool_stores: Vec<ToolStore> = users.iter().filter_map(|u| u.tool_store.clone()).collect();
+        for tool_dir in subdirectories(tools_dir) {
+            let tool_name = file_name(&tool_dir);
+            if python_env_names.contains(&tool_name) || is_prerequisite_install_dir(&tool_dir) {
+                continue;
+            }
+            for version_dir in subdirectories(&tool_dir) {
+                if !referenced.contains(&(tool_name.clone(), file_name(&version_dir))) {
+                    report.push(OrphanKind::ToolVersion, version_dir);
+                    if let Some(orphan) = report.orphans.last_mut() {
+                        orphan.tool_stores = tool_stores.clone();
+                    }
+                }
+            }
+        }
+    }
+
+    // Python environments, per python env folder
+    let mut python_env_dirs: HashSet<PathBuf> = usages.iter().map(|u| u.python_env_dir.clone()).collect();
+    for tools_dir in tools_dirs.keys() {
+        for name in &python_env_names {
+            python_env_dirs.insert(tools_dir.join(name));
+        }
+    }
+    for env_dir in python_env_dirs {
+        // The standalone Python may be extracted right into the python env folder, its own
+        // directories are no environments
+        let holds_python = is_prerequisite_install_dir(&env_dir);
+        for version_dir in subdirectories(&env_dir) {
+            if holds_python && !version_dir.join("venv").join("pyvenv.cfg").is_file() {
+                continue;
+            }
+            if !usages.iter().any(|u| u.python.starts_with(&version_dir)) {
+                report.push(OrphanKind::PythonEnv, version_dir);
+            }
+        }
+    }
+
+    // Archives, per download directory
+    let mut download_dirs: HashMap<PathBuf, Vec<&InstallationUsage>> = HashMap::new();
+    for dir in extra_download_dirs {
+        download_dirs.entry(dir).or_default();
+    }
+    for usage in &usages {
+        download_dirs.entry(usage.download_dir.clone()).or_default().push(usage);
+    }
+    for (download_dir, users) in &download_dirs {
+        let Some(referenced) = referenced_archives(users) else {
+            report.skipped.push(download_dir.clone());
+            continue;
+        };
+        let Ok(entries) = fs::read_dir(download_dir) else {
+            continue;
+        };
+        for entry in entries.filter_map(|e| e.ok()) {
+            let path = entry.path();
+            if path.is_file() && !referenced.contains(&file_name(&path)) {
+                report.push(OrphanKind::Archive, path);
+            }
+        }
+    }
+
+    // A directory holding an installation is never garbage, whatever it is called
+    let protected: Vec<PathBuf> = installations.iter().map(|i| PathBuf::from(&i.path)).collect();
+    report.orphans.retain(|orphan| !protected.iter().any(|p| p.starts_with(&orphan.path)));
+    report.orphans.sort_by(|a, b| a.path.cmp(&b.path));
+    report.skipped.sort();
+    report.total_size = report.orphans.iter().map(|o| o.size).sum();
+    report
+}
+
+impl GcReport {
+    fn push(&mut self, kind: OrphanKind, path: PathBuf) {
+        let size = path_size(&path);
+        debug!("Found orphaned {:?} {} ({} bytes)", kind, path.display(), size);
+        self.orphans.push(Orphan {
+            kind,
+            path,
+            size,
+            tool_stores: Vec::new(),
+        });
+    }
+}
+
+/// All tool versions declared in the tools.json files of `users`, or `None` if one of them is unknown.
+fn referenced_tool_versions(users: &[&InstallationUsage]) -> Option<HashSet<(String, String)>> {
+    let mut referenced = HashSet::new();
+    for usage in users {
+        for tool in &usage.tools_file.as_ref()?.tools {
+            for version in &tool.versions {
+                referenced.insert((tool.name.clone(), version.name.clone()));
+            }
+        }
+    }
+    Some(referenced)
+}
+
+/// File names of all archives declared in the tools.json files of `users`, or `None` if one of them is unknown.
+fn referenced_archives(users: &[&InstallationUsage]) -> Option<HashSet<String>> {
+    let mut referenced = HashSet::new();
+    for usage in users {
+        for tool in &usage.tools_file.as_ref()?.tools {
+            for version in &tool.versions {
+                for download in version.downloads.values() {
+                    if let Some(name) = Path::new(&download.url).file_name() {
+                        referenced.insert(name.to_string_lossy().into_owned());
+                    }
+                    if let Some(name) = &download.rename_dist {
+                        referenced.insert(name.clone());
+                    }
+                }
+            }
+        }
+    }
+    Some(referenced)
+}
+
+fn subdirectories(dir: &Path) -> Vec<PathBuf> {
+    match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.is_dir())
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Size of a file or directory tree on disk, symlinks are not followed.
+fn path_size(path: &Path) -> u64 {
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return 0;
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+    fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .filter_map(|e| e.ok())
+                .map(|e| path_size(&e.path()))
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+/// Deletes the given orphans. Failures are collected, they do not stop the removal of the others.
+pub fn remove_orphans(orphans: &[Orphan]) -> GcResult {
+    let mut result = GcResult::default();
+    for orphan in orphans {
+        match remove_orphan(orphan) {
+            Ok(kept) => {
+                for path in &kept {
+                    warn!("Kept {}, it was not installed with the tool", path.display());
+                }
+                let kept_size: u64 = kept.iter().map(|p| path_size(p)).sum();
+                // Drop the tool directory once its last version is gone
+                if orphan.kind == OrphanKind::ToolVersion {
+                    if let Some(parent) = orphan.path.parent() {
+                        let _ = fs::remove_dir(parent);
+                    }
+                }
+                result.freed_bytes += orphan.size.saturating_sub(kept_size);
+                result.removed.push(orphan.clone());
+            }
+            Err(e) => result.failed.push((orphan.path.clone(), e.to_string())),
+        }
+    }
+    result
+}
+
+/// Removes an orphan and returns the files left in place. Tool versions linked from a tool store
+/// are released there, those with a file manifest only lose the files they were installed with.
+fn remove_orphan(orphan: &Orphan) -> io::Result<Vec<PathBuf>> {
+    for store in &orphan.tool_stores {
+        if store.forget_link(&orphan.path)? {
+            return Ok(Vec::new());
+        }
+    }
+    let metadata = fs::symlink_metadata(&orphan.path)?;
+    if orphan.kind == OrphanKind::ToolVersion && metadata.is_dir() {
+        crate::tool_manifest::uninstall(&orphan.path)
+    } else {
+        remove_path(&orphan.path).map(|()| Vec::new())
+    }
+}
+
+fn remove_path(path: &Path) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(path)?;
+    if metadata.file_type().is_symlink() {
+        // Directory symlinks and junctions are removed with remove_dir on Windows
+        fs::remove_file(path).or_else(|_| fs::remove_dir(path))
+    } else if metadata.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::write_tools_json;
+    use tempfile::TempDir;
+
+    fn installation(root: &Path, version: &str) -> IdfInstallation {
+        let idf_path = root.join(version).join("esp-idf");
+        write_tools_json(&idf_path, &[("cmake", "3.30.2")]);
+        let tools_dir = root.join("tools");
+        let settings = Settings {
+            tool_download_folder_name: Some(root.join("dist").to_string_lossy().into_owned()),
+            ..Settings::default()
+        };
+        IdfInstallation {
+            activation_script: String::new(),
+            id: version.to_string(),
+            idf_tools_path: tools_dir.to_string_lossy().into_owned(),
+            name: version.to_string(),
+            path: idf_path.to_string_lossy().into_owned(),
+            python: tools_dir
+                .join("python")
+                .join(version)
+                .join("venv")
+                .join("bin")
+                .join("python")
+                .to_string_lossy()
+                .into_owned(),
+            installation_config: Some(settings.to_installation_config().unwrap()),
+            idf_commit: None,
+            idf_source: None,
+        }
+    }
+
+    #[test]
+    fn test_scan_reports_only_unused_files() {
+        let root = TempDir::new().unwrap();
+        let tools = root.path().join("tools");
+        let dist = root.path().join("dist");
+        for dir in [
+            tools.join("cmake").join("3.30.2").join("bin"),
+            tools.join("cmake").join("3.24.0").join("bin"),
+            tools.join("python").join("v5.5").join("venv"),
+            tools.join("python").join("v5.3").join("venv"),
+            dist.clone(),
+        ] {
+            fs::create_dir_all(dir).unwrap();
+        }
+        fs::write(tools.join("cmake").join("3.24.0").join("bin").join("cmake"), b"12345").unwrap();
+        fs::write(dist.join("cmake-3.30.2.tar.gz"), b"used").unwrap();
+        fs::write(dist.join("cmake-3.24.0.tar.gz"), b"unused").unwrap();
+        fs::write(tools.join("eim_idf.json"), b"{}").unwrap();
+
+        let report = scan(&[installation(root.path(), "v5.5")], vec![], vec![]);
+
+        let found: Vec<(OrphanKind, PathBuf)> =
+            report.orphans.iter().map(|o| (o.kind, o.path.clone())).collect();
+        assert_eq!(
+            found,
+            vec![
+                (OrphanKind::Archive, dist.join("cmake-3.24.0.tar.gz")),
+                (OrphanKind::ToolVersion, tools.join("cmake").join("3.24.0")),
+                (OrphanKind::PythonEnv, tools.join("python").join("v5.3")),
+            ]
+        );
+        assert_eq!(report.total_size, 11);
+
+        let result = remove_orphans(&report.orphans);
+        assert!(result.failed.is_empty());
+        assert_eq!(result.freed_bytes, 11);
+        assert!(tools.join("cmake").join("3.30.2").exists());
+        assert!(!tools.join("cmake").join("3.24.0").exists());
+        assert!(tools.join("python").join("v5.5").exists());
+        assert!(dist.join("cmake-3.30.2.tar.gz").exists());
+    }
+
+    #[test]
+    fn test_scan_skips_directories_of_unknown_installations() {
+        let root = TempDir::new().unwrap();
+        let tools = root.path().join("tools");
+        fs::create_dir_all(tools.join("cmake").join("3.24.0")).unwrap();
+
+        let broken = installation(root.path(), "v5.4");
+        fs::remove_file(PathBuf::from(&broken.path).join("tools").join("tools.json")).unwrap();
+
+        let report = scan(&[broken], vec![], vec![]);
+        assert!(report.orphans.iter().all(|o| o.kind != OrphanKind::ToolVersion));
+        assert!(report.skipped.contains(&tools));
+    }
+
+    #[test]
+    fn test_scan_without_installations_leaves_shared_directories_alone() {
+        let root = TempDir::new().unwrap();
+        let tools = root.path().join("tools");
+        let dist = root.path().join("dist");
+        fs::create_dir_all(tools.join("cmake").join("3.30.2")).unwrap();
+        fs::create_dir_all(tools.join("python").join("v5.5").join("venv")).unwrap();
+        fs::create_dir_all(&dist).unwrap();
+        fs::write(dist.join("cmake-3.30.2.tar.gz"), b"archive").unwrap();
+
+        let report = scan(&[], vec![tools.clone()], vec![dist.clone()]);
+        assert!(report.orphans.is_empty());
+        assert_eq!(report.skipped, vec![dist, tools]);
+    }
+
+    #[test]
+    fn test_scan_keeps_git_and_python_installed_as_prerequisites() {
+        let root = TempDir::new().unwrap();
+        let tools = root.path().join("tools");
+        let python = tools.join("python");
+        for dir in [
+            tools.join("git").join("cmd"),
+            tools.join("git").join("usr"),
+            python.join("Lib").join("venv"),
+            python.join("DLLs"),
+            python.join("v5.5").join("venv"),
+            python.join("v5.3").join("venv"),
+        ] {
+            fs::create_dir_all(dir).unwrap();
+        }
+        fs::write(tools.join("git").join("cmd").join("git.exe"), b"git").unwrap();
+        fs::write(python.join("python.exe"), b"python").unwrap();
+        fs::write(python.join("v5.5").join("venv").join("pyvenv.cfg"), b"").unwrap();
+        fs::write(python.join("v5.3").join("venv").join("pyvenv.cfg"), b"").unwrap();
+
+        let report = scan(&[installation(root.path(), "v5.5")], vec![], vec![]);
+
+        let found: Vec<(OrphanKind, PathBuf)> =
+            report.orphans.iter().map(|o| (o.kind, o.path.clone())).collect();
+        assert_eq!(found, vec![(OrphanKind::PythonEnv, python.join("v5.3"))]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_removing_a_linked_tool_version_releases_it_in_the_store() {
+        use crate::tool_store::LinkMode;
+
+        let root = TempDir::new().unwrap();
+        let store_dir = root.path().join("store");
+        let tools = root.path().join("tools");
+        let link = tools.join("cmake").join("3.24.0");
+
+        let store = ToolStore::new(&store_dir, LinkMode::Symlink);
+        let entry = store.prepare_entry("cmake", "3.24.0", "linux-amd64").unwrap();
+        fs::write(entry.join("cmake"), b"cmake").unwrap();
+        store.mark_complete("cmake", "3.24.0", "linux-amd64").unwrap();
+        // Linked for an installation which since moved to another cmake
+        store
+            .clone()
+            .with_owner("/idf/v5.4")
+            .link("cmake", "3.24.0", "linux-amd64", &link)
+            .unwrap();
+
+        let mut current = installation(root.path(), "v5.5");
+        let settings = Settings {
+            tool_download_folder_name: Some(root.path().join("dist").to_string_lossy().into_owned()),
+            tool_store_dir: Some(store_dir.clone()),
+            ..Settings::default()
+        };
+        current.installation_config = Some(settings.to_installation_config().unwrap());
+
+        let report = scan(&[current], vec![], vec![]);
+        assert_eq!(report.orphans.len(), 1);
+        assert_eq!(report.orphans[0].path, link);
+
+        let result = remove_orphans(&report.orphans);
+        assert!(result.failed.is_empty());
+        assert!(fs::symlink_metadata(&link).is_err());
+        assert!(!entry.exists());
+        assert_eq!(store.reference_count("cmake", "3.24.0", "linux-amd64"), 0);
+    }
+}
@@ -0,0 +1,287 @@
+//! Shared store of git objects, reused by all ESP-IDF clones.
+//!
+//! When enabled, a bare repository in the eim data directory holds the objects of every ESP-IDF
+//! clone and its submodules. New clones reference it as a git alternate
+//! (`objects/info/alternates`), so the server only sends what the store does not have yet and a
+//! second ESP-IDF version downloads little more than the commits between the two versions.
+//!
+//! After a clone, the objects of the clone and of each of its submodules are fetched into the
+//! store under `refs/eim/<clone>/`, and the clone drops its own copies. Those refs tell the
+//! server which objects the store has when the next version is cloned.
+//!
+//! Clones can not work without the objects they borrow, so nothing is ever deleted from the
+//! store: removing an installation only drops its refs and [`GitObjectStore::repack`] keeps
+//! unreachable objects. Shallow repositories can not be used as a reference, so clones using the
+//! store are full clones and shallow git directories are never fetched into it.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Output;
+
+use log::{debug, info, warn};
+use sha2::{Digest, Sha256};
+
+use crate::command_executor::execute_command_with_dir;
+
+/// Namespace of the refs recording the clones that use the store.
+const REFS_PREFIX: &str = "refs/eim";
+
+/// Directory of the store unless configured otherwise, `None` when the system has no local
+/// data directory.
+pub fn default_git_object_store_dir() -> Option<PathBuf> {
+    dirs::data_local_dir().map(|dir| dir.join("eim").join("git-objects"))
+}
+
+#[derive(Debug, Clone)]
+pub struct GitObjectStore {
+    root: PathBuf,
+}
+
+impl GitObjectStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// Builds the store when `git_object_store` is enabled in `settings`.
+    pub fn from_settings(settings: &crate::settings::Settings) -> Option<Self> {
+        if !settings.git_object_store.unwrap_or(false) {
+            return None;
+        }
+        default_git_object_store_dir().map(Self::new)
+    }
+
+    /// The store in its default location if it was ever created, whatever the current settings
+    /// say. Used to clean up after installations that were cloned with the store.
+    pub fn existing() -> Option<Self> {
+        default_git_object_store_dir()
+            .map(Self::new)
+            .filter(|store| store.is_initialized())
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn is_initialized(&self) -> bool {
+        self.root.join("HEAD").is_file() && self.root.join("objects").is_dir()
+    }
+
+    /// Creates the bare repository if needed. Automatic garbage collection is switched off in it,
+    /// git would otherwise prune objects clones still borrow.
+    pub fn ensure(&self) -> io::Result<()> {
+        if !self.is_initialized() {
+            fs::create_dir_all(&self.root)?;
+            self.git(&["init", "--bare", "--quiet"])?;
+            info!("Created the shared git object store in {}", self.root.display());
+        }
+        self.git(&["config", "gc.auto", "0"])?;
+        self.git(&["config", "gc.pruneExpire", "never"])?;
+        Ok(())
+    }
+
+    /// Moves the objects of the clone at `clone_path` and of all its submodules into the store.
+    ///
+    /// Every git directory is fetched into the store under refs of the clone, then repacked
+    /// without the objects the store now has. Returns the number of git directories that were
+    /// absorbed; shallow ones are skipped.
+    pub fn absorb(&self, clone_path: &Path) -> io::Result<usize> {
+        let key = owner_key(clone_path);
+        let mut absorbed = 0;
+        for (name, git_dir) in git_dirs(clone_path) {
+            if git_dir.join("shallow").exists() {
+                debug!("Not adding the shallow repository {} to the git object store", git_dir.display());
+                continue;
+            }
+            let refspec = format!("+HEAD:{}/{}/{}", REFS_PREFIX, key, name);
+            let source = git_dir.to_string_lossy().to_string();
+            self.git(&["fetch", "--quiet", "--no-tags", &source, &refspec])?;
+            check_status(
+                execute_command_with_dir("git", &["repack", "-a", "-d", "-l", "-q"], &source)?,
+                "git repack",
+            )?;
+            absorbed += 1;
+        }
+        debug!("Added {} repositories of {} to the git object store", absorbed, clone_path.display());
+        Ok(absorbed)
+    }
+
+    /// Drops the refs recorded for the clone at `clone_path`. Its objects stay in the store,
+    /// other clones may borrow them.
+    pub fn release(&self, clone_path: &Path) -> io::Result<usize> {
+        let prefix = format!("{}/{}/", REFS_PREFIX, owner_key(clone_path));
+        let output = self.git(&["for-each-ref", "--format=%(refname)", &prefix])?;
+        let refs: Vec<String> = String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect();
+        for refname in &refs {
+            self.git(&["update-ref", "-d", refname])?;
+        }
+        Ok(refs.len())
+    }
+
+    /// Repacks the store into a single pack. Unreachable objects are kept, a clone may borrow
+    /// objects that no recorded ref points to anymore.
+    pub fn repack(&self) -> io::Result<()> {
+        self.git(&["repack", "-a", "-d", "--keep-unreachable", "-q"])?;
+        Ok(())
+    }
+
+    fn git(&self, args: &[&str]) -> io::Result<Output> {
+        let output = execute_command_with_dir("git", args, &self.root.to_string_lossy())?;
+        check_status(output, &format!("git {}", args.first().copied().unwrap_or_default()))
+    }
+}
+
+fn check_status(output: Output, command: &str) -> io::Result<Output> {
+    if output.status.success() {
+        Ok(output)
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        warn!("{} failed: {}", command, stderr);
+        Err(io::Error::other(format!("{} failed: {}", command, stderr)))
+    }
+}
+
+/// Identifies a clone in the store refs, stable for the same path written differently.
+fn owner_key(clone_path: &Path) -> String {
+    let path = crate::utils::normalize_path_for_comparison(&clone_path.to_string_lossy())
+        .unwrap_or_else(|| clone_path.to_string_lossy().trim_end_matches(['/', '\\']).to_string());
+    let digest = Sha256::digest(path.as_bytes());
+    format!("{:x}", digest)[..16].to_string()
+}
+
+/// The git directories of the clone at `clone_path` with the ref names used for them: `root` for
+/// the clone itself and the flattened path below `.git/modules` for each submodule, nested
+/// ones included. Paths are flattened because a ref can not also be a ref directory.
+fn git_dirs(clone_path: &Path) -> Vec<(String, PathBuf)> {
+    let git_dir = clone_path.join(".git");
+    let mut dirs = Vec::new();
+    if !git_dir.is_dir() {
+        return dirs;
+    }
+    dirs.push(("root".to_string(), git_dir.clone()));
+    collect_module_dirs(&git_dir.join("modules"), &git_dir.join("modules"), &mut dirs);
+    dirs
+}
+
+fn collect_module_dirs(modules_root: &Path, dir: &Path, dirs: &mut Vec<(String, PathBuf)>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let mut entries: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+        .collect();
+    entries.sort();
+    for path in entries {
+        if path.join("HEAD").is_file() && path.join("objects").is_dir() {
+            let relative = path
+                .strip_prefix(modules_root)
+                .unwrap_or(&path)
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("--");
+            dirs.push((format!("modules/{}", relative), path.clone()));
+            // Nested submodules live in the `modules` directory of their parent
+            collect_module_dirs(modules_root, &path.join("modules"), dirs);
+        } else {
+            // Submodules with a path like `components/bt/controller/lib_esp32`
+            collect_module_dirs(modules_root, &path, dirs);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::run_git;
+    use tempfile::TempDir;
+
+    fn git_dir(path: &Path) {
+        fs::create_dir_all(path.join("objects")).unwrap();
+        fs::write(path.join("HEAD"), "ref: refs/heads/master\n").unwrap();
+    }
+
+    #[test]
+    fn test_git_dirs_include_nested_submodules() {
+        let root = TempDir::new().unwrap();
+        let clone = root.path().join("esp-idf");
+        git_dir(&clone.join(".git"));
+        git_dir(&clone.join(".git/modules/components/bt/controller/lib_esp32"));
+        git_dir(&clone.join(".git/modules/components/mbedtls/mbedtls"));
+        git_dir(&clone.join(".git/modules/components/mbedtls/mbedtls/modules/framework"));
+
+        let names: Vec<String> = git_dirs(&clone).into_iter().map(|(name, _)| name).collect();
+        assert_eq!(
+            names,
+            vec![
+                "root",
+                "modules/components--bt--controller--lib_esp32",
+                "modules/components--mbedtls--mbedtls",
+                "modules/components--mbedtls--mbedtls--modules--framework",
+            ]
+        );
+        assert!(git_dirs(root.path()).is_empty());
+    }
+
+    #[test]
+    fn test_owner_key_ignores_trailing_separator() {
+        let root = TempDir::new().unwrap();
+        let clone = root.path().join("esp-idf");
+        fs::create_dir_all(&clone).unwrap();
+        let with_separator = PathBuf::from(format!("{}/", clone.display()));
+        assert_eq!(owner_key(&clone), owner_key(&with_separator));
+        assert_eq!(owner_key(&clone).len(), 16);
+        assert_ne!(owner_key(&clone), owner_key(root.path()));
+    }
+
+    #[test]
+    fn test_clone_keeps_working_after_the_originating_clone_is_removed() {
+        let root = TempDir::new().unwrap();
+        let origin = root.path().join("origin");
+        fs::create_dir_all(&origin).unwrap();
+        run_git(&origin, &["init", "--quiet"]);
+        fs::write(origin.join("README.md"), "ESP-IDF\n").unwrap();
+        run_git(&origin, &["add", "README.md"]);
+        run_git(
+            &origin,
+            &["-c", "user.name=eim", "-c", "user.email=eim@example.com", "commit", "--quiet", "-m", "Initial commit"],
+        );
+
+        let store = GitObjectStore::new(root.path().join("git-objects"));
+        store.ensure().unwrap();
+        let store_path = store.root().to_string_lossy().to_string();
+        let origin_url = origin.to_string_lossy().to_string();
+        let clone = |name: &str| {
+            let path = root.path().join(name);
+            let path_str = path.to_string_lossy().to_string();
+            run_git(
+                root.path(),
+                &["clone", "--quiet", "--no-local", "--reference-if-able", &store_path, &origin_url, &path_str],
+            );
+            assert_eq!(store.absorb(&path).unwrap(), 1);
+            path
+        };
+
+        // The first installation brings the objects into the store, the second borrows them
+        let first = clone("v5.4");
+        let second = clone("v5.5");
+        assert!(second.join(".git/objects/info/alternates").is_file());
+
+        // The first installation and even the origin go away, the store is repacked
+        assert_eq!(store.release(&first).unwrap(), 1);
+        fs::remove_dir_all(&first).unwrap();
+        fs::remove_dir_all(&origin).unwrap();
+        store.repack().unwrap();
+
+        run_git(&second, &["fsck", "--full", "--no-progress"]);
+        assert_eq!(run_git(&second, &["show", "HEAD:README.md"]), "ESP-IDF");
+        assert_eq!(run_git(&second, &["log", "--format=%s"]), "Initial commit");
+    }
+}
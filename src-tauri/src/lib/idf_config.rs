@@ -72,6 +72,10 @@ pub struct IdfInstallation {
     pub python: String,
     #[serde(rename = "installationConfig", default, skip_serializing_if = "Option::is_none", deserialize_with = "deserialize_base64_bytes")]
     pub installation_config: Option<Base64Bytes>,
+    #[serde(rename = "idfCommit", default, skip_serializing_if = "Option::is_none")]
+    pub idf_commit: Option<String>, // Commit of the ESP-IDF checkout when it was installed, checked by `eim verify`
+    #[serde(rename = "idfSource", default, skip_serializing_if = "Option::is_none")]
+    pub idf_source: Option<String>, // `git+<url>@<reference>` spec ESP-IDF was cloned from, unset for Espressif releases
 }
 
 pub const IDF_CONFIG_FILE_NAME: &str = "eim_idf.json";
@@ -351,6 +355,8 @@ mod tests {
                     path: String::from("/tmp/esp-new/v5.4/esp-idf"),
                     python: String::from("/tmp/esp-new/v5.4/tools/python/bin/python3"),
                     installation_config: None,
+                    idf_commit: None,
+                    idf_source: None,
 
                 },
                 IdfInstallation {
@@ -361,6 +367,8 @@ mod tests {
                     path: String::from("/tmp/esp-new/v5.1.5/esp-idf"),
                     python: String::from("/tmp/esp-new/v5.1.5/tools/python/bin/python3"),
                     installation_config: None,
+                    idf_commit: None,
+                    idf_source: None,
                 },
             ],
             idf_selected_id: String::from("esp-idf-5705c12db93b4d1a8b084c6986173c1b"),
@@ -371,7 +379,7 @@ mod tests {
 
     fn create_test_config_with_installation_config() -> IdfConfig {
         let settings = crate::settings::Settings::default();
-        let settings_binary = bincode::serialize(&settings).unwrap();
+        let settings_binary = settings.to_installation_config().unwrap().into_inner();
 
         IdfConfig {
             git_path: String::from("/opt/homebrew/bin/git"),
@@ -384,6 +392,8 @@ mod tests {
                     path: String::from("/tmp/esp-new/v5.4/esp-idf"),
                     python: String::from("/tmp/esp-new/v5.4/tools/python/bin/python3"),
                     installation_config: Some(Base64Bytes::new(settings_binary.clone())),
+                    idf_commit: None,
+                    idf_source: None,
                 },
                 IdfInstallation {
                     activation_script: String::from("/tmp/esp-new/activate_idf_v5.1.5.sh"),
@@ -393,6 +403,8 @@ mod tests {
                     path: String::from("/tmp/esp-new/v5.1.5/esp-idf"),
                     python: String::from("/tmp/esp-new/v5.1.5/tools/python/bin/python3"),
                     installation_config: Some(Base64Bytes::new(settings_binary)),
+                    idf_commit: None,
+                    idf_source: None,
                 },
             ],
             idf_selected_id: String::from("esp-idf-5705c12db93b4d1a8b084c6986173c1b"),
@@ -492,6 +504,8 @@ mod tests {
             path: String::from("/esp/idf/v5.1.0"),
             python: String::from("/usr/bin/python3"),
             installation_config: None,
+            idf_commit: None,
+            idf_source: None,
         };
 
         config.idf_installed = vec![new_installation.clone()];
@@ -583,6 +597,8 @@ fn test_append_with_same_path_replacement() -> Result<()> {
                 path: String::from("/tmp/esp-new/v5.1.5/esp-idf"), // Same path as the first installation in initial_config
                 python: String::from("/tmp/esp/v5.0/updated-tools/python/bin/python3"),
                 installation_config: None,
+                idf_commit: None,
+                idf_source: None,
             },
         ],
         idf_selected_id: String::from("esp-idf-new-id"),
@@ -677,9 +693,9 @@ fn test_append_with_same_path_replacement() -> Result<()> {
     for installation in &read_config.idf_installed {
       assert!(installation.installation_config.is_some());
 
-      // Verify we can deserialize the binary data back to Settings
+      // Verify we can decode the stored data back to Settings
       let settings_bytes = installation.installation_config.as_ref().unwrap();
-      let settings: crate::settings::Settings = bincode::deserialize(settings_bytes.as_slice())?;
+      let settings = crate::settings::Settings::from_installation_config(settings_bytes.as_slice())?;
       assert!(settings.path.is_some());
     }
 
@@ -750,6 +766,8 @@ fn test_append_with_same_path_replacement() -> Result<()> {
       path: String::from("/new/esp/new/esp-idf"),
       python: String::from("/new/esp/tools/python/bin/python3"),
       installation_config: None,
+      idf_commit: None,
+      idf_source: None,
     };
 
     config.idf_installed.push(new_installation);
@@ -65,7 +65,9 @@ impl RequirementsMetadata {
 
     /// Parse from a URL
     pub fn from_url(url: &str) -> Result<Self, ParseError> {
-        let response = reqwest::blocking::get(url)
+        let response = crate::proxy::blocking_client_builder()
+            .build()
+            .and_then(|client| client.get(url).send())
             .map_err(|e: reqwest::Error| ParseError::HttpError(e.to_string()))?;
 
         let text = response
@@ -77,7 +79,11 @@ impl RequirementsMetadata {
 
     /// Parse from a URL (async version)
     pub async fn from_url_async(url: &str) -> Result<Self, ParseError> {
-        let response: reqwest::Response = reqwest::get(url)
+        let client = crate::proxy::client_builder()
+            .build()
+            .map_err(|e: reqwest::Error| ParseError::HttpError(e.to_string()))?;
+        let response: reqwest::Response = client.get(url)
+            .send()
             .await
             .map_err(|e: reqwest::Error| ParseError::HttpError(e.to_string()))?;
 
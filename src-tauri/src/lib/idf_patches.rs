@@ -0,0 +1,311 @@
+//! Local patches applied on top of an ESP-IDF checkout.
+//!
+//! The patch queue of an installation is the list in
+//! [`Settings::idf_patches`](crate::settings::Settings::idf_patches), stored with the
+//! installation like the rest of its settings. Each entry is a patch file (a plain diff or a
+//! `git format-patch` mbox) or a directory whose `.patch`, `.diff` and `.mbox` files are taken in
+//! name order. The queue is applied with `git apply --index` after ESP-IDF is cloned, and again
+//! after `eim update` and `eim fix`.
+//!
+//! Copies of the applied patches are kept in `.git/eim-patches` of the checkout together with a
+//! digest of the resulting changes. They tell which patches are applied, let them be reverted
+//! before the queue is applied again or the checkout moves to another version, and let
+//! `eim verify` notice when the patched files were changed afterwards.
+//!
+//! Applying is all or nothing: when a patch does not apply, the patches applied before it are
+//! reverted and the checkout is left as it was.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use log::{debug, info, warn};
+use sha2::{Digest, Sha256};
+
+use crate::command_executor::execute_command_with_dir;
+
+/// Directory of the git directory holding the applied patches.
+const APPLIED_DIR: &str = "eim-patches";
+/// Digest of `git diff HEAD` right after the queue was applied.
+const DIGEST_FILE: &str = "diff.sha256";
+const PATCH_EXTENSIONS: [&str; 3] = ["patch", "diff", "mbox"];
+
+fn applied_dir(idf_path: &Path) -> PathBuf {
+    idf_path.join(".git").join(APPLIED_DIR)
+}
+
+/// The patch files of the queue `entries` in the order they are applied. Fails when an entry
+/// does not exist.
+pub fn patch_files(entries: &[String]) -> Result<Vec<PathBuf>, String> {
+    let mut files = Vec::new();
+    for entry in entries {
+        let path = PathBuf::from(entry);
+        if path.is_file() {
+            files.push(path);
+        } else if path.is_dir() {
+            let mut patches: Vec<PathBuf> = fs::read_dir(&path)
+                .map_err(|e| format!("Failed to read the patch directory {}: {}", path.display(), e))?
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|p| {
+                    p.is_file()
+                        && p.extension()
+                            .is_some_and(|ext| PATCH_EXTENSIONS.contains(&ext.to_string_lossy().as_ref()))
+                })
+                .collect();
+            patches.sort();
+            if patches.is_empty() {
+                warn!("The patch directory {} has no .patch, .diff or .mbox files", path.display());
+            }
+            files.extend(patches);
+        } else {
+            return Err(format!("Patch {} does not exist", path.display()));
+        }
+    }
+    Ok(files)
+}
+
+/// Names of the patches applied to the checkout at `idf_path`, in the order they were applied.
+pub fn applied_patches(idf_path: &Path) -> Vec<String> {
+    applied_patch_files(idf_path)
+        .iter()
+        .filter_map(|path| path.file_name())
+        .map(|name| {
+            let name = name.to_string_lossy();
+            // Copies are numbered to keep the order, e.g. 0001-fix-uart.patch
+            name.split_once('-').map(|(_, rest)| rest.to_string()).unwrap_or_else(|| name.to_string())
+        })
+        .collect()
+}
+
+fn applied_patch_files(idf_path: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(applied_dir(idf_path)) else {
+        return Vec::new();
+    };
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.file_name().is_some_and(|name| name != DIGEST_FILE))
+        .collect();
+    files.sort();
+    files
+}
+
+/// Whether the changes in the checkout at `idf_path` are still exactly the ones the applied
+/// patches made. `None` when no patches are applied or the checkout can not be read.
+pub fn patches_intact(idf_path: &Path) -> Option<bool> {
+    let recorded = fs::read_to_string(applied_dir(idf_path).join(DIGEST_FILE)).ok()?;
+    let current = diff_digest(idf_path).ok()?;
+    Some(recorded.trim() == current)
+}
+
+/// Reverts the patches applied to the checkout at `idf_path` and returns their names. The
+/// checkout is left untouched when one of them no longer reverts cleanly, e.g. because the
+/// patched files were changed.
+pub fn revert_patches(idf_path: &Path) -> Result<Vec<String>, String> {
+    let names = applied_patches(idf_path);
+    let mut files = applied_patch_files(idf_path);
+    if files.is_empty() {
+        return Ok(names);
+    }
+    files.reverse();
+    if let Err((failed, error)) = apply_all(idf_path, &files, true) {
+        return Err(format!(
+            "Failed to revert patch {} of {}, the patched files were changed: {}",
+            failed.display(),
+            idf_path.display(),
+            error
+        ));
+    }
+    fs::remove_dir_all(applied_dir(idf_path)).map_err(|e| e.to_string())?;
+    info!("Reverted {} patches of {}", names.len(), idf_path.display());
+    Ok(names)
+}
+
+/// Applies the patch queue `entries` to the checkout at `idf_path`, reverting the patches
+/// applied before first, and returns the names of the applied patches.
+///
+/// When a patch does not apply, the ones applied before it are reverted and the error names the
+/// patch and the files that conflict; the checkout is then left without patches.
+pub fn apply_patches(idf_path: &Path, entries: &[String]) -> Result<Vec<String>, String> {
+    let files = patch_files(entries)?;
+    revert_patches(idf_path)?;
+    if files.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    if let Err((failed, error)) = apply_all(idf_path, &files, false) {
+        return Err(format!(
+            "Patch {} does not apply to {}, the checkout was left without patches: {}",
+            failed.display(),
+            idf_path.display(),
+            error
+        ));
+    }
+
+    let dir = applied_dir(idf_path);
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    let mut names = Vec::new();
+    for (index, file) in files.iter().enumerate() {
+        let name = file.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        fs::copy(file, dir.join(format!("{:04}-{}", index + 1, name)))
+            .map_err(|e| format!("Failed to record patch {}: {}", file.display(), e))?;
+        names.push(name);
+    }
+    let digest = diff_digest(idf_path)?;
+    fs::write(dir.join(DIGEST_FILE), digest).map_err(|e| e.to_string())?;
+    info!("Applied {} patches to {}", names.len(), idf_path.display());
+    Ok(names)
+}
+
+/// Applies `files` in order, or reverts them with `reverse`. When one fails, the ones done
+/// before it are undone again and the failing file is returned with the error of git.
+fn apply_all(idf_path: &Path, files: &[PathBuf], reverse: bool) -> Result<(), (PathBuf, String)> {
+    for (index, file) in files.iter().enumerate() {
+        debug!("{} {}", if reverse { "Reverting" } else { "Applying" }, file.display());
+        if let Err(error) = git_apply(idf_path, file, reverse) {
+            for done in files[..index].iter().rev() {
+                if let Err(e) = git_apply(idf_path, done, !reverse) {
+                    warn!("Failed to undo {}: {}", done.display(), e);
+                }
+            }
+            return Err((file.clone(), error));
+        }
+    }
+    Ok(())
+}
+
+fn git_apply(idf_path: &Path, file: &Path, reverse: bool) -> Result<(), String> {
+    let file = file.to_string_lossy();
+    let mut args = vec!["apply", "--index", "--whitespace=nowarn"];
+    if reverse {
+        args.push("--reverse");
+    }
+    args.push(&file);
+    let output = execute_command_with_dir("git", &args, &idf_path.to_string_lossy()).map_err(|e| e.to_string())?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+    }
+}
+
+/// Digest of the changes of the checkout against its head commit.
+fn diff_digest(idf_path: &Path) -> Result<String, String> {
+    let output = execute_command_with_dir("git", &["diff", "HEAD", "--binary"], &idf_path.to_string_lossy())
+        .map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(format!("git diff failed: {}", String::from_utf8_lossy(&output.stderr)));
+    }
+    Ok(format!("{:x}", Sha256::digest(&output.stdout)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::run_git;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_patch_files_expands_directories_in_name_order() {
+        let root = TempDir::new().unwrap();
+        let dir = root.path().join("patches");
+        fs::create_dir_all(&dir).unwrap();
+        for name in ["0002-b.patch", "0001-a.mbox", "README.md", "0003-c.diff"] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+        let single = root.path().join("extra.patch");
+        fs::write(&single, "").unwrap();
+
+        let entries = vec![single.to_string_lossy().to_string(), dir.to_string_lossy().to_string()];
+        let names: Vec<String> = patch_files(&entries)
+            .unwrap()
+            .iter()
+            .map(|p| p.file_name().unwrap().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(names, vec!["extra.patch", "0001-a.mbox", "0002-b.patch", "0003-c.diff"]);
+
+        let missing = vec![root.path().join("missing.patch").to_string_lossy().to_string()];
+        assert!(patch_files(&missing).is_err());
+    }
+
+    /// A checkout with `uart.c` and `spi.c` committed, and a patch queue directory holding a
+    /// patch that applies to it and a later one that conflicts with it.
+    fn checkout_with_patches(root: &Path) -> (PathBuf, PathBuf) {
+        let idf_path = root.join("esp-idf");
+        fs::create_dir_all(&idf_path).unwrap();
+        run_git(&idf_path, &["init", "--quiet"]);
+        fs::write(idf_path.join("uart.c"), "int baud = 9600;\n").unwrap();
+        fs::write(idf_path.join("spi.c"), "int mode = 0;\n").unwrap();
+        run_git(&idf_path, &["add", "."]);
+        run_git(
+            &idf_path,
+            &["-c", "user.name=eim", "-c", "user.email=eim@example.com", "commit", "--quiet", "-m", "v5.4"],
+        );
+
+        let patches = root.join("patches");
+        fs::create_dir_all(&patches).unwrap();
+        fs::write(
+            patches.join("0001-uart.patch"),
+            "--- a/uart.c\n+++ b/uart.c\n@@ -1 +1 @@\n-int baud = 9600;\n+int baud = 115200;\n",
+        )
+        .unwrap();
+        fs::write(
+            patches.join("0002-spi.patch"),
+            "--- a/spi.c\n+++ b/spi.c\n@@ -1 +1 @@\n-int mode = 3;\n+int mode = 1;\n",
+        )
+        .unwrap();
+        (idf_path, patches)
+    }
+
+    fn entry(path: &Path) -> Vec<String> {
+        vec![path.to_string_lossy().to_string()]
+    }
+
+    #[test]
+    fn test_conflicting_patch_leaves_checkout_without_patches() {
+        let root = TempDir::new().unwrap();
+        let (idf_path, patches) = checkout_with_patches(root.path());
+
+        let error = apply_patches(&idf_path, &entry(&patches)).unwrap_err();
+
+        assert!(error.contains("0002-spi.patch"), "{}", error);
+        // The patch applied before the conflicting one was rolled back
+        assert_eq!(fs::read_to_string(idf_path.join("uart.c")).unwrap(), "int baud = 9600;\n");
+        assert!(run_git(&idf_path, &["status", "--porcelain"]).is_empty());
+        assert!(applied_patches(&idf_path).is_empty());
+        assert_eq!(patches_intact(&idf_path), None);
+    }
+
+    #[test]
+    fn test_patches_are_reverted_before_being_applied_again() {
+        let root = TempDir::new().unwrap();
+        let (idf_path, patches) = checkout_with_patches(root.path());
+        let uart = patches.join("0001-uart.patch");
+
+        assert_eq!(apply_patches(&idf_path, &entry(&uart)).unwrap(), vec!["0001-uart.patch"]);
+        assert_eq!(fs::read_to_string(idf_path.join("uart.c")).unwrap(), "int baud = 115200;\n");
+        assert_eq!(applied_patches(&idf_path), vec!["0001-uart.patch"]);
+        assert_eq!(patches_intact(&idf_path), Some(true));
+
+        // Applying the queue again does not stack the patch on top of itself
+        assert_eq!(apply_patches(&idf_path, &entry(&uart)).unwrap(), vec!["0001-uart.patch"]);
+        assert_eq!(fs::read_to_string(idf_path.join("uart.c")).unwrap(), "int baud = 115200;\n");
+
+        assert_eq!(revert_patches(&idf_path).unwrap(), vec!["0001-uart.patch"]);
+        assert_eq!(fs::read_to_string(idf_path.join("uart.c")).unwrap(), "int baud = 9600;\n");
+        assert!(applied_patches(&idf_path).is_empty());
+    }
+
+    #[test]
+    fn test_edits_to_patched_files_are_detected() {
+        let root = TempDir::new().unwrap();
+        let (idf_path, patches) = checkout_with_patches(root.path());
+        apply_patches(&idf_path, &entry(&patches.join("0001-uart.patch"))).unwrap();
+
+        fs::write(idf_path.join("uart.c"), "int baud = 921600;\n").unwrap();
+
+        assert_eq!(patches_intact(&idf_path), Some(false));
+        // The edited file no longer reverts cleanly and is left alone
+        assert!(revert_patches(&idf_path).is_err());
+        assert_eq!(fs::read_to_string(idf_path.join("uart.c")).unwrap(), "int baud = 921600;\n");
+        assert_eq!(applied_patches(&idf_path), vec!["0001-uart.patch"]);
+    }
+}
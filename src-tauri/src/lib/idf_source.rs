@@ -0,0 +1,260 @@
+//! ESP-IDF installed from an arbitrary git repository instead of an Espressif release.
+//!
+//! Wherever an ESP-IDF version is expected, a source spec `git+<url>[@<reference>]` can be given
+//! instead, e.g. `git+https://github.com/org/esp-idf@my-branch`. The reference is a branch name,
+//! a commit id of 7 to 40 hex digits or a full ref name such as `refs/tags/v5.4-patched` or
+//! `refs/pull/123/head`; without one the default branch of the repository is checked out.
+//!
+//! The Espressif repository settings (`repo_stub`, the mirrors) do not apply to such a source,
+//! and since its `tools.json` and `tools/requirements.json` may differ from any release, they
+//! are read from the checkout instead of being downloaded.
+
+use sha2::{Digest, Sha256};
+
+use crate::git_tools::GitReference;
+
+/// Prefix telling a source spec apart from a version name.
+pub const IDF_SOURCE_PREFIX: &str = "git+";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IdfSource {
+    /// URL of the repository, without the `git+` prefix and the reference.
+    pub url: String,
+    pub reference: GitReference,
+}
+
+/// Whether `version` is a source spec rather than an ESP-IDF version name.
+pub fn is_idf_source(version: &str) -> bool {
+    version.starts_with(IDF_SOURCE_PREFIX)
+}
+
+/// Name of a new installation of `version`: the version itself, or the
+/// [`IdfSource::version_name`] of a source spec.
+pub fn installation_name(version: &str) -> String {
+    if !is_idf_source(version) {
+        return version.to_string();
+    }
+    IdfSource::parse(version)
+        .map(|source| source.version_name())
+        .unwrap_or_else(|_| version.to_string())
+}
+
+impl IdfSource {
+    /// Parses a `git+<url>[@<reference>]` spec.
+    ///
+    /// The reference is taken from the last `@` in the path of the URL, so user names like in
+    /// `git+ssh://git@host/org/esp-idf` are left alone.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let rest = spec
+            .trim()
+            .strip_prefix(IDF_SOURCE_PREFIX)
+            .ok_or_else(|| format!("'{}' does not start with {}", spec, IDF_SOURCE_PREFIX))?;
+        let scheme_end = rest
+            .find("://")
+            .map(|pos| pos + 3)
+            .ok_or_else(|| format!("'{}' has no URL scheme, e.g. git+https://", spec))?;
+        let scheme = &rest[..scheme_end - 3];
+        if !["https", "http", "ssh", "git", "file"].contains(&scheme) {
+            return Err(format!("'{}' uses the unsupported URL scheme {}", spec, scheme));
+        }
+        let path_start = rest[scheme_end..]
+            .find('/')
+            .map(|pos| scheme_end + pos)
+            .ok_or_else(|| format!("'{}' has no repository path", spec))?;
+
+        let (url, reference) = match rest[path_start..].rfind('@') {
+            Some(pos) => (&rest[..path_start + pos], Some(&rest[path_start + pos + 1..])),
+            None => (rest, None),
+        };
+        let url = url.trim_end_matches('/');
+        if url.len() <= path_start + 1 {
+            return Err(format!("'{}' has no repository path", spec));
+        }
+
+        let reference = match reference {
+            None => GitReference::None,
+            Some("") => return Err(format!("'{}' has an empty reference after @", spec)),
+            Some(reference) => parse_reference(reference)
+                .ok_or_else(|| format!("'{}' is not a valid git reference", reference))?,
+        };
+        Ok(Self {
+            url: url.to_string(),
+            reference,
+        })
+    }
+
+    /// Name of the installation made from this source, also used for its directory and its
+    /// activation script: the owner and name of the repository, the reference and a short hash
+    /// of the URL and the reference, e.g. `org-esp-idf-my-branch-3f9a1c`,
+    /// `org-esp-idf-pull-123-head-3f9a1c` or `org-esp-idf-1a2b3c4d-3f9a1c`. Forks building the
+    /// same branch, repositories with the same path on different hosts and references spelled
+    /// alike once made safe for a file name, like `feature/usb` and `feature-usb`, get different
+    /// names.
+    pub fn version_name(&self) -> String {
+        let url = self.url.trim_end_matches(".git");
+        let mut segments = url.rsplit(['/', ':']).filter(|segment| !segment.is_empty());
+        let repository = segments.next().unwrap_or_default();
+        let owner = segments.next().filter(|owner| !url.ends_with(&format!("//{}/{}", owner, repository)));
+        let (reference, full_reference) = match &self.reference {
+            GitReference::Branch(name) => (name.clone(), name.clone()),
+            GitReference::Tag(name) => (name.clone(), format!("refs/tags/{}", name)),
+            GitReference::Commit(sha) => (sha.chars().take(8).collect(), sha.clone()),
+            GitReference::Ref(name) => (
+                name.strip_prefix("refs/tags/")
+                    .or_else(|| name.strip_prefix("refs/"))
+                    .unwrap_or(name)
+                    .to_string(),
+                name.clone(),
+            ),
+            GitReference::None => (String::new(), String::new()),
+        };
+        let hash = source_hash(url, &full_reference);
+        let name = [owner.unwrap_or_default(), repository, reference.as_str(), hash.as_str()]
+            .iter()
+            .filter(|part| !part.is_empty())
+            .copied()
+            .collect::<Vec<_>>()
+            .join("-");
+        name.chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '_' { c } else { '-' })
+            .collect()
+    }
+}
+
+/// First six hex digits of the sha256 of `url` and the unaltered `reference`, which tells
+/// repositories with the same owner and name, and references that only differ in the characters
+/// replaced in the name, apart. Only the scheme and the host of the URL are not case sensitive,
+/// so only they are lowercased.
+fn source_hash(url: &str, reference: &str) -> String {
+    let authority_start = url.find("://").map_or(0, |pos| pos + 3);
+    let authority_end = url[authority_start..]
+        .find('/')
+        .map_or(url.len(), |pos| authority_start + pos);
+    let host_start = url[authority_start..authority_end]
+        .rfind('@')
+        .map_or(authority_start, |pos| authority_start + pos + 1);
+    let mut source = format!(
+        "{}{}{}{}",
+        url[..authority_start].to_lowercase(),
+        &url[authority_start..host_start],
+        url[host_start..authority_end].to_lowercase(),
+        &url[authority_end..]
+    );
+    if !reference.is_empty() {
+        source.push('@');
+        source.push_str(reference);
+    }
+    let digest = Sha256::digest(source.as_bytes());
+    format!("{:x}", digest)[..6].to_string()
+}
+
+/// Maps the reference of a source spec to a `GitReference`: commit ids of 7 to 40 hex digits to
+/// commits (a branch with such a name has to be given as `refs/heads/<name>`),
+/// `refs/heads/` and `refs/tags/` to branches and tags, other full ref names are fetched as they
+/// are and anything else is a branch name.
+fn parse_reference(reference: &str) -> Option<GitReference> {
+    if reference.contains("..")
+        || reference.ends_with('/')
+        || reference.contains(|c: char| c.is_whitespace() || "~^:?*[\\".contains(c))
+    {
+        return None;
+    }
+    let reference = if (7..=40).contains(&reference.len()) && reference.chars().all(|c| c.is_ascii_hexdigit()) {
+        GitReference::Commit(reference.to_lowercase())
+    } else if let Some(branch) = reference.strip_prefix("refs/heads/") {
+        GitReference::Branch(branch.to_string())
+    } else if let Some(tag) = reference.strip_prefix("refs/tags/") {
+        GitReference::Tag(tag.to_string())
+    } else if reference.starts_with("refs/") {
+        GitReference::Ref(reference.to_string())
+    } else {
+        GitReference::Branch(reference.to_string())
+    };
+    Some(reference)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_idf_source_references() {
+        let source = IdfSource::parse("git+https://gitlab.example.com/org/esp-idf@feature/usb").unwrap();
+        assert_eq!(source.url, "https://gitlab.example.com/org/esp-idf");
+        assert_eq!(source.reference, GitReference::Branch("feature/usb".to_string()));
+        assert_eq!(source.version_name(), "org-esp-idf-feature-usb-058070");
+
+        let source = IdfSource::parse("git+https://github.com/org/esp-idf.git@refs/pull/123/head").unwrap();
+        assert_eq!(source.url, "https://github.com/org/esp-idf.git");
+        assert_eq!(source.reference, GitReference::Ref("refs/pull/123/head".to_string()));
+        assert_eq!(source.version_name(), "org-esp-idf-pull-123-head-1b4334");
+
+        let sha = "0123456789ABCDEF0123456789abcdef01234567";
+        let source = IdfSource::parse(&format!("git+ssh://git@host/org/esp-idf@{}", sha)).unwrap();
+        assert_eq!(source.url, "ssh://git@host/org/esp-idf");
+        assert_eq!(source.reference, GitReference::Commit(sha.to_lowercase()));
+        assert_eq!(source.version_name(), "org-esp-idf-01234567-2caef8");
+
+        let source = IdfSource::parse("git+ssh://git@host/org/esp-idf").unwrap();
+        assert_eq!(source.reference, GitReference::None);
+        assert_eq!(source.version_name(), "org-esp-idf-fa6ec1");
+
+        let source = IdfSource::parse("git+https://host/esp-idf@refs/tags/v5.4-patched").unwrap();
+        assert_eq!(source.reference, GitReference::Tag("v5.4-patched".to_string()));
+        assert_eq!(source.version_name(), "esp-idf-v5.4-patched-b538f7");
+    }
+
+    #[test]
+    fn test_short_commit_ids_and_distinct_names() {
+        let source = IdfSource::parse("git+https://github.com/org/esp-idf@1A2B3C4").unwrap();
+        assert_eq!(source.reference, GitReference::Commit("1a2b3c4".to_string()));
+        // Too short to be a commit id
+        let source = IdfSource::parse("git+https://github.com/org/esp-idf@cafe").unwrap();
+        assert_eq!(source.reference, GitReference::Branch("cafe".to_string()));
+
+        let name = |spec: &str| IdfSource::parse(spec).unwrap().version_name();
+        // Forks building the same branch
+        assert_ne!(
+            name("git+https://github.com/alice/esp-idf@main"),
+            name("git+https://github.com/bob/esp-idf@main")
+        );
+        // The same path on another host
+        assert_ne!(
+            name("git+https://github.com/org/esp-idf@main"),
+            name("git+https://gitlab.com/org/esp-idf@main")
+        );
+        // Repositories on a case sensitive host
+        assert_ne!(
+            name("git+https://git.example.com/org/ESP-IDF@main"),
+            name("git+https://git.example.com/org/esp-idf@main")
+        );
+        // References spelled alike in the name
+        assert_ne!(
+            name("git+https://github.com/org/esp-idf@feature/usb"),
+            name("git+https://github.com/org/esp-idf@feature-usb")
+        );
+        // Spellings of the same repository
+        assert_eq!(
+            name("git+https://github.com/org/esp-idf.git@main"),
+            name("git+https://GitHub.com/org/esp-idf/@main")
+        );
+        assert_eq!(
+            name("git+https://github.com/org/esp-idf@main"),
+            name("git+https://github.com/org/esp-idf@refs/heads/main")
+        );
+        assert_eq!(installation_name("git+https://github.com/org/esp-idf"), "org-esp-idf-5d8065");
+        assert_eq!(installation_name("v5.4"), "v5.4");
+    }
+
+    #[test]
+    fn test_parse_idf_source_rejects_invalid_specs() {
+        assert!(IdfSource::parse("v5.4").is_err());
+        assert!(IdfSource::parse("git+host/org/esp-idf").is_err());
+        assert!(IdfSource::parse("git+ftp://host/org/esp-idf").is_err());
+        assert!(IdfSource::parse("git+https://host").is_err());
+        assert!(IdfSource::parse("git+https://host/org/esp-idf@").is_err());
+        assert!(IdfSource::parse("git+https://host/org/esp-idf@a..b").is_err());
+        assert!(is_idf_source("git+https://host/org/esp-idf"));
+        assert!(!is_idf_source("v5.4"));
+    }
+}
@@ -6,11 +6,26 @@ use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io::prelude::*;
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
 use anyhow::{anyhow, Result};
+use once_cell::sync::Lazy;
 
 use crate::command_executor::{execute_command, execute_command_with_env};
+use crate::download_cache::DownloadCache;
+use crate::tool_store::{EntryLock, ToolStore};
 use crate::{decompress_archive, download_file, verify_file_checksum, DownloadProgress};
-use crate::utils::{find_by_name_and_extension, find_directories_by_name, versions_match};
+use crate::utils::{find_by_name_and_extension, find_directories_by_name, versions_match, MirrorFailover};
+
+/// Number of tools `setup_tools` downloads at the same time unless configured otherwise.
+pub const DEFAULT_MAX_CONCURRENT_DOWNLOADS: usize = 4;
+
+/// Platform identifiers used by the download entries of tools.json.
+pub const PLATFORM_IDENTIFIERS: [&str; 9] = [
+    "win32", "win64", "macos", "macos-arm64",
+    "linux-i686", "linux-amd64", "linux-arm64", "linux-armel", "linux-armhf",
+];
+
+static HOST_PLATFORM_OVERRIDE: Lazy<RwLock<Option<String>>> = Lazy::new(|| RwLock::new(None));
 
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 pub struct Tool {
@@ -79,12 +94,16 @@ pub enum ToolStatus {
 /// # Arguments
 ///
 /// * `path` - A string slice representing the path to the tools file.
+/// * `overlay` - An optional tools overlay merged into the file, see [`apply_tools_overlay`].
 ///
 /// # Returns
 ///
 /// * `Result<ToolsFile, Box<dyn std::error::Error>>` - On success, returns a `ToolsFile` instance.
 ///   On error, returns a `Box<dyn std::error::Error>` containing the error details.
-pub fn read_and_parse_tools_file(path: &str) -> Result<ToolsFile, Box<dyn std::error::Error>> {
+pub fn read_and_parse_tools_file(
+    path: &str,
+    overlay: Option<&Path>,
+) -> Result<ToolsFile, Box<dyn std::error::Error>> {
     let path = Path::new(path);
     let mut file = File::open(path)?;
     let mut contents = String::new();
@@ -93,7 +112,50 @@ pub fn read_and_parse_tools_file(path: &str) -> Result<ToolsFile, Box<dyn std::e
     let tools_file: ToolsFile = serde_json::from_str(&contents)?;
     let platform = get_platform_identification()?;
 
-    Ok(apply_platform_overrides(tools_file, &platform))
+    apply_tools_overlay(apply_platform_overrides(tools_file, &platform), overlay)
+}
+
+/// Merges the tools overlay at `overlay` into `tools_file`, which must already have its platform
+/// overrides applied. Does nothing without an overlay.
+///
+/// The overlay is a file in the tools.json format listing tools that are not part of ESP-IDF,
+/// e.g. internal flashing or signing tools. A tool of the overlay replaces the tool of the same
+/// name, every other one is added. The merged tools are installed, exported and checked like the
+/// stock ones.
+pub fn apply_tools_overlay(
+    tools_file: ToolsFile,
+    overlay: Option<&Path>,
+) -> Result<ToolsFile, Box<dyn std::error::Error>> {
+    let Some(overlay) = overlay else {
+        return Ok(tools_file);
+    };
+    let contents = std::fs::read_to_string(overlay)
+        .map_err(|e| format!("Failed to read the tools overlay {}: {}", overlay.display(), e))?;
+    let overlay_file: ToolsFile = serde_json::from_str(&contents)
+        .map_err(|e| format!("Failed to parse the tools overlay {}: {}", overlay.display(), e))?;
+    let platform = get_platform_identification()?;
+    Ok(merge_tools_overlay(
+        tools_file,
+        apply_platform_overrides(overlay_file, &platform),
+    ))
+}
+
+/// Returns `tools_file` with each tool of `overlay` replacing the tool of the same name, or
+/// appended if there is none.
+pub fn merge_tools_overlay(mut tools_file: ToolsFile, overlay: ToolsFile) -> ToolsFile {
+    for tool in overlay.tools {
+        match tools_file.tools.iter_mut().find(|t| t.name == tool.name) {
+            Some(stock) => {
+                debug!("Tools overlay replaces {}", tool.name);
+                *stock = tool;
+            }
+            None => {
+                debug!("Tools overlay adds {}", tool.name);
+                tools_file.tools.push(tool);
+            }
+        }
+    }
+    tools_file
 }
 
 /// Applies platform-specific overrides to the tools within a `ToolsFile`.
@@ -285,6 +347,51 @@ pub fn filter_tools_by_target(tools: Vec<Tool>, target: &[String]) -> Vec<Tool>
         .collect()
 }
 
+/// Returns `tools_file` with the version chosen in `versions` (tool name to version) marked as the
+/// recommended one of each tool, so that everything installing or checking the recommended
+/// version uses it instead. The previously recommended version becomes `supported`. Versions
+/// tools.json does not list are ignored, [`check_tool_pins`] rejects such pins beforehand.
+pub fn apply_tool_versions(mut tools_file: ToolsFile, versions: &HashMap<String, String>) -> ToolsFile {
+    for tool in tools_file.tools.iter_mut() {
+        let Some(chosen) = versions.get(&tool.name) else {
+            continue;
+        };
+        if !tool.versions.iter().any(|v| &v.name == chosen) {
+            log::warn!("Tool '{}' has no version {} in tools.json, keeping the recommended one", tool.name, chosen);
+            continue;
+        }
+        for version in tool.versions.iter_mut() {
+            if &version.name == chosen {
+                version.status = "recommended".to_string();
+            } else if version.status == "recommended" {
+                version.status = "supported".to_string();
+            }
+        }
+    }
+    tools_file
+}
+
+/// Fails if one of `pins` (tool name to version) names a version tools.json does not list for
+/// that tool, with the versions it does list. Pins of tools tools.json does not contain are
+/// left to [`apply_tool_versions`], which ignores them.
+pub fn check_tool_pins(tools_file: &ToolsFile, pins: &HashMap<String, String>) -> Result<(), String> {
+    for tool in &tools_file.tools {
+        let Some(pinned) = pins.get(&tool.name) else {
+            continue;
+        };
+        if !tool.versions.iter().any(|v| &v.name == pinned) {
+            let available: Vec<&str> = tool.versions.iter().map(|v| v.name.as_str()).collect();
+            return Err(format!(
+                "Tool '{}' can not be pinned to {}, tools.json lists: {}",
+                tool.name,
+                pinned,
+                available.join(", ")
+            ));
+        }
+    }
+    Ok(())
+}
+
 /// Returns a standardized platform identifier string based on the current system.
 ///
 /// This function maps the system's OS and architecture to a more common and standardized
@@ -318,7 +425,17 @@ pub fn filter_tools_by_target(tools: Vec<Tool>, target: &[String]) -> Vec<Tool>
 /// A `Result` which is:
 /// - `Ok(String)`: A `String` representing the standardized platform identifier.
 /// - `Err(String)`: An error message if the platform is unsupported.
+///
+/// When a host platform was set with [`set_host_platform`], that platform is returned instead.
 pub fn get_platform_identification() -> Result<String, String> {
+    if let Some(platform) = host_platform_override() {
+        return Ok(platform);
+    }
+    detect_platform_identification()
+}
+
+/// Identifies the platform eim is actually running on, ignoring [`set_host_platform`].
+pub fn detect_platform_identification() -> Result<String, String> {
     let mut platform_from_name = HashMap::new();
 
     // Windows identifiers
@@ -350,6 +467,54 @@ pub fn get_platform_identification() -> Result<String, String> {
     Ok(platform.to_string())
 }
 
+/// Validates a platform identifier given by the user, e.g. with `--host-platform`.
+pub fn parse_host_platform(platform: &str) -> Result<String, String> {
+    let platform = platform.trim();
+    if PLATFORM_IDENTIFIERS.contains(&platform) {
+        Ok(platform.to_string())
+    } else {
+        Err(format!(
+            "Unsupported host platform '{}', expected one of: {}",
+            platform,
+            PLATFORM_IDENTIFIERS.join(", ")
+        ))
+    }
+}
+
+/// Makes the installer select, download and extract tools for `platform` instead of the running
+/// host, e.g. to prepare a tools directory or offline archive for `linux-arm64` on an x86_64 CI
+/// machine. `None` goes back to detecting the host.
+///
+/// The override is process wide, it changes [`get_platform_identification`] and with it the
+/// platform overrides and download links taken from tools.json. Tools prepared for another
+/// platform cannot be run here, see [`is_foreign_host_platform`].
+pub fn set_host_platform(platform: Option<&str>) -> Result<(), String> {
+    let platform = platform.map(parse_host_platform).transpose()?;
+    if let Some(platform) = &platform {
+        log::info!("Preparing tools for host platform {}", platform);
+    }
+    *HOST_PLATFORM_OVERRIDE.write().unwrap_or_else(|e| e.into_inner()) = platform;
+    Ok(())
+}
+
+/// Returns the platform set with [`set_host_platform`], if any.
+pub fn host_platform_override() -> Option<String> {
+    HOST_PLATFORM_OVERRIDE
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone()
+}
+
+/// Returns true when tools are prepared for a platform other than the running host. Nothing that
+/// executes the downloaded tools (version checks, Python environments, post install steps) may
+/// run then.
+pub fn is_foreign_host_platform() -> bool {
+    match host_platform_override() {
+        Some(platform) => detect_platform_identification().map_or(true, |host| host != platform),
+        None => false,
+    }
+}
+
 /// Returns a string representing the current platform in the format "os-arch".
 ///
 /// This function retrieves the operating system and architecture information
@@ -727,10 +892,11 @@ pub fn get_tools_export_vars_from_list(
 ) -> Vec<(String, String)> {
     let mut vars: Vec<(String, String)> = Vec::new();
 
-    for (tool_name, (_version, _download)) in installed_tools {
+    for (tool_name, (version, _download)) in installed_tools {
         if let Some(tool) = _tools_file.tools.iter().find(|t| t.name == tool_name) {
             for (var_name, var_value) in &tool.export_vars {
-                let single_tool_install_path = PathBuf::from(tools_install_path).join(tool.name.clone()).join(tool.versions.first().unwrap().name.clone());
+                // The installed version, which differs from the first listed one for pinned tools
+                let single_tool_install_path = PathBuf::from(tools_install_path).join(tool.name.clone()).join(&version);
                 let processed_value = var_value.replace("${TOOL_PATH}", &single_tool_install_path.to_string_lossy());
                 vars.push((var_name.clone(), processed_value));
             }
@@ -755,24 +921,87 @@ pub fn find_bin_directories(path: &Path) -> Vec<String> {
     find_directories_by_name(path, "bin")
 }
 
+/// Options of [`setup_tools`] that apply to all the tools it sets up.
+#[derive(Debug, Clone)]
+pub struct ToolSetupOptions {
+    /// Mirror URL to use for downloads. If `Some`, download URLs are adjusted to use this mirror.
+    pub mirror: Option<String>,
+    /// Mirrors to move on to when a download fails on `mirror` or the archive does not match its
+    /// checksum.
+    pub mirror_failover: Option<MirrorFailover>,
+    /// Maximum number of tools downloaded and extracted at the same time. Values lower than 1 are
+    /// treated as 1.
+    pub max_concurrent_downloads: usize,
+    /// Shared cache of archives keyed by their sha256. It is pruned to its size limit once all tools
+    /// are processed.
+    pub download_cache: Option<DownloadCache>,
+    /// Shared store of extracted tools. When given, tools are extracted into the store once and
+    /// `install_dir/<tool_name>/<version>` links to the store entry. The store must have an owner
+    /// set (see `ToolStore::with_owner`).
+    pub tool_store: Option<ToolStore>,
+    /// Extract tar archives while they are downloaded.
+    pub stream_extraction: bool,
+}
+
+impl Default for ToolSetupOptions {
+    fn default() -> Self {
+      Self {
+        mirror: None,
+        mirror_failover: None,
+        max_concurrent_downloads: DEFAULT_MAX_CONCURRENT_DOWNLOADS,
+        download_cache: None,
+        tool_store: None,
+        stream_extraction: false,
+      }
+    }
+}
+
+impl ToolSetupOptions {
+    /// The options configured in `settings` for the installation at `idf_path`, which owns the
+    /// links into the tool store. Downloads use the configured mirror and fail over to the known
+    /// IDF tools mirrors.
+    pub fn from_settings(settings: &crate::settings::Settings, idf_path: impl Into<String>) -> Self {
+      let mirror = settings.mirror.clone();
+      Self {
+        mirror_failover: Some(MirrorFailover::new(mirror.as_deref(), crate::get_idf_tools_mirrors_list())),
+        mirror,
+        max_concurrent_downloads: settings
+          .max_concurrent_downloads
+          .unwrap_or(DEFAULT_MAX_CONCURRENT_DOWNLOADS),
+        download_cache: DownloadCache::from_settings(settings),
+        tool_store: ToolStore::from_settings(settings).map(|store| store.with_owner(idf_path)),
+        stream_extraction: settings.stream_tool_extraction.unwrap_or(false),
+      }
+    }
+}
+
 /// Sets up (downloads and installs) a list of selected tools based on their definitions.
 ///
 /// This asynchronous function orchestrates the entire setup process for a given set of tools.
-/// It first determines which tools need to be downloaded, then iteratively processes each tool:
+/// It first determines which tools need to be downloaded, then processes the tools concurrently,
+/// with at most `max_concurrent_downloads` of them in flight at any time. Each tool goes through:
 ///
 /// 1. **Verifies Installation Status**: Checks if the tool is already installed correctly,
 ///    if a different version is present, or if it's missing. If already correct, it skips
 ///    the download and installation.
-/// 2. **Handles Existing Downloads**: If a tool's archive already exists in the download
-///    directory and passes checksum verification, it skips the download phase.
+/// 2. **Handles Existing Downloads**: If the archive is already in the `download_cache`, or it
+///    exists in the download directory and passes checksum verification, it skips the download phase.
 /// 3. **Downloads Tools**: Downloads the tool's archive to the specified download directory,
 ///    providing progress updates via the `progress_callback`.
 /// 4. **Verifies Checksum**: After download, it verifies the integrity of the downloaded file
-///    using its SHA256 checksum. Corrupted files are removed.
+///    using its SHA256 checksum. Corrupted files are removed, verified ones are moved into the
+///    `download_cache` when one is given. When the download fails or the file is corrupted,
+///    the next mirror of `mirror_failover` is tried.
 /// 5. **Extracts Archives**: Decompresses the downloaded archive into the appropriate
 ///    installation directory, structured by tool name and version.
 ///
+/// Every tool works with its own archive and its own installation directory, so a failing tool
+/// never touches the files of the others. A failure does not cancel the remaining tools either;
+/// all failures are collected and reported together once every tool has been processed.
+///
 /// Progress updates throughout these stages are communicated via the `progress_callback`.
+/// Byte-level download progress is reported as `DownloadProgress::ToolProgress` so that
+/// concurrent downloads can be told apart by their URL.
 ///
 /// # Arguments
 ///
@@ -780,8 +1009,7 @@ pub fn find_bin_directories(path: &Path) -> Vec<String> {
 /// * `selected_targets` - A `Vec` of strings, representing the names of the tools to be set up.
 /// * `download_dir` - A `PathBuf` indicating the directory where tool archives should be downloaded.
 /// * `install_dir` - A `PathBuf` indicating the base directory where tools should be installed.
-/// * `mirror` - An `Option<&str>` specifying an optional mirror URL to use for downloads.
-///              If `Some`, download URLs will be adjusted to use this mirror.
+/// * `options` - Mirrors, concurrency, download cache and tool store to use, see [`ToolSetupOptions`].
 /// * `progress_callback` - A closure that implements `Fn(DownloadProgress) + Clone + Send + 'static`.
 ///                         This callback is invoked to report the progress and status of downloads
 ///                         and installations.
@@ -789,10 +1017,11 @@ pub fn find_bin_directories(path: &Path) -> Vec<String> {
 /// # Returns
 ///
 /// A `Result` which is:
-/// * `Ok(HashMap<String, (String, Download)>)` - A `HashMap` containing the names of the tools
-///   that were processed, mapped to a tuple of their preferred version (String) and the
-///   `Download` information used for that version.
-/// * `Err(anyhow::Error)` - An error if any critical step during the setup process fails
+/// * `Ok((HashMap<String, (String, Download)>, HashMap<String, ToolSource>))` - The names of the
+///   tools that were processed, mapped to a tuple of their preferred version (String) and the
+///   `Download` information used for that version, and where each of them was taken from. For
+///   downloaded tools, the URL is the one of the mirror that finally served the archive.
+/// * `Err(anyhow::Error)` - An error listing every tool whose setup failed
 ///   (e.g., download failure, checksum mismatch, extraction error).
 ///
 pub async fn setup_tools(
@@ -800,117 +1029,457 @@ pub async fn setup_tools(
     selected_targets: Vec<String>,
     download_dir: &PathBuf,
     install_dir: &PathBuf,
-    mirror: Option<&str>,
+    options: &ToolSetupOptions,
     progress_callback: impl Fn(DownloadProgress) + Clone + Send + 'static,
-) -> anyhow::Result<HashMap<String, (String, Download)>> {
-
-    let download_links = get_list_of_tools_to_download(tools.clone(), selected_targets, mirror);
-    // Download each tool
-    for (tool_name, (version, download_link)) in download_links.iter() {
-      let file_path = Path::new(&download_link.url);
-      let filename = file_path.file_name()
-          .ok_or_else(|| anyhow::anyhow!("Invalid filename in URL"))?
-          .to_str()
-          .ok_or_else(|| anyhow::anyhow!("Invalid UTF-8 in filename"))?;
-
-      let full_file_path = download_dir.join(filename);
-      let this_install_dir = install_dir.join(tool_name).join(version);
-
-      match verify_tool_installation(tool_name, tools, install_dir, version) {
-        Ok(ToolStatus::Correct { version }) => {
-          progress_callback(DownloadProgress::Verified(download_link.url.clone()));
-          progress_callback(DownloadProgress::Complete);
-          log::info!("Tool '{}' is already installed with the correct version: {}", tool_name, version);
-          continue; // Skip if already installed correctly
-        }
-        Ok(ToolStatus::DifferentVersion { installed, expected }) => {
-          // todo: install to different folder
-          log::warn!("Tool '{}' is installed with version '{}', but expected '{}'. Reinstalling...", tool_name, installed, expected);
+) -> anyhow::Result<(HashMap<String, (String, Download)>, HashMap<String, ToolSource>)> {
+
+    let mut download_links = get_list_of_tools_to_download(tools.clone(), selected_targets, options.mirror.as_deref());
+    // The part of the download URLs to swap when failing over to another mirror
+    let mirror_base = options.mirror.clone().unwrap_or_else(|| "https://github.com".to_string());
+    let concurrency = options.max_concurrent_downloads.max(1);
+    log::info!(
+      "Setting up {} tools with up to {} concurrent downloads",
+      download_links.len(),
+      concurrency
+    );
+
+    let semaphore = Arc::new(tokio::sync::Semaphore::new(concurrency));
+    let shared_tools = Arc::new(tools.clone());
+    let mut tasks = tokio::task::JoinSet::new();
+
+    for (tool_name, (version, download_link)) in download_links.clone() {
+      let semaphore = semaphore.clone();
+      let tools = shared_tools.clone();
+      let download_dir = download_dir.clone();
+      let install_dir = install_dir.clone();
+      let options = options.clone();
+      let mirror_base = mirror_base.clone();
+      let callback = progress_callback.clone();
+
+      tasks.spawn(async move {
+        let result = match semaphore.acquire_owned().await {
+          Ok(_permit) => {
+            setup_single_tool(&tools, &tool_name, &version, &download_link, &download_dir, &install_dir, &mirror_base, &options, callback).await
+          }
+          Err(e) => Err(anyhow!("Download queue closed: {}", e)),
+        };
+        (tool_name, result)
+      });
+    }
+
+    let mut failures = Vec::new();
+    let mut sources = HashMap::new();
+    while let Some(joined) = tasks.join_next().await {
+      match joined {
+        Ok((tool_name, Ok(source))) => {
+          if let ToolSource::Downloaded(served_url) = &source {
+            if let Some((_, download)) = download_links.get_mut(&tool_name) {
+              download.url = served_url.clone();
+            }
+          }
+          sources.insert(tool_name, source);
         }
-        Ok(ToolStatus::Missing) => {
-          log::info!("Tool '{}' is not installed. Downloading...", tool_name);
+        Ok((tool_name, Err(e))) => {
+          log::error!("Failed to set up tool '{}': {}", tool_name, e);
+          failures.push(format!("{}: {}", tool_name, e));
         }
         Err(e) => {
-          log::error!("Error verifying tool '{}': {}", tool_name, e);
-          return Err(anyhow::anyhow!("Error verifying tool '{}': {}", tool_name, e));
+          log::error!("Tool setup task failed: {}", e);
+          failures.push(e.to_string());
         }
       }
+    }
 
+    if let Some(cache) = &options.download_cache {
+      cache.enforce_limit();
+    }
 
+    if !failures.is_empty() {
+      failures.sort();
+      return Err(anyhow!(
+        "Failed to set up {} tool(s): {}",
+        failures.len(),
+        failures.join("; ")
+      ));
+    }
 
+    Ok((download_links, sources))
+}
 
-      // Notify start of processing this tool
-      progress_callback(DownloadProgress::Start(download_link.url.clone()));
+/// Where [`setup_tools`] took a tool from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ToolSource {
+    /// Downloaded from this URL, on the mirror that finally served it.
+    Downloaded(String),
+    /// Extracted from this archive in the download cache.
+    DownloadCache(PathBuf),
+    /// Extracted from this archive left in the download folder by an earlier installation.
+    DownloadFolder(PathBuf),
+    /// Linked from the tool store.
+    ToolStore,
+    /// Already installed with the right version.
+    AlreadyInstalled,
+}
 
-      // Check if file already exists and has correct checksum
-      if let Ok(true) = verify_file_checksum(&download_link.sha256, full_file_path.to_str().unwrap()) {
+/// Downloads, verifies and extracts a single tool.
+///
+/// Used by `setup_tools` for each of the tools it processes. Returns where the tool was taken
+/// from, for downloads the URL the archive was finally downloaded from. Everything this function writes
+/// is confined to the tool's own archive in `download_dir`, to `install_dir/<tool_name>/<version>`
+/// and to the tool's own entry in the tool store, which keeps concurrently running setups independent.
+/// With `options.stream_extraction`, tar archives are extracted while they are downloaded and are neither
+/// written to `download_dir` nor stored in the download cache.
+async fn setup_single_tool(
+    tools: &ToolsFile,
+    tool_name: &str,
+    version: &str,
+    download_link: &Download,
+    download_dir: &PathBuf,
+    install_dir: &PathBuf,
+    mirror_base: &str,
+    options: &ToolSetupOptions,
+    progress_callback: impl Fn(DownloadProgress) + Clone + Send + 'static,
+) -> Result<ToolSource> {
+    let download_cache = options.download_cache.as_ref();
+    let tool_store = options.tool_store.as_ref();
+    let file_path = Path::new(&download_link.url);
+    let filename = file_path.file_name()
+        .ok_or_else(|| anyhow::anyhow!("Invalid filename in URL"))?
+        .to_str()
+        .ok_or_else(|| anyhow::anyhow!("Invalid UTF-8 in filename"))?;
+
+    let full_file_path = download_dir.join(filename);
+    let this_install_dir = install_dir.join(tool_name).join(version);
+    let platform = get_platform_identification().unwrap_or_else(|_| std::env::consts::OS.to_string());
+
+    let mut changed_files = ChangedFiles::default();
+
+    // Tools already in the store only need to be linked, unless their files were changed
+    if let Some(store) = tool_store.filter(|store| store.can_link(&this_install_dir)) {
+      let _entry_lock = lock_store_entry(store, tool_name, version, &platform).await?;
+      if store.is_complete(tool_name, version, &platform)
+        && !changed_files.check(tool_name, &store.entry_dir(tool_name, version, &platform))
+      {
+        store.link(tool_name, version, &platform, &this_install_dir)?;
+        log::info!("Tool '{}' {} linked from the tool store", tool_name, version);
         progress_callback(DownloadProgress::Verified(download_link.url.clone()));
-        decompress_archive(full_file_path.to_str().unwrap(), this_install_dir.to_str().unwrap())?;
+        progress_callback(DownloadProgress::Extracted(download_link.url.clone(), this_install_dir.to_str().unwrap().to_string()));
+        progress_callback(DownloadProgress::Complete);
+        return Ok(ToolSource::ToolStore);
+      }
+    }
 
-        // Post-extraction operations
-        if let Some(tool) = tools.tools.iter().find(|t| &t.name == tool_name) {
-          post_extract_operations(tool_name, tool, &this_install_dir)?;
-        }
+    // A tool whose files were changed is extracted again, even if it still reports its version
+    if this_install_dir.exists() && changed_files.check(tool_name, &this_install_dir) {
+      let removed = match std::fs::symlink_metadata(&this_install_dir) {
+        Ok(metadata) if metadata.file_type().is_symlink() => std::fs::remove_file(&this_install_dir)
+          .or_else(|_| std::fs::remove_dir(&this_install_dir)),
+        _ => std::fs::remove_dir_all(&this_install_dir),
+      };
+      removed.map_err(|e| anyhow!("Failed to remove the changed tool '{}': {}", tool_name, e))?;
+    }
 
-        progress_callback(DownloadProgress::Extracted(download_link.url.clone(), this_install_dir.to_str().unwrap().to_string()));
+    match verify_tool_installation(tool_name, tools, install_dir, version) {
+      Ok(ToolStatus::Correct { version }) => {
+        progress_callback(DownloadProgress::Verified(download_link.url.clone()));
         progress_callback(DownloadProgress::Complete);
-        continue;
+        log::info!("Tool '{}' is already installed with the correct version: {}", tool_name, version);
+        return Ok(ToolSource::AlreadyInstalled); // Skip if already installed correctly
+      }
+      Ok(ToolStatus::DifferentVersion { installed, expected }) => {
+        // todo: install to different folder
+        log::warn!("Tool '{}' is installed with version '{}', but expected '{}'. Reinstalling...", tool_name, installed, expected);
+      }
+      Ok(ToolStatus::Missing) => {
+        log::info!("Tool '{}' is not installed. Downloading...", tool_name);
       }
+      Err(e) => {
+        log::error!("Error verifying tool '{}': {}", tool_name, e);
+        return Err(anyhow::anyhow!("Error verifying tool '{}': {}", tool_name, e));
+      }
+    }
 
-      // Create a channel for progress updates
-      let (tx, rx) = std::sync::mpsc::channel();
+    let tool = tools.tools.iter().find(|t| t.name == tool_name).cloned();
 
-      // Spawn a thread to forward progress updates to the callback
-      let callback = progress_callback.clone();
-      let url = download_link.url.clone();
-      std::thread::spawn(move || {
-        while let Ok(progress) = rx.recv() {
-          match progress {
-            DownloadProgress::Progress(current, total) => {
-              callback(DownloadProgress::Progress(current, total));
-            }
-            DownloadProgress::Complete => {
-              callback(DownloadProgress::Downloaded(url.clone()));
-            }
-            DownloadProgress::Error(e) => {
-              callback(DownloadProgress::Error(e));
-            }
-            _ => {}
-          }
-        }
-      });
+    // Notify start of processing this tool
+    progress_callback(DownloadProgress::Start(download_link.url.clone()));
 
-      // Download the file
-      match download_file(&download_link.url, download_dir.to_str().unwrap(), Some(tx)).await {
-        Ok(_) => {
-          // Verify downloaded file
-          if verify_file_checksum(&download_link.sha256, full_file_path.to_str().unwrap())? {
-            progress_callback(DownloadProgress::Verified(download_link.url.clone()));
-            // Extract the archive
+    // Archives in the cache were verified when they were stored
+    if let Some(cached) = download_cache.and_then(|cache| cache.lookup(&download_link.sha256)) {
+      log::info!("Using cached archive for '{}': {}", tool_name, cached.display());
+      progress_callback(DownloadProgress::Verified(download_link.url.clone()));
+      install_tool_archive(tool_name, version, &platform, tool, ArchiveSource::File(&cached), &this_install_dir, tool_store).await?;
 
-            decompress_archive(full_file_path.to_str().unwrap(), this_install_dir.to_str().unwrap())?;
+      progress_callback(DownloadProgress::Extracted(download_link.url.clone(), this_install_dir.to_str().unwrap().to_string()));
+      progress_callback(DownloadProgress::Complete);
+      return Ok(ToolSource::DownloadCache(cached));
+    }
 
-            // Post-extraction operations
-            if let Some(tool) = tools.tools.iter().find(|t| &t.name == tool_name) {
-              post_extract_operations(tool_name, tool, &this_install_dir)?;
-            }
+    // Check if file already exists and has correct checksum
+    if let Ok(true) = verify_file_checksum(&download_link.sha256, full_file_path.to_str().unwrap()) {
+      progress_callback(DownloadProgress::Verified(download_link.url.clone()));
+      let archive_path = store_in_cache(download_cache, &download_link.sha256, &full_file_path);
+      install_tool_archive(tool_name, version, &platform, tool, ArchiveSource::File(&archive_path), &this_install_dir, tool_store).await?;
 
-            progress_callback(DownloadProgress::Extracted(download_link.url.clone(), this_install_dir.to_str().unwrap().to_string()));
-            progress_callback(DownloadProgress::Complete);
-          } else {
-            // Remove corrupted file
-            std::fs::remove_file(&full_file_path)?;
-            return Err(anyhow::anyhow!("Downloaded file is corrupted"));
+      progress_callback(DownloadProgress::Extracted(download_link.url.clone(), this_install_dir.to_str().unwrap().to_string()));
+      progress_callback(DownloadProgress::Complete);
+      return Ok(ToolSource::DownloadFolder(full_file_path));
+    }
+
+    // Download the file, moving on to the next mirror when the current one fails or serves a
+    // corrupted archive
+    let stream = options.stream_extraction && crate::stream_extract::supports_streaming(filename);
+    let mut url = download_link.url.clone();
+    let mut tried = vec![mirror_base.to_string()];
+    loop {
+      let attempt = if stream {
+        let source = ArchiveSource::Download {
+          url: &url,
+          sha256: &download_link.sha256,
+          progress: forward_progress(&url, progress_callback.clone()),
+        };
+        install_tool_archive(tool_name, version, &platform, tool.clone(), source, &this_install_dir, tool_store).await
+      } else {
+        download_and_verify(&url, &download_link.sha256, download_dir, &full_file_path, progress_callback.clone()).await
+      };
+      let error = match attempt {
+        Ok(()) => break,
+        Err(e) => e,
+      };
+      let next_mirror = match &options.mirror_failover {
+        Some(failover) if download_link.url.starts_with(mirror_base) => failover.next_mirror(&tried).await,
+        _ => None,
+      };
+      let Some(next_mirror) = next_mirror else {
+        progress_callback(DownloadProgress::Error(format!("{}: {}", tool_name, error)));
+        return Err(error);
+      };
+      let next_url = download_link.url.replacen(mirror_base, &next_mirror, 1);
+      log::warn!("Failed to download '{}' from {}: {}. Trying mirror {}", tool_name, url, error, next_mirror);
+      tried.push(next_mirror);
+      url = next_url;
+      progress_callback(DownloadProgress::Start(url.clone()));
+    }
+    log::info!("Tool '{}' downloaded from {}", tool_name, url);
+
+    progress_callback(DownloadProgress::Verified(url.clone()));
+    if !stream {
+      let archive_path = store_in_cache(download_cache, &download_link.sha256, &full_file_path);
+      install_tool_archive(tool_name, version, &platform, tool, ArchiveSource::File(&archive_path), &this_install_dir, tool_store).await?;
+    }
+
+    progress_callback(DownloadProgress::Extracted(url.clone(), this_install_dir.to_str().unwrap().to_string()));
+    progress_callback(DownloadProgress::Complete);
+    Ok(ToolSource::Downloaded(url))
+}
+
+/// Returns true if files recorded in the manifest of `tool_dir` were modified or removed.
+///
+/// Runs on every installation, so files whose size and modification time are unchanged are
+/// not hashed; `verify` and `fix` do the full check.
+fn has_changed_files(tool_name: &str, tool_dir: &Path) -> bool {
+    match crate::tool_manifest::check_dir_quick(tool_dir) {
+      Some(changes) if !changes.is_empty() => {
+        log::warn!(
+          "{} files of tool '{}' in {} were modified or removed, extracting it again",
+          changes.len(),
+          tool_name,
+          tool_dir.display()
+        );
+        true
+      }
+      _ => false,
+    }
+}
+
+/// Results of [`has_changed_files`] per physical directory, so a tool linked from the tool store
+/// is not checked a second time through its link.
+#[derive(Default)]
+struct ChangedFiles(HashMap<PathBuf, bool>);
+
+impl ChangedFiles {
+    fn check(&mut self, tool_name: &str, tool_dir: &Path) -> bool {
+      let key = std::fs::canonicalize(tool_dir).unwrap_or_else(|_| tool_dir.to_path_buf());
+      *self.0.entry(key).or_insert_with(|| has_changed_files(tool_name, tool_dir))
+    }
+}
+
+/// Downloads `url` into `download_dir` and verifies the archive at `full_file_path` against
+/// `sha256`. A corrupted archive is removed.
+async fn download_and_verify(
+    url: &str,
+    sha256: &str,
+    download_dir: &Path,
+    full_file_path: &Path,
+    progress_callback: impl Fn(DownloadProgress) + Clone + Send + 'static,
+) -> Result<()> {
+    let tx = forward_progress(url, progress_callback);
+    download_file(url, download_dir.to_str().unwrap(), Some(tx))
+      .await
+      .map_err(|e| anyhow::anyhow!("Download failed: {}", e))?;
+
+    if verify_file_checksum(sha256, full_file_path.to_str().unwrap())? {
+      Ok(())
+    } else {
+      // Remove corrupted file
+      std::fs::remove_file(full_file_path)?;
+      Err(anyhow::anyhow!("Downloaded file is corrupted"))
+    }
+}
+
+/// Returns a sender whose download progress is forwarded to the callback, tagged with `url` so
+/// that concurrent downloads can be told apart.
+fn forward_progress(
+    url: &str,
+    progress_callback: impl Fn(DownloadProgress) + Send + 'static,
+) -> std::sync::mpsc::Sender<DownloadProgress> {
+    let (tx, rx) = std::sync::mpsc::channel();
+    let forwarded_url = url.to_string();
+    std::thread::spawn(move || {
+      while let Ok(progress) = rx.recv() {
+        match progress {
+          DownloadProgress::Progress(current, total) => {
+            progress_callback(DownloadProgress::ToolProgress(forwarded_url.clone(), current, total));
+          }
+          DownloadProgress::Complete => {
+            progress_callback(DownloadProgress::Downloaded(forwarded_url.clone()));
           }
+          // Errors are reported by setup_single_tool once no mirror is left to try
+          _ => {}
         }
+      }
+    });
+    tx
+}
+
+/// Where `install_tool_archive` takes a tool archive from.
+enum ArchiveSource<'a> {
+    /// A verified archive on disk.
+    File(&'a Path),
+    /// An archive extracted while it is downloaded, verified against `sha256` at the end.
+    Download {
+      url: &'a str,
+      sha256: &'a str,
+      progress: std::sync::mpsc::Sender<DownloadProgress>,
+    },
+}
+
+/// Moves a verified archive into the download cache and returns the path to extract it from.
+///
+/// Falls back to the original file when there is no cache or storing it fails.
+fn store_in_cache(download_cache: Option<&DownloadCache>, sha256: &str, archive_path: &Path) -> PathBuf {
+    match download_cache {
+      Some(cache) => match cache.insert(sha256, archive_path) {
+        Ok(cached) => cached,
         Err(e) => {
-          progress_callback(DownloadProgress::Error(e.to_string()));
-          return Err(anyhow::anyhow!("Download failed: {}", e));
+          log::warn!("Failed to store {} in the download cache: {}", archive_path.display(), e);
+          archive_path.to_path_buf()
         }
-      }
+      },
+      None => archive_path.to_path_buf(),
+    }
+}
+
+/// Installs a tool archive into `this_install_dir`.
+///
+/// Without a tool store the archive is extracted there directly. With a store it is extracted into
+/// the store entry of the tool, which is then linked to `this_install_dir`.
+async fn install_tool_archive(
+    tool_name: &str,
+    version: &str,
+    platform: &str,
+    tool: Option<Tool>,
+    source: ArchiveSource<'_>,
+    this_install_dir: &Path,
+    tool_store: Option<&ToolStore>,
+) -> Result<()> {
+    let Some(store) = tool_store else {
+      return extract_tool_archive(tool_name, tool, source, this_install_dir).await;
+    };
+    if !store.can_link(this_install_dir) {
+      log::warn!(
+        "{} is not a tool store link, extracting '{}' there instead of into the tool store",
+        this_install_dir.display(),
+        tool_name
+      );
+      return extract_tool_archive(tool_name, tool, source, this_install_dir).await;
     }
 
-    Ok(download_links)
+    // Held until the entry is linked, so no other installation deletes or re-extracts it meanwhile
+    let _entry_lock = lock_store_entry(store, tool_name, version, platform).await?;
+    if store.is_complete(tool_name, version, platform)
+      && !has_changed_files(tool_name, &store.entry_dir(tool_name, version, platform))
+    {
+      // Another process extracted it while this one was waiting for the lock
+      store.link(tool_name, version, platform, this_install_dir)?;
+      log::info!("Tool '{}' {} linked from the tool store", tool_name, version);
+      return Ok(());
+    }
+    let entry_dir = store.prepare_entry(tool_name, version, platform)?;
+    extract_tool_archive(tool_name, tool, source, &entry_dir).await?;
+    store.mark_complete(tool_name, version, platform)?;
+    store.link(tool_name, version, platform, this_install_dir)?;
+    log::info!("Tool '{}' {} added to the tool store at {}", tool_name, version, entry_dir.display());
+    Ok(())
+}
+
+/// Waits for the lock on a tool store entry on a blocking thread, another process may hold it
+/// while it extracts the tool.
+async fn lock_store_entry(store: &ToolStore, tool_name: &str, version: &str, platform: &str) -> Result<EntryLock> {
+    let (locking_store, name, locked_version, locked_platform) =
+      (store.clone(), tool_name.to_string(), version.to_string(), platform.to_string());
+    tokio::task::spawn_blocking(move || locking_store.lock_entry(&name, &locked_version, &locked_platform))
+      .await?
+      .map_err(|e| anyhow!("Failed to lock the tool store entry of '{}' {}: {}", tool_name, version, e))
+}
+
+/// Extracts a tool archive and runs the post-extraction operations on a blocking thread.
+///
+/// If anything fails, including the checksum of a streamed archive, the partially extracted
+/// installation directory is removed so that a broken tool is never mistaken for an installed one.
+async fn extract_tool_archive(
+    tool_name: &str,
+    tool: Option<Tool>,
+    source: ArchiveSource<'_>,
+    this_install_dir: &Path,
+) -> Result<()> {
+    let tool_name = tool_name.to_string();
+    let this_install_dir = this_install_dir.to_path_buf();
+    let target_dir = this_install_dir.clone();
+
+    let archive_path = match source {
+      ArchiveSource::File(path) => Some(path.to_path_buf()),
+      ArchiveSource::Download { url, sha256, progress } => {
+        crate::stream_extract::download_and_extract(url, sha256, &this_install_dir, Some(progress)).await?;
+        None
+      }
+    };
+
+    let result = tokio::task::spawn_blocking(move || -> Result<()> {
+      if let Some(archive_path) = archive_path {
+        decompress_archive(archive_path.to_str().unwrap(), target_dir.to_str().unwrap())?;
+      }
+
+      // Post-extraction operations
+      if let Some(tool) = tool {
+        post_extract_operations(&tool_name, &tool, &target_dir)?;
+      }
+      // Without a manifest the tool still works, it just can not be checked for changed files
+      if let Err(e) = crate::tool_manifest::write(&target_dir) {
+        log::warn!("Failed to write the file manifest of '{}': {}", tool_name, e);
+      }
+      Ok(())
+    })
+    .await
+    .map_err(|e| anyhow!("Extraction task failed: {}", e))
+    .and_then(|r| r);
+
+    if result.is_err() && this_install_dir.exists() {
+      if let Err(e) = std::fs::remove_dir_all(&this_install_dir) {
+        log::warn!("Failed to clean up partially extracted tool in {}: {}", this_install_dir.display(), e);
+      }
+    }
+    result
 }
 
 /// Performs post-extraction operations: stripping container directories and setting permissions.
@@ -1035,7 +1604,8 @@ pub fn verify_tool_installation(tool_name: &str, tools_file: &ToolsFile, install
         }
     }
 
-    if tool.version_cmd.is_empty() || tool.version_cmd[0].is_empty() {
+    // Tools prepared for another platform cannot be run, only their directory is checked
+    if tool.version_cmd.is_empty() || tool.version_cmd[0].is_empty() || is_foreign_host_platform() {
       match expected_dir.try_exists() {
           Ok(true) => {
               return Ok(ToolStatus::Correct {
@@ -1373,6 +1943,45 @@ mod tests {
         assert_eq!(result_first.tools[0].install, result_second.tools[0].install);
         assert_eq!(result_first.tools[0].export_paths, result_second.tools[0].export_paths);
     }
+    #[test]
+    fn test_merge_tools_overlay_replaces_and_adds_tools() {
+        let tool = |name: &str, description: &str| Tool {
+            description: description.to_string(),
+            export_paths: vec![vec!["bin".to_string()]],
+            export_vars: HashMap::new(),
+            info_url: "https://example.com".to_string(),
+            install: "always".to_string(),
+            license: None,
+            name: name.to_string(),
+            platform_overrides: None,
+            supported_targets: None,
+            strip_container_dirs: None,
+            version_cmd: vec![name.to_string(), "--version".to_string()],
+            version_regex: "([0-9.]+)".to_string(),
+            version_regex_replace: None,
+            versions: vec![],
+        };
+        let stock = ToolsFile {
+            tools: vec![tool("openocd-esp32", "stock"), tool("cmake", "stock")],
+            version: 3,
+        };
+        let overlay = ToolsFile {
+            tools: vec![tool("openocd-esp32", "patched"), tool("acme-flasher", "internal")],
+            version: 3,
+        };
+
+        let merged = merge_tools_overlay(stock, overlay);
+        let described: Vec<(&str, &str)> = merged
+            .tools
+            .iter()
+            .map(|t| (t.name.as_str(), t.description.as_str()))
+            .collect();
+        assert_eq!(
+            described,
+            vec![("openocd-esp32", "patched"), ("cmake", "stock"), ("acme-flasher", "internal")]
+        );
+    }
+
     #[test]
     fn test_platform_override_install_only() {
         let mut tool = Tool {
@@ -1548,6 +2157,15 @@ mod tests {
         assert!(valid_platforms.contains(&platform.as_str()));
     }
 
+    #[test]
+    fn test_parse_host_platform() {
+        assert_eq!(parse_host_platform("linux-arm64"), Ok("linux-arm64".to_string()));
+        assert_eq!(parse_host_platform(" win64 "), Ok("win64".to_string()));
+        assert!(parse_host_platform("linux-x86_64").is_err());
+        assert!(parse_host_platform("").is_err());
+        assert!(PLATFORM_IDENTIFIERS.contains(&detect_platform_identification().unwrap().as_str()));
+    }
+
     #[test]
     fn test_platform_definition() {
         let platform = get_platform_definition();
@@ -1579,4 +2197,242 @@ mod tests {
         let valid_arch = vec!["x86", "x86_64", "arm", "aarch64"];
         assert!(valid_arch.contains(&arch.as_str()));
     }
+
+    fn make_downloadable_tool(name: &str, url: &str, sha256: &str) -> Tool {
+        let mut downloads = HashMap::new();
+        downloads.insert(
+            "any".to_string(),
+            Download {
+                sha256: sha256.to_string(),
+                size: 0,
+                url: url.to_string(),
+                rename_dist: None,
+            },
+        );
+        Tool {
+            description: "Test tool".to_string(),
+            export_paths: vec![],
+            export_vars: HashMap::new(),
+            info_url: "https://example.com".to_string(),
+            install: "always".to_string(),
+            license: None,
+            name: name.to_string(),
+            platform_overrides: None,
+            supported_targets: None,
+            strip_container_dirs: None,
+            version_cmd: vec![],
+            version_regex: "".to_string(),
+            version_regex_replace: None,
+            versions: vec![Version {
+                name: "1.0".to_string(),
+                status: "recommended".to_string(),
+                downloads,
+            }],
+        }
+    }
+
+    #[test]
+    fn test_pin_to_unlisted_version_is_rejected() {
+        let mut cmake = make_downloadable_tool("cmake", "https://example.com/cmake.tar", "00");
+        cmake.versions.push(Version {
+            name: "3.24.0".to_string(),
+            status: "supported".to_string(),
+            downloads: HashMap::new(),
+        });
+        let tools_file = ToolsFile {
+            tools: vec![cmake],
+            version: 3,
+        };
+
+        let listed = HashMap::from([("cmake".to_string(), "3.24.0".to_string())]);
+        assert!(check_tool_pins(&tools_file, &listed).is_ok());
+        let unknown_tool = HashMap::from([("acme-flasher".to_string(), "2.0".to_string())]);
+        assert!(check_tool_pins(&tools_file, &unknown_tool).is_ok());
+
+        let unlisted = HashMap::from([("cmake".to_string(), "9.9.9".to_string())]);
+        let error = check_tool_pins(&tools_file, &unlisted).unwrap_err();
+        assert!(error.contains("9.9.9"));
+        assert!(error.contains("1.0, 3.24.0"));
+    }
+
+    /// Creates `<dir>/<name>.tar` holding a single file and returns its sha256.
+    fn create_tool_archive(dir: &Path, name: &str) -> String {
+        use sha2::{Digest, Sha256};
+
+        let content_path = dir.join(format!("{}.txt", name));
+        std::fs::write(&content_path, name).unwrap();
+        let archive_path = dir.join(format!("{}.tar", name));
+        let mut builder = tar::Builder::new(File::create(&archive_path).unwrap());
+        builder
+            .append_path_with_name(&content_path, format!("{}.txt", name))
+            .unwrap();
+        builder.finish().unwrap();
+        std::fs::remove_file(&content_path).unwrap();
+
+        format!("{:x}", Sha256::digest(std::fs::read(&archive_path).unwrap()))
+    }
+
+    #[tokio::test]
+    async fn test_setup_tools_failed_tool_does_not_affect_others() {
+        let download_dir = tempfile::TempDir::new().unwrap();
+        let install_dir = tempfile::TempDir::new().unwrap();
+
+        let sha_a = create_tool_archive(download_dir.path(), "tool-a");
+        let sha_b = create_tool_archive(download_dir.path(), "tool-b");
+        let tools_file = ToolsFile {
+            tools: vec![
+                make_downloadable_tool("tool-a", "https://example.com/tool-a.tar", &sha_a),
+                make_downloadable_tool("tool-b", "https://example.com/tool-b.tar", &sha_b),
+                // Nothing listens on the discard port, so this download always fails
+                make_downloadable_tool("tool-broken", "http://127.0.0.1:9/tool-broken.tar", "0"),
+            ],
+            version: 1,
+        };
+
+        let result = setup_tools(
+            &tools_file,
+            vec!["all".to_string()],
+            &download_dir.path().to_path_buf(),
+            &install_dir.path().to_path_buf(),
+            &ToolSetupOptions { max_concurrent_downloads: 2, ..Default::default() },
+            |_| {},
+        )
+        .await;
+
+        let err = result.expect_err("the broken tool should fail the setup").to_string();
+        assert!(err.contains("tool-broken"));
+        assert!(!err.contains("tool-a"));
+        assert!(!err.contains("tool-b"));
+
+        for name in ["tool-a", "tool-b"] {
+            let extracted = install_dir.path().join(name).join("1.0").join(format!("{}.txt", name));
+            assert_eq!(std::fs::read_to_string(extracted).unwrap(), name);
+        }
+        assert!(!install_dir.path().join("tool-broken").exists());
+    }
+
+    /// Serves `content` for every request and returns the base URL of the server.
+    fn serve_content(content: Vec<u8>) -> String {
+        use std::io::{BufRead, BufReader, Write};
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                loop {
+                    let mut line = String::new();
+                    if reader.read_line(&mut line).unwrap() == 0 || line == "\r\n" {
+                        break;
+                    }
+                }
+                let mut response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    content.len()
+                )
+                .into_bytes();
+                response.extend_from_slice(&content);
+                stream.write_all(&response).unwrap();
+            }
+        });
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn test_setup_tools_fails_over_to_next_mirror() {
+        let served_dir = tempfile::TempDir::new().unwrap();
+        let download_dir = tempfile::TempDir::new().unwrap();
+        let install_dir = tempfile::TempDir::new().unwrap();
+
+        let sha = create_tool_archive(served_dir.path(), "tool-a");
+        let working_mirror = serve_content(std::fs::read(served_dir.path().join("tool-a.tar")).unwrap());
+        // Nothing listens on the discard port, so the selected mirror always fails
+        let broken_mirror = "http://127.0.0.1:9";
+        let tools_file = ToolsFile {
+            tools: vec![make_downloadable_tool("tool-a", &format!("{}/tool-a.tar", broken_mirror), &sha)],
+            version: 1,
+        };
+        let failover = MirrorFailover::with_latency_entries(
+            Some(broken_mirror),
+            vec![
+                crate::utils::MirrorEntry { url: broken_mirror.to_string(), latency: None },
+                crate::utils::MirrorEntry { url: working_mirror.clone(), latency: Some(1) },
+            ],
+        );
+
+        let (result, sources) = setup_tools(
+            &tools_file,
+            vec!["all".to_string()],
+            &download_dir.path().to_path_buf(),
+            &install_dir.path().to_path_buf(),
+            &ToolSetupOptions {
+                mirror: Some(broken_mirror.to_string()),
+                mirror_failover: Some(failover),
+                max_concurrent_downloads: 1,
+                ..Default::default()
+            },
+            |_| {},
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(result["tool-a"].1.url, format!("{}/tool-a.tar", working_mirror));
+        assert_eq!(sources["tool-a"], ToolSource::Downloaded(format!("{}/tool-a.tar", working_mirror)));
+        let extracted = install_dir.path().join("tool-a").join("1.0").join("tool-a.txt");
+        assert_eq!(std::fs::read_to_string(extracted).unwrap(), "tool-a");
+    }
+
+    #[tokio::test]
+    async fn test_setup_tools_shares_tool_store_between_installations() {
+        use crate::tool_store::{LinkMode, ToolStore};
+
+        let download_dir = tempfile::TempDir::new().unwrap();
+        let store_dir = tempfile::TempDir::new().unwrap();
+        let sha = create_tool_archive(download_dir.path(), "tool-a");
+        let tools_file = ToolsFile {
+            tools: vec![make_downloadable_tool("tool-a", "https://example.com/tool-a.tar", &sha)],
+            version: 1,
+        };
+        let store = ToolStore::new(store_dir.path(), LinkMode::Symlink);
+        let platform = get_platform_identification().unwrap();
+
+        let mut install_dirs = Vec::new();
+        let mut sources = Vec::new();
+        for owner in ["/idf/a", "/idf/b"] {
+            let install_dir = tempfile::TempDir::new().unwrap();
+            let owned_store = store.clone().with_owner(owner);
+            let (_, mut source) = setup_tools(
+                &tools_file,
+                vec!["all".to_string()],
+                &download_dir.path().to_path_buf(),
+                &install_dir.path().to_path_buf(),
+                &ToolSetupOptions {
+                    max_concurrent_downloads: 1,
+                    tool_store: Some(owned_store),
+                    ..Default::default()
+                },
+                |_| {},
+            )
+            .await
+            .unwrap();
+
+            let extracted = install_dir.path().join("tool-a").join("1.0").join("tool-a.txt");
+            assert_eq!(std::fs::read_to_string(extracted).unwrap(), "tool-a");
+            install_dirs.push(install_dir);
+            sources.push(source.remove("tool-a").unwrap());
+        }
+
+        // Only the first installation extracted the archive, the second one was linked
+        assert_eq!(
+            sources,
+            vec![
+                ToolSource::DownloadFolder(download_dir.path().join("tool-a.tar")),
+                ToolSource::ToolStore,
+            ]
+        );
+        assert!(store.is_complete("tool-a", "1.0", &platform));
+        assert_eq!(store.reference_count("tool-a", "1.0", &platform), 2);
+    }
 }
@@ -0,0 +1,348 @@
+//! Moves an installed ESP-IDF release to a newer patch release in place.
+//!
+//! Instead of installing the new release next to the old one, the new tag is fetched into the
+//! existing clone and checked out together with its submodules. The tools of the installation
+//! are then reconciled with the new tools.json, the Python environment is rebuilt against the
+//! constraints of the release and the activation scripts are regenerated. The installation keeps
+//! its id, name and paths, so the IDEs pointing at it keep working. The patch queue of the
+//! installation is reverted before the update and applied again to the new release.
+//!
+//! Installations made from a git source spec are not releases and are not updated this way.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::git_object_store::GitObjectStore;
+use crate::idf_tools::{apply_tool_versions, get_platform_identification, ToolsFile};
+use crate::settings::VersionPaths;
+use crate::tool_management::{versions_on_disk, InstallationContext, ToolUpgrade};
+use crate::utils::MirrorFailover;
+use crate::version_manager::{is_newer_semver, ToolListIdfContext};
+use crate::DownloadProgress;
+
+/// Result of [`update_installation`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IdfUpdateReport {
+    pub idf: ToolListIdfContext,
+    /// Release the installation was at
+    pub from: String,
+    /// Release the installation is at now, the same as `from` when it was up to date
+    pub to: String,
+    /// Commit checked out after the update
+    #[serde(default)]
+    pub commit: Option<String>,
+    /// Tools whose version changed with the release
+    #[serde(default)]
+    pub tools: Vec<ToolUpgrade>,
+    /// Tools the new release needs that the installation did not have, with their version
+    #[serde(default)]
+    pub added_tools: BTreeMap<String, String>,
+    /// Patches of the installation's patch queue applied to the new release
+    #[serde(default)]
+    pub patches: Vec<String>,
+}
+
+impl IdfUpdateReport {
+    pub fn is_updated(&self) -> bool {
+        self.from != self.to
+    }
+}
+
+/// Major, minor and patch number of a release name like `v5.3.2` or `v5.4`. Pre-releases and
+/// anything else, e.g. `master` or `release/v5.3`, are not releases.
+fn release_numbers(name: &str) -> Option<(u64, u64, u64)> {
+    let numbers: Vec<u64> = name
+        .strip_prefix('v')?
+        .split('.')
+        .map(|part| part.parse().ok())
+        .collect::<Option<_>>()?;
+    match numbers.as_slice() {
+        [major, minor] => Some((*major, *minor, 0)),
+        [major, minor, patch] => Some((*major, *minor, *patch)),
+        _ => None,
+    }
+}
+
+/// The newest of `releases` with the same major and minor version as `current` that is newer
+/// than `current`.
+fn newest_patch_release(current: &str, releases: &[String]) -> Option<String> {
+    let (major, minor, patch) = release_numbers(current)?;
+    releases
+        .iter()
+        .filter_map(|name| release_numbers(name).map(|numbers| (numbers, name)))
+        .filter(|((ma, mi, pa), _)| *ma == major && *mi == minor && *pa > patch)
+        .max_by_key(|(numbers, _)| *numbers)
+        .map(|(_, name)| name.clone())
+}
+
+/// Checks that `target` is a newer patch release of `current`.
+fn check_update_target(current: &str, target: &str) -> Result<(), String> {
+    let (major, minor, patch) = release_numbers(current)
+        .ok_or_else(|| format!("{} is not an ESP-IDF release", current))?;
+    match release_numbers(target) {
+        Some((ma, mi, pa)) if ma == major && mi == minor && pa > patch => Ok(()),
+        Some((ma, mi, _)) if ma == major && mi == minor => {
+            Err(format!("{} is not newer than {}", target, current))
+        }
+        Some(_) => Err(format!(
+            "{} is not a patch release of v{}.{}, install it as a new version instead",
+            target, major, minor
+        )),
+        None => Err(format!("{} is not an ESP-IDF release", target)),
+    }
+}
+
+/// The paths of the installation as the wizard would have computed them, taken from its entry
+/// in eim_idf.json so that nothing moves.
+fn version_paths(context: &InstallationContext) -> VersionPaths {
+    let installation = &context.installation;
+    let idf_path = PathBuf::from(&installation.path);
+    let python_path = PathBuf::from(&installation.python);
+    let activation_script = PathBuf::from(&installation.activation_script);
+    VersionPaths {
+        version_installation_path: idf_path.parent().map(Path::to_path_buf).unwrap_or_default(),
+        idf_path,
+        tool_download_directory: context.download_dir(),
+        tool_install_directory: context.tools_dir(),
+        // <venv>/bin/python or <venv>\Scripts\python.exe
+        python_venv_path: python_path
+            .parent()
+            .and_then(Path::parent)
+            .map(Path::to_path_buf)
+            .unwrap_or_default(),
+        python_path,
+        activation_script_path: activation_script.parent().map(Path::to_path_buf).unwrap_or_default(),
+        activation_script,
+        actual_version: installation.name.clone(),
+        using_existing_idf: true,
+    }
+}
+
+/// Updates the installation matching `identifier` to the release `target`, or to the newest
+/// patch release of its major and minor version if `target` is `None`.
+///
+/// The checkout has to be free of local changes other than its patches. After the new tag is checked out, the tools
+/// the new tools.json recommends are installed and the versions recorded by `eim tools upgrade`
+/// that are not newer than them are dropped; pinned versions stay. With `remove_old_tools`, the
+/// versions no longer used are removed unless another installation sharing the tools directory
+/// uses them. The Python environment is then recreated and the activation scripts regenerated.
+pub async fn update_installation(
+    identifier: &str,
+    target: Option<&str>,
+    remove_old_tools: bool,
+    config_path: Option<&PathBuf>,
+    is_gui: bool,
+    progress_callback: impl Fn(DownloadProgress) + Clone + Send + 'static,
+) -> Result<IdfUpdateReport, String> {
+    let context = InstallationContext::load(identifier, config_path)?;
+    context.ensure_settings_readable()?;
+    let installation = context.installation.clone();
+    if let Some(source) = &installation.idf_source {
+        return Err(format!(
+            "{} was installed from {}, only Espressif releases can be updated",
+            installation.name, source
+        ));
+    }
+    let idf_path = PathBuf::from(&installation.path);
+    let current = crate::git_tools::head_tag(&idf_path)
+        .filter(|tag| release_numbers(tag).is_some())
+        .or_else(|| release_numbers(&installation.name).map(|_| installation.name.clone()))
+        .ok_or_else(|| format!("{} is not checked out at an ESP-IDF release", installation.name))?;
+
+    let mut report = IdfUpdateReport {
+        idf: context.idf_context(),
+        from: current.clone(),
+        to: current.clone(),
+        commit: installation.idf_commit.clone(),
+        tools: Vec::new(),
+        added_tools: BTreeMap::new(),
+        patches: crate::idf_patches::applied_patches(&idf_path),
+    };
+    let target = match target {
+        Some(target) => {
+            check_update_target(&current, target)?;
+            target.to_string()
+        }
+        None => match newest_patch_release(&current, &crate::idf_versions::get_idf_names(false).await) {
+            Some(target) => target,
+            None => {
+                info!("{} is at the newest patch release {}", installation.name, current);
+                return Ok(report);
+            }
+        },
+    };
+    // The patch queue is applied again on top of the new release
+    let patches = context.settings.idf_patches.clone().unwrap_or_default();
+    let reapply_patches = |error: String| match crate::idf_patches::apply_patches(&idf_path, &patches) {
+        Ok(_) => error,
+        Err(e) => format!("{}. The patches could not be applied again either: {}", error, e),
+    };
+    crate::idf_patches::revert_patches(&idf_path)?;
+    if crate::git_tools::has_local_changes(&idf_path).map_err(|e| e.to_string())? {
+        return Err(reapply_patches(format!(
+            "{} has local changes, commit or discard them before updating",
+            idf_path.display()
+        )));
+    }
+
+    let old_versions = context.installed_versions();
+    info!("Updating {} from {} to {}", installation.name, current, target);
+    let commit = crate::git_tools::update_to_tag_git_cli(
+        &idf_path,
+        &target,
+        context.settings.idf_mirror.as_deref(),
+    )
+        .map_err(|e| reapply_patches(format!("Failed to check out {}: {}", target, e)))?;
+    report.to = target.clone();
+    report.commit = Some(commit.clone());
+
+    // Everything from here on reads the tools.json of the new release
+    let mut context = InstallationContext::from_installation(context.config_path, installation)?;
+    context.installation.idf_commit = Some(commit);
+    match crate::idf_patches::apply_patches(&idf_path, &patches) {
+        Ok(applied) => report.patches = applied,
+        Err(e) => {
+            // The checkout is at the new release, without patches
+            context.save_settings()?;
+            return Err(format!(
+                "{} is checked out at {}, but its patches were not applied: {}. Update the patch and run `eim fix` to finish the update",
+                context.installation.name, target, e
+            ));
+        }
+    }
+    if let Some(tool_versions) = context.settings.tool_versions.as_mut() {
+        let recommended: BTreeMap<&str, &str> = context
+            .tools_file
+            .tools
+            .iter()
+            .filter_map(|tool| {
+                let version = tool.versions.iter().find(|v| v.status == "recommended")?;
+                Some((tool.name.as_str(), version.name.as_str()))
+            })
+            .collect();
+        tool_versions.retain(|tool, version| {
+            let keep = recommended
+                .get(tool.as_str())
+                .is_some_and(|newest| is_newer_semver(version, Some(*newest)));
+            if !keep {
+                debug!("{} {} is superseded by the tools.json of {}", tool, version, target);
+            }
+            keep
+        });
+    }
+
+    let tools_dir = context.tools_dir();
+    let platform = get_platform_identification()?;
+    let chosen = apply_tool_versions(context.tools_file.clone(), &context.settings.chosen_tool_versions());
+    let missing: Vec<_> = chosen
+        .tools
+        .iter()
+        .filter(|tool| context.is_selected(tool))
+        .filter(|tool| {
+            tool.versions.iter().any(|v| {
+                v.status == "recommended"
+                    && (v.downloads.contains_key(&platform) || v.downloads.contains_key("any"))
+                    && !tools_dir.join(&tool.name).join(&v.name).exists()
+            })
+        })
+        .cloned()
+        .collect();
+    if !missing.is_empty() {
+        let install_file = ToolsFile {
+            tools: missing,
+            version: context.tools_file.version,
+        };
+        context.install(&install_file, progress_callback).await?;
+    }
+
+    let new_versions = context.installed_versions();
+    let used_elsewhere = if remove_old_tools {
+        context.versions_used_elsewhere()
+    } else {
+        None
+    };
+    for (name, (to, _)) in &new_versions {
+        let Some((from, _)) = old_versions.get(name) else {
+            report.added_tools.insert(name.clone(), to.clone());
+            continue;
+        };
+        if from == to {
+            continue;
+        }
+        let mut removed = Vec::new();
+        if remove_old_tools && versions_on_disk(&tools_dir, name).contains(from) {
+            match context.remove_version(name, from, used_elsewhere.as_ref()) {
+                Ok(true) => removed.push(from.clone()),
+                Ok(false) => {}
+                Err(e) => warn!("Failed to remove {} {}: {}", name, from, e),
+            }
+        }
+        info!("Updated {} from {} to {}", name, from, to);
+        report.tools.push(ToolUpgrade {
+            name: name.clone(),
+            from: from.clone(),
+            to: to.clone(),
+            removed,
+        });
+    }
+    report.tools.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let paths = version_paths(&context);
+    let features = context.settings.get_features_for_version(&context.installation.name);
+    crate::python_utils::install_python_env(
+        &paths,
+        &target,
+        &tools_dir,
+        true,
+        &features,
+        None,
+        &context.settings.pypi_mirror,
+        Some(&MirrorFailover::new(
+            context.settings.pypi_mirror.as_deref(),
+            crate::get_pypi_mirrors_list(),
+        )),
+    )
+    .await
+    .map_err(|e| format!("Failed to refresh the Python environment: {}", e))?;
+
+    context.save_settings()?;
+    context.refresh_activation_scripts(is_gui);
+    if let Some(store) = GitObjectStore::from_settings(&context.settings) {
+        // Shallow clones are skipped, the objects of full ones are shared with the next clones
+        if let Err(e) = store.absorb(&idf_path) {
+            warn!("Failed to add the objects of {} to the git object store: {}", target, e);
+        }
+    }
+    info!("Updated {} to {}", context.installation.name, target);
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_newest_patch_release() {
+        let releases: Vec<String> = ["master", "v5.4", "v5.3.3", "v5.3.2", "v5.3.4-beta1", "release/v5.3", "v5.2.5"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(newest_patch_release("v5.3.1", &releases).as_deref(), Some("v5.3.3"));
+        assert_eq!(newest_patch_release("v5.3", &releases).as_deref(), Some("v5.3.3"));
+        assert_eq!(newest_patch_release("v5.3.3", &releases), None);
+        assert_eq!(newest_patch_release("v5.4", &releases), None);
+        assert_eq!(newest_patch_release("master", &releases), None);
+    }
+
+    #[test]
+    fn test_check_update_target() {
+        assert!(check_update_target("v5.3.1", "v5.3.2").is_ok());
+        assert!(check_update_target("v5.3.2", "v5.3.1").is_err());
+        assert!(check_update_target("v5.3.1", "v5.4").is_err());
+        assert!(check_update_target("v5.3.1", "master").is_err());
+        assert!(check_update_target("release/v5.3", "v5.3.2").is_err());
+    }
+}
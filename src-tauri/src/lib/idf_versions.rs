@@ -152,10 +152,18 @@ pub async fn get_avalible_targets() -> Result<Vec<String>, String> {
 ///
 pub async fn download_idf_versions() -> Result<Releases, Box<dyn std::error::Error>> {
     let url = IDF_VERSIONS_URL.to_string();
-    let client = reqwest::Client::builder()
+    let client = crate::proxy::client_builder()
         .user_agent("esp-idf-installer")
         .build()?;
-    let response: reqwest::Response = client.get(&url).send().await?;
+    let response: reqwest::Response = client.get(&url).send().await.map_err(
+        |e| -> Box<dyn std::error::Error> {
+            if crate::tls::is_certificate_error(&e) {
+                crate::tls::describe_error(&e).into()
+            } else {
+                e.into()
+            }
+        },
+    )?;
     let json_versions_file: String = response.text().await?;
     let versions: Releases = serde_json::from_str(&json_versions_file)?;
 
@@ -1,5 +1,3 @@
-use bzip2::read::BzDecoder;
-use flate2::read::GzDecoder;
 use std::ffi::OsStr;
 use std::hash::{DefaultHasher, Hash,Hasher};
 use anyhow::{anyhow, Result};
@@ -12,11 +10,8 @@ use serde_json::{json, Value};
 use sha2::{Digest, Sha256};
 use system_dependencies::copy_openocd_rules;
 use tempfile::TempDir;
-use tar::Archive;
 use thiserror::Error;
 use utils::{find_directories_by_name};
-use zip::ZipArchive;
-use std::io::Seek;
 
 /// Simple template renderer - replaces {{variable}} placeholders with values
 /// This is a lightweight replacement for tera, using only std library
@@ -28,26 +23,44 @@ pub fn render_template(template: &str, variables: &[(&str, String)]) -> String {
     }
     result
 }
+pub mod archive_formats;
 pub mod command_executor;
+pub mod download_cache;
+pub mod gc;
+pub mod git_object_store;
 pub mod git_tools;
 pub mod idf_config;
 pub mod idf_tools;
 pub mod idf_versions;
 pub mod idf_features;
+pub mod idf_patches;
+pub mod idf_source;
+pub mod idf_update;
+pub mod proxy;
 pub mod python_utils;
+pub mod rate_limit;
+pub mod safe_extract;
 pub mod settings;
+pub mod stream_extract;
 pub mod system_dependencies;
 pub mod utils;
+pub mod verify;
 pub mod version_manager;
 pub mod offline_installer;
 pub mod telemetry;
 pub mod tool_selection;
+pub mod tool_management;
+pub mod tool_manifest;
+pub mod tool_store;
+pub mod tls;
 pub mod logging;
+#[cfg(test)]
+mod test_fixtures;
 use std::fs::{set_permissions, File};
 use std::{
     env,
     fs,
-    io::{self, Read, Write, BufReader},
+    io::{self, Read, Write},
     path::{Path, PathBuf},
     sync::mpsc::Sender,
 };
@@ -1424,6 +1437,7 @@ fn get_openocd_scripts_folder(idf_tools_path: &PathBuf) -> Result<String, std::i
 pub enum DownloadProgress {
     Start(String),
     Progress(u64, u64), // (downloaded, total)
+    ToolProgress(String, u64, u64), // (url, downloaded, total)
     Downloaded(String),
     Verified(String),
     Extracted(String, String), // (url, destination_path)
@@ -1431,6 +1445,15 @@ pub enum DownloadProgress {
     Complete,
     Error(String),
 }
+/// Applies the network related settings (proxy, CA bundle and download rate limit) to all
+/// downloads, git operations and pip runs of this process.
+pub fn apply_network_settings(settings: &settings::Settings) -> Result<()> {
+    proxy::configure(settings)?;
+    tls::configure(settings)?;
+    rate_limit::configure(settings);
+    Ok(())
+}
+
 pub async fn download_file(
     url: &str,
     destination_path: &str,
@@ -1446,6 +1469,16 @@ pub async fn download_file(
     .await
 }
 
+/// Downloads `url` into `destination_path`, optionally under `new_name`, retrying up to `max_retries` times.
+///
+/// Data is written to a `<filename>.part` file next to the destination and only renamed to the
+/// final name once the transfer finishes. When a `.part` file is already present (from a failed
+/// attempt or an earlier run), the download resumes from its end using a `Range` request guarded
+/// by `If-Range`. Servers that do not honour the range simply send the whole file again. A
+/// `.part` file without a stored `ETag`/`Last-Modified` validator is discarded instead, as
+/// nothing would tell whether the remote file changed since it was written.
+/// Resuming does not replace integrity checks; callers still verify the result with
+/// `verify_file_checksum`.
 pub async fn download_file_and_rename(
     url: &str,
     destination_path: &str,
@@ -1453,7 +1486,9 @@ pub async fn download_file_and_rename(
     new_name: Option<&str>,
     max_retries: u32,
 ) -> Result<(), std::io::Error> {
-    let client: reqwest::Client = reqwest::Client::new();
+    let client: reqwest::Client = crate::proxy::client_builder()
+        .build()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
     let retries = max_retries.max(1); // At least one attempt
 
     let filename = if let Some(new_name) = new_name {
@@ -1510,17 +1545,112 @@ pub async fn download_file_and_rename(
     unreachable!()
 }
 
+/// Returns the path of the `.part` file an unfinished download of `file_path` is written to.
+pub fn partial_download_path(file_path: &Path) -> PathBuf {
+    let mut name = file_path.as_os_str().to_os_string();
+    name.push(".part");
+    PathBuf::from(name)
+}
+
+/// Returns the path storing the `ETag`/`Last-Modified` validator of a `.part` file,
+/// sent back as `If-Range` when the download is resumed.
+fn partial_download_validator_path(file_path: &Path) -> PathBuf {
+    let mut name = partial_download_path(file_path).into_os_string();
+    name.push(".validator");
+    PathBuf::from(name)
+}
+
+fn remove_partial_download(file_path: &Path) {
+    let _ = fs::remove_file(partial_download_path(file_path));
+    let _ = fs::remove_file(partial_download_validator_path(file_path));
+}
+
+async fn send_download_request(
+    client: &reqwest::Client,
+    url: &str,
+    resume_from: u64,
+    validator: Option<&str>,
+) -> Result<reqwest::Response, std::io::Error> {
+    let mut request = client.get(url);
+    if resume_from > 0 {
+        request = request.header(reqwest::header::RANGE, format!("bytes={}-", resume_from));
+        if let Some(validator) = validator {
+            request = request.header(reqwest::header::IF_RANGE, validator);
+        }
+    }
+    request.send().await.map_err(|e: reqwest::Error| {
+        if crate::tls::is_certificate_error(&e) {
+            std::io::Error::new(std::io::ErrorKind::Other, crate::tls::describe_error(&e))
+        } else {
+            std::io::Error::new(std::io::ErrorKind::Other, e)
+        }
+    })
+}
+
+/// Picks a validator usable in `If-Range`: a strong `ETag`, otherwise `Last-Modified`.
+fn response_validator(response: &reqwest::Response) -> Option<String> {
+    let headers = response.headers();
+    headers
+        .get(reqwest::header::ETAG)
+        .and_then(|v| v.to_str().ok())
+        .filter(|etag| !etag.starts_with("W/"))
+        .or_else(|| {
+            headers
+                .get(reqwest::header::LAST_MODIFIED)
+                .and_then(|v| v.to_str().ok())
+        })
+        .map(str::to_string)
+}
+
 async fn attempt_download(
     client: &reqwest::Client,
     url: &str,
     file_path: &Path,
     progress_sender: &Option<Sender<DownloadProgress>>,
 ) -> Result<(), std::io::Error> {
-    let mut response: reqwest::Response = client
-        .get(url)
-        .send()
-        .await
-        .map_err(|e: reqwest::Error| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+    let part_path = partial_download_path(file_path);
+    let validator_path = partial_download_validator_path(file_path);
+
+    let mut resume_from = fs::metadata(&part_path).map(|m| m.len()).unwrap_or(0);
+    let validator = if resume_from > 0 {
+        fs::read_to_string(&validator_path)
+            .ok()
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+    } else {
+        None
+    };
+
+    if resume_from > 0 && validator.is_none() {
+        log::info!(
+            "Partial download of {} has no validator, downloading the whole file again",
+            url
+        );
+        remove_partial_download(file_path);
+        resume_from = 0;
+    }
+
+    if resume_from > 0 {
+        log::info!(
+            "Found partial download of {} ({} bytes), trying to resume",
+            url,
+            resume_from
+        );
+    }
+
+    let mut response = send_download_request(client, url, resume_from, validator.as_deref()).await?;
+
+    if resume_from > 0 && response.status() == reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
+        // The partial file does not fit the remote one, start over
+        log::warn!(
+            "Server rejected resuming {} at byte {}, discarding the partial download",
+            url,
+            resume_from
+        );
+        remove_partial_download(file_path);
+        resume_from = 0;
+        response = send_download_request(client, url, 0, None).await?;
+    }
 
     if !response.status().is_success() {
         return Err(std::io::Error::new(
@@ -1529,7 +1659,45 @@ async fn attempt_download(
         ));
     }
 
-    let total_size = response.content_length();
+    let resuming = resume_from > 0 && response.status() == reqwest::StatusCode::PARTIAL_CONTENT;
+    if resuming {
+        let expected_range = format!("bytes {}-", resume_from);
+        let range_matches = response
+            .headers()
+            .get(reqwest::header::CONTENT_RANGE)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.starts_with(&expected_range))
+            .unwrap_or(true);
+        if !range_matches {
+            remove_partial_download(file_path);
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Server returned an unexpected range while resuming {}", url),
+            ));
+        }
+        log::info!("Resuming download of {} from byte {}", url, resume_from);
+    } else {
+        if resume_from > 0 {
+            log::info!(
+                "Server does not support resuming {}, downloading the whole file again",
+                url
+            );
+        }
+        // Remember how to validate the data we are about to write, so a later attempt can resume it
+        match response_validator(&response) {
+            Some(validator) => {
+                if let Err(e) = fs::write(&validator_path, validator) {
+                    log::debug!("Failed to store download validator for {}: {}", url, e);
+                }
+            }
+            None => {
+                let _ = fs::remove_file(&validator_path);
+            }
+        }
+    }
+
+    let offset = if resuming { resume_from } else { 0 };
+    let total_size = response.content_length().map(|len| len + offset);
 
     if total_size.is_none() {
         log::debug!(
@@ -1538,17 +1706,22 @@ async fn attempt_download(
         );
     }
 
-    log::debug!("Downloading {} to {}", url, file_path.display());
+    log::debug!("Downloading {} to {}", url, part_path.display());
 
-    let mut file = File::create(file_path)?;
+    let mut file = if resuming {
+        fs::OpenOptions::new().append(true).open(&part_path)?
+    } else {
+        File::create(&part_path)?
+    };
 
-    let mut downloaded: u64 = 0;
+    let mut downloaded: u64 = offset;
 
     while let Some(chunk) = response
         .chunk()
         .await
         .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?
     {
+        crate::rate_limit::throttle(chunk.len()).await;
         downloaded += chunk.len() as u64;
         file.write_all(&chunk)?;
 
@@ -1567,6 +1740,11 @@ async fn attempt_download(
         }
     }
 
+    file.flush()?;
+    drop(file);
+    fs::rename(&part_path, file_path)?;
+    let _ = fs::remove_file(&validator_path);
+
     if let Some(sender) = progress_sender {
         if let Err(e) = sender.send(DownloadProgress::Complete) {
             log::warn!("Failed to send completion: {}", e);
@@ -1584,10 +1762,17 @@ pub enum DecompressionError {
     Zip(#[from] zip::result::ZipError),
     #[error("Unsupported archive format")]
     UnsupportedFormat,
+    #[error("Archive contains unsafe entries: {}", .0.iter().map(|e| e.to_string()).collect::<Vec<_>>().join(", "))]
+    UnsafeEntries(Vec<safe_extract::UnsafeEntry>),
+    #[error("Archive expands to more than {0} bytes")]
+    SizeLimitExceeded(u64),
 }
 
 /// Decompresses an archive file into the specified destination directory.
 ///
+/// The format is detected from the magic bytes of the file and only falls back to the file
+/// extension when no signature matches, see [`archive_formats`].
+///
 /// # Parameters
 ///
 /// * `archive_path`: A string representing the path to the archive file to be decompressed.
@@ -1605,6 +1790,8 @@ pub enum DecompressionError {
 /// * `DecompressionError::Io`: An error occurred while performing I/O operations.
 /// * `DecompressionError::Zip`: An error occurred while decompressing a ZIP archive.
 /// * `DecompressionError::UnsupportedFormat`: The specified archive format is not supported.
+/// * `DecompressionError::UnsafeEntries`: Entries escaping the destination were skipped, see [`safe_extract`].
+/// * `DecompressionError::SizeLimitExceeded`: The archive expands to more than [`safe_extract::MAX_EXTRACTED_SIZE`].
 pub fn decompress_archive(
     archive_path: &str,
     destination_path: &str,
@@ -1616,34 +1803,7 @@ pub fn decompress_archive(
         std::fs::create_dir_all(destination_path)?;
     }
 
-    let result = match archive_path.extension().and_then(|ext| ext.to_str()) {
-        Some("zip") | Some("ZIP") => decompress_zip(archive_path, destination_path),
-        Some("tar") | Some("TAR") => decompress_tar(archive_path, destination_path),
-        Some("gz") | Some("tgz") | Some("GZ") | Some("TGZ") => {
-            if archive_path.to_str().unwrap_or("").ends_with(".tar.gz")
-                || archive_path.extension().unwrap() == "tgz"
-            {
-                decompress_tar_gz(archive_path, destination_path)
-            } else {
-                Err(DecompressionError::UnsupportedFormat)
-            }
-        }
-        Some("xz") => {
-            if archive_path.to_str().unwrap_or("").ends_with(".tar.xz") {
-                decompress_tar_xz(archive_path, destination_path)
-            } else {
-                Err(DecompressionError::UnsupportedFormat)
-            }
-        }
-        Some("bz2") => {
-            if archive_path.to_str().unwrap_or("").ends_with(".tar.bz2") {
-                decompress_tar_bz2(archive_path, destination_path)
-            } else {
-                Err(DecompressionError::UnsupportedFormat)
-            }
-        }
-        _ => Err(DecompressionError::UnsupportedFormat),
-    };
+    let result = archive_formats::unpack_file(archive_path, destination_path);
     // Check the result of the decompression
     // if the file already exists, skip the decompression
     match result {
@@ -1668,217 +1828,11 @@ pub fn decompress_archive(
                 log::error!("Unsupported archive format.");
                 Err(DecompressionError::UnsupportedFormat)
             }
-        },
-    }
-}
-
-fn decompress_zip(archive_path: &Path, destination_path: &Path) -> Result<(), DecompressionError> {
-    log::info!(
-        "Decompressing {} to {}",
-        archive_path.display(),
-        destination_path.display()
-    );
-
-    if !Path::new(archive_path).exists() {
-        log::error!("File does not exist.");
-        return Err(DecompressionError::Io(io::Error::new(
-            io::ErrorKind::NotFound,
-            "Archive file not found",
-        )));
-    }
-
-    // First, try using ZipArchive for all platforms
-    let zip_result = (|| {
-        let file = File::open(archive_path)?;
-        let mut archive = ZipArchive::new(file)?;
-
-        for i in 0..archive.len() {
-            let mut file = archive.by_index(i)?;
-            let outpath = match file.enclosed_name() {
-                Some(path) => destination_path.join(path),
-                None => continue,
-            };
-
-            if file.name().ends_with('/') {
-                std::fs::create_dir_all(&outpath)?;
-            } else {
-                if let Some(p) = outpath.parent() {
-                    if !p.exists() {
-                        std::fs::create_dir_all(p)?;
-                    }
-                }
-                let mut outfile = File::create(&outpath)?;
-                io::copy(&mut file, &mut outfile)?;
-            }
-        }
-        Ok(())
-    })();
-
-    // If ZipArchive failed and we're on Windows, fall back to PowerShell
-    if let Err(err) = zip_result {
-        if std::env::consts::OS == "windows" {
-            log::warn!(
-                "ZipArchive decompression failed: {}. Falling back to PowerShell approach.",
-                err
-            );
-
-            let executor = crate::command_executor::get_executor();
-            let archive_path_str = archive_path.to_string_lossy().to_string();
-            let destination_path_str = destination_path.to_string_lossy().to_string();
-
-            // Create a separate thread to run the PowerShell command
-            let handle = std::thread::spawn(move || {
-                let script = format!(
-                    "Expand-Archive -Path '{}' -DestinationPath '{}' -Force",
-                    archive_path_str, destination_path_str
-                );
-
-                executor.run_script_from_string(&script)
-            });
-
-            // Wait for the thread to complete
-            match handle.join() {
-                Ok(result) => match result {
-                    Ok(output) => {
-                        if !output.status.success() {
-                            let error_message = String::from_utf8_lossy(&output.stderr);
-                            log::error!("PowerShell decompression failed: {}", error_message);
-                            return Err(DecompressionError::Io(io::Error::new(
-                                io::ErrorKind::Other,
-                                format!("PowerShell decompression failed: {}", error_message),
-                            )));
-                        }
-                        Ok(())
-                    }
-                    Err(e) => {
-                        log::error!("Failed to execute PowerShell command: {}", e);
-                        Err(DecompressionError::Io(e))
-                    }
-                },
-                Err(e) => {
-                    log::error!("Thread panicked: {:?}", e);
-                    Err(DecompressionError::Io(io::Error::new(
-                        io::ErrorKind::Other,
-                        "Thread panicked during decompression",
-                    )))
-                }
-            }
-        } else {
-            // On non-Windows platforms, just return the original error
-            Err(err)
-        }
-    } else {
-        // ZipArchive succeeded
-        Ok(())
-    }
-}
-
-/// Decompresses a TAR archive into the specified destination directory.
-///
-/// # Parameters
-///
-/// * `archive_path`: A reference to a `Path` representing the path to the TAR archive.
-/// * `destination_path`: A reference to a `Path` representing the destination directory where the archive should be decompressed.
-///
-/// # Return Value
-///
-/// * `Result<(), DecompressionError>`: On success, returns `Ok(())`. On error, returns a `DecompressionError` indicating the cause of the error.
-fn decompress_tar(archive_path: &Path, destination_path: &Path) -> Result<(), DecompressionError> {
-    let file = File::open(archive_path)?;
-    let mut archive = Archive::new(file);
-    archive.unpack(destination_path)?;
-    Ok(())
-}
-
-/// Decompresses a TAR.GZ archive into the specified destination directory.
-///
-/// # Parameters
-///
-/// * `archive_path`: A reference to a `Path` representing the path to the TAR.GZ archive.
-/// * `destination_path`: A reference to a `Path` representing the destination directory where the archive should be decompressed.
-///
-/// # Return Value
-///
-/// * `Result<(), DecompressionError>`: On success, returns `Ok(())`. On error, returns a `DecompressionError` indicating the cause of the error.
-fn decompress_tar_gz(
-    archive_path: &Path,
-    destination_path: &Path,
-) -> Result<(), DecompressionError> {
-    let file = File::open(archive_path)?;
-    let gz = GzDecoder::new(file);
-    let mut archive = Archive::new(gz);
-    archive.unpack(destination_path)?;
-    Ok(())
-}
-
-/// Decompresses a TAR.XZ archive into the specified destination directory.
-///
-/// # Parameters
-///
-/// * `archive_path`: A reference to a `Path` representing the path to the TAR.XZ archive.
-/// * `destination_path`: A reference to a `Path` representing the destination directory where the archive should be decompressed.
-///
-/// # Returns
-///
-/// * `Result<(), DecompressionError>`: On success, returns `Ok(())`. On error, returns a `DecompressionError` indicating the cause of the error.
-fn decompress_tar_xz(
-    archive_path: &Path,
-    destination_path: &Path,
-) -> Result<(), DecompressionError> {
-    let file = File::open(archive_path)?;
-    let mut reader = BufReader::new(file);
-
-    let mut temp = tempfile::tempfile()?;
-    lzma_rs::xz_decompress(&mut reader, &mut temp)
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
-
-    temp.seek(std::io::SeekFrom::Start(0))?;
-    let mut archive = Archive::new(BufReader::new(temp));
-    archive.unpack(destination_path)?;
-
-    Ok(())
-}
-
-/// Decompresses a TAR.BZ2 archive into the specified destination directory.
-///
-/// # Parameters
-///
-/// * `archive_path`: A reference to a `Path` representing the path to the TAR.BZ2 archive.
-/// * `destination_path`: A reference to a `Path` representing the destination directory where the archive should be decompressed.
-///
-/// # Returns
-///
-/// * `Result<(), DecompressionError>`: On success, returns `Ok(())`. On error, returns a `DecompressionError` indicating the cause of the error.
-fn decompress_tar_bz2(
-    archive_path: &Path,
-    destination_path: &Path,
-) -> Result<(), DecompressionError> {
-    log::info!(
-        "Decompressing {} to {}",
-        archive_path.display(),
-        destination_path.display()
-    );
-
-    let file = File::open(archive_path)?;
-    let bz = BzDecoder::new(file);
-    let mut archive = Archive::new(bz);
-
-    // Try to unpack, but if it fails on special files on Windows, just log and continue
-    match archive.unpack(destination_path) {
-        Ok(_) => Ok(()),
-        Err(e) => {
-            // On Windows, special files like dev/fd may cause errors - log and return success if extraction succeeded partially
-            if std::env::consts::OS == "windows" {
-                log::warn!("tar unpack had issues on Windows (likely special files): {}. Continuing anyway.", e);
-                Ok(())
-            } else {
-                log::error!("Failed to unpack tar.bz2 archive: {}", e);
-                Err(DecompressionError::Io(io::Error::new(
-                    io::ErrorKind::Other,
-                    format!("Failed to unpack tar.bz2 archive: {}", e),
-                )))
+            e @ (DecompressionError::UnsafeEntries(_) | DecompressionError::SizeLimitExceeded(_)) => {
+                log::error!("{}", e);
+                Err(e)
             }
-        }
+        },
     }
 }
 
@@ -3174,4 +3128,118 @@ mod tests {
             "BAT deactivate must clear ACTIVATE_SCRIPT at the :end label. Got:\n{bat_deact_content}"
         );
     }
+
+    /// Serves `content` over plain HTTP on a local port for `requests` connections.
+    /// When `honor_range` is set, `Range: bytes=N-` requests get a 206 with the tail of the content,
+    /// unless they carry an `If-Range` other than the served `ETag`.
+    fn spawn_http_server(content: Vec<u8>, honor_range: bool, requests: usize) -> String {
+        use std::io::{BufRead, BufReader};
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().take(requests) {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut range_start: Option<usize> = None;
+                let mut if_range: Option<String> = None;
+                loop {
+                    let mut line = String::new();
+                    if reader.read_line(&mut line).unwrap() == 0 || line == "\r\n" {
+                        break;
+                    }
+                    let lower = line.to_ascii_lowercase();
+                    if let Some(value) = lower.strip_prefix("range: bytes=") {
+                        range_start = value.trim().trim_end_matches('-').parse().ok();
+                    }
+                    if let Some(value) = lower.strip_prefix("if-range: ") {
+                        if_range = Some(value.trim().to_string());
+                    }
+                }
+                let validator_matches = if_range.map_or(true, |v| v == "\"test-etag\"");
+                let response = match range_start.filter(|_| honor_range && validator_matches) {
+                    Some(start) => {
+                        let body = &content[start..];
+                        let mut head = format!(
+                            "HTTP/1.1 206 Partial Content\r\nContent-Length: {}\r\nContent-Range: bytes {}-{}/{}\r\nConnection: close\r\n\r\n",
+                            body.len(),
+                            start,
+                            content.len() - 1,
+                            content.len()
+                        )
+                        .into_bytes();
+                        head.extend_from_slice(body);
+                        head
+                    }
+                    None => {
+                        let mut head = format!(
+                            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nETag: \"test-etag\"\r\nConnection: close\r\n\r\n",
+                            content.len()
+                        )
+                        .into_bytes();
+                        head.extend_from_slice(&content);
+                        head
+                    }
+                };
+                stream.write_all(&response).unwrap();
+            }
+        });
+        format!("http://{}/archive.bin", addr)
+    }
+
+    #[tokio::test]
+    async fn test_download_resumes_partial_file() {
+        let content: Vec<u8> = (0..4096u32).map(|i| (i % 251) as u8).collect();
+        let url = spawn_http_server(content.clone(), true, 1);
+        let dir = TempDir::new().unwrap();
+        let file_path = dir.path().join("archive.bin");
+
+        // Leave the first half behind, as an interrupted download would
+        fs::write(partial_download_path(&file_path), &content[..2048]).unwrap();
+        fs::write(partial_download_validator_path(&file_path), "\"test-etag\"").unwrap();
+
+        download_file_and_rename(&url, dir.path().to_str().unwrap(), None, None, 1)
+            .await
+            .unwrap();
+
+        assert_eq!(fs::read(&file_path).unwrap(), content);
+        assert!(!partial_download_path(&file_path).exists());
+    }
+
+    #[tokio::test]
+    async fn test_download_restarts_when_range_is_not_supported() {
+        let content: Vec<u8> = (0..4096u32).map(|i| (i % 13) as u8).collect();
+        let url = spawn_http_server(content.clone(), false, 1);
+        let dir = TempDir::new().unwrap();
+        let file_path = dir.path().join("archive.bin");
+
+        // Stale partial data which must not end up in the final file
+        fs::write(partial_download_path(&file_path), vec![0xFFu8; 1024]).unwrap();
+
+        download_file_and_rename(&url, dir.path().to_str().unwrap(), None, None, 1)
+            .await
+            .unwrap();
+
+        assert_eq!(fs::read(&file_path).unwrap(), content);
+        assert!(!partial_download_path(&file_path).exists());
+    }
+
+    #[tokio::test]
+    async fn test_download_without_validator_does_not_resume() {
+        let content: Vec<u8> = (0..4096u32).map(|i| (i % 7) as u8).collect();
+        let url = spawn_http_server(content.clone(), true, 1);
+        let dir = TempDir::new().unwrap();
+        let file_path = dir.path().join("archive.bin");
+
+        // Partial data of an older file, written without a validator
+        fs::write(partial_download_path(&file_path), vec![0xFFu8; 2048]).unwrap();
+
+        download_file_and_rename(&url, dir.path().to_str().unwrap(), None, None, 1)
+            .await
+            .unwrap();
+
+        assert_eq!(fs::read(&file_path).unwrap(), content);
+        assert!(!partial_download_validator_path(&file_path).exists());
+    }
 }
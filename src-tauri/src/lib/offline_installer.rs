@@ -3,7 +3,7 @@ use std::{fs, io::{self, Read, Write}, path::{Path, PathBuf}};
 use log::{debug, error, info, warn};
 use tempfile::TempDir;
 
-use crate::{add_path_to_path, command_executor::{self, execute_command}, python_utils::detect_default_python, render_template, settings::Settings, system_dependencies::{add_to_path, get_correct_powershell_command}, utils::{copy_dir_contents,copy_dir_contents_preserving_mtime, extract_zst_archive, get_git_path}};
+use crate::{add_path_to_path, command_executor::{self, execute_command}, python_utils::detect_default_python, render_template, settings::Settings, system_dependencies::{add_to_path, get_correct_powershell_command}, utils::{copy_dir_contents,copy_dir_contents_preserving_mtime, get_git_path}};
 
 /// One entry of the offline archive manifest served at
 /// `https://dl.espressif.com/dl/eim/offline_archives.json`.
@@ -54,7 +54,9 @@ pub fn current_offline_platform() -> Option<String> {
 pub async fn fetch_offline_archive_manifest(
     manifest_url: &str,
 ) -> Result<Vec<OfflineArchiveEntry>, String> {
-    let client = reqwest::Client::new();
+    let client = crate::proxy::client_builder()
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
     let response = client
         .get(manifest_url)
         .send()
@@ -389,7 +391,10 @@ pub fn use_offline_archive(mut config: Settings, offline_archive_dir: &TempDir)
             config.use_local_archive.as_ref().unwrap().display()
         ));
     }
-    match extract_zst_archive(&config.use_local_archive.as_ref().unwrap(), &offline_archive_dir.path()) {
+    match crate::decompress_archive(
+      &config.use_local_archive.as_ref().unwrap().to_string_lossy(),
+      &offline_archive_dir.path().to_string_lossy(),
+    ) {
       Ok(_) => {
           info!("Successfully extracted archive to: {:?}", offline_archive_dir);
       }
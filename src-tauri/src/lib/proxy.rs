@@ -0,0 +1,310 @@
+//! Proxy configuration shared by every network path of the installer.
+//!
+//! The proxy comes from the `proxy` and `no_proxy` settings and falls back to the usual
+//! `HTTPS_PROXY` / `ALL_PROXY` / `HTTP_PROXY` and `NO_PROXY` environment variables. Once
+//! [`configure`] has been called, the same proxy is applied to the reqwest clients built with
+//! [`client_builder`] and [`blocking_client_builder`], to gix fetches and clones, to the `git`
+//! command line fallbacks and to pip.
+//!
+//! `http://`, `https://`, `socks5://` and `socks5h://` proxies are supported. `no_proxy` is a
+//! comma separated list of host names; an entry also matches all subdomains of the host, and
+//! `*` disables the proxy for every host. The hosts of the `no_proxy` setting and of the
+//! `NO_PROXY` environment variable are both bypassed, wherever the proxy itself comes from.
+
+use std::sync::RwLock;
+
+use anyhow::{anyhow, Result};
+use log::{debug, warn};
+use once_cell::sync::Lazy;
+
+use crate::settings::Settings;
+
+const SUPPORTED_SCHEMES: [&str; 4] = ["http", "https", "socks5", "socks5h"];
+
+static ACTIVE_PROXY: Lazy<RwLock<Option<ProxyConfig>>> =
+    Lazy::new(|| RwLock::new(ProxyConfig::from_env()));
+
+/// A validated proxy URL together with the hosts that bypass it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProxyConfig {
+    url: String,
+    no_proxy: Vec<String>,
+}
+
+impl ProxyConfig {
+    /// Validates `url` and parses the comma separated `no_proxy` list.
+    ///
+    /// A URL without a scheme is treated as an `http://` proxy.
+    pub fn new(url: &str, no_proxy: Option<&str>) -> Result<Self> {
+        let url = url.trim();
+        let url = if url.contains("://") {
+            url.to_string()
+        } else {
+            format!("http://{}", url)
+        };
+        let parsed = url::Url::parse(&url).map_err(|e| anyhow!("Invalid proxy URL '{}': {}", url, e))?;
+        if !SUPPORTED_SCHEMES.contains(&parsed.scheme()) {
+            return Err(anyhow!(
+                "Unsupported proxy scheme '{}', expected one of: {}",
+                parsed.scheme(),
+                SUPPORTED_SCHEMES.join(", ")
+            ));
+        }
+        if parsed.host_str().is_none() {
+            return Err(anyhow!("Proxy URL '{}' has no host", url));
+        }
+
+        Ok(Self {
+            url,
+            no_proxy: no_proxy.map(parse_no_proxy).unwrap_or_default(),
+        })
+    }
+
+    /// Builds the configuration from the standard proxy environment variables, if any is set.
+    pub fn from_env() -> Option<Self> {
+        let url = env_var(&["HTTPS_PROXY", "https_proxy", "ALL_PROXY", "all_proxy", "HTTP_PROXY", "http_proxy"])?;
+        let no_proxy = env_var(&["NO_PROXY", "no_proxy"]);
+        match Self::new(&url, no_proxy.as_deref()) {
+            Ok(config) => Some(config),
+            Err(e) => {
+                warn!("Ignoring proxy from the environment: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Builds the configuration from the settings, falling back to the environment.
+    ///
+    /// The `no_proxy` setting adds to the hosts of the `NO_PROXY` environment variable, for a
+    /// proxy from the settings as well as for one taken from the environment.
+    pub fn from_settings(settings: &Settings) -> Result<Option<Self>> {
+        Self::from_settings_and_env(settings, Self::from_env(), env_var(&["NO_PROXY", "no_proxy"]))
+    }
+
+    fn from_settings_and_env(
+        settings: &Settings,
+        env_config: Option<Self>,
+        env_no_proxy: Option<String>,
+    ) -> Result<Option<Self>> {
+        let configured = settings.proxy.as_deref().filter(|p| !p.trim().is_empty());
+        let mut config = match configured {
+            Some(url) => Some(Self::new(url, env_no_proxy.as_deref())?),
+            None => env_config,
+        };
+        if let (Some(config), Some(no_proxy)) = (config.as_mut(), settings.no_proxy.as_deref()) {
+            for entry in parse_no_proxy(no_proxy) {
+                if !config.no_proxy.contains(&entry) {
+                    config.no_proxy.push(entry);
+                }
+            }
+        }
+        Ok(config)
+    }
+
+    /// The proxy URL.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// The hosts that bypass the proxy.
+    pub fn no_proxy(&self) -> &[String] {
+        &self.no_proxy
+    }
+
+    /// Returns true if requests to `target_url` must not go through the proxy.
+    pub fn bypasses(&self, target_url: &str) -> bool {
+        let host = match url::Url::parse(target_url)
+            .ok()
+            .and_then(|u| u.host_str().map(|h| h.trim_matches(&['[', ']'][..]).to_ascii_lowercase()))
+        {
+            Some(host) => host,
+            None => return false,
+        };
+        self.no_proxy.iter().any(|entry| {
+            entry == "*" || host == *entry || host.ends_with(&format!(".{}", entry))
+        })
+    }
+
+    /// Returns the proxy URL to use for `target_url`, or `None` for hosts listed in `no_proxy`
+    /// and for transports that can not be proxied (ssh, local paths).
+    pub fn proxy_for(&self, target_url: &str) -> Option<&str> {
+        let is_http = target_url.starts_with("http://") || target_url.starts_with("https://");
+        if !is_http || self.bypasses(target_url) {
+            return None;
+        }
+        Some(&self.url)
+    }
+
+    /// Environment variables understood by pip (and any other child process using requests
+    /// or libcurl).
+    pub fn subprocess_env(&self) -> Vec<(&'static str, String)> {
+        let mut env = vec![
+            ("HTTP_PROXY", self.url.clone()),
+            ("HTTPS_PROXY", self.url.clone()),
+            ("http_proxy", self.url.clone()),
+            ("https_proxy", self.url.clone()),
+        ];
+        if !self.no_proxy.is_empty() {
+            env.push(("NO_PROXY", self.no_proxy.join(",")));
+            env.push(("no_proxy", self.no_proxy.join(",")));
+        }
+        env
+    }
+
+    fn reqwest_proxy(&self) -> Option<reqwest::Proxy> {
+        match reqwest::Proxy::all(&self.url) {
+            Ok(proxy) => Some(proxy.no_proxy(reqwest::NoProxy::from_string(&self.no_proxy.join(",")))),
+            Err(e) => {
+                warn!("Failed to configure proxy {}: {}", self.url, e);
+                None
+            }
+        }
+    }
+}
+
+fn env_var(names: &[&str]) -> Option<String> {
+    names
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|value| !value.trim().is_empty())
+}
+
+fn parse_no_proxy(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|entry| entry.trim().to_ascii_lowercase())
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            // Port suffixes are ignored, the proxy is bypassed for the whole host.
+            let entry = match entry.rsplit_once(':') {
+                Some((host, port)) if !host.contains(':') && port.chars().all(|c| c.is_ascii_digit()) => {
+                    host.to_string()
+                }
+                _ => entry.clone(),
+            };
+            entry
+                .trim_start_matches("*.")
+                .trim_start_matches('.')
+                .trim_matches(&['[', ']'][..])
+                .to_string()
+        })
+        .collect()
+}
+
+/// Makes the proxy from `settings` (or the environment) the one used by all network access.
+pub fn configure(settings: &Settings) -> Result<()> {
+    let config = ProxyConfig::from_settings(settings)?;
+    if let Some(config) = &config {
+        // Only the host is logged, the URL may carry credentials.
+        let host = url::Url::parse(&config.url)
+            .ok()
+            .and_then(|u| u.host_str().map(str::to_string))
+            .unwrap_or_default();
+        debug!("Using proxy at {} (no_proxy: {:?})", host, config.no_proxy);
+    }
+    match ACTIVE_PROXY.write() {
+        Ok(mut active) => *active = config,
+        Err(e) => *e.into_inner() = config,
+    }
+    Ok(())
+}
+
+/// The proxy currently in use, if any.
+pub fn active() -> Option<ProxyConfig> {
+    match ACTIVE_PROXY.read() {
+        Ok(active) => active.clone(),
+        Err(e) => e.into_inner().clone(),
+    }
+}
+
+/// A `reqwest::ClientBuilder` routed through the active proxy and trusting the configured CA
+/// bundle (see [`crate::tls`]).
+pub fn client_builder() -> reqwest::ClientBuilder {
+    let builder = crate::tls::add_root_certificates(reqwest::Client::builder());
+    match active().and_then(|p| p.reqwest_proxy()) {
+        Some(proxy) => builder.proxy(proxy),
+        None => builder,
+    }
+}
+
+/// A `reqwest::blocking::ClientBuilder` routed through the active proxy and trusting the
+/// configured CA bundle.
+pub fn blocking_client_builder() -> reqwest::blocking::ClientBuilder {
+    let builder = crate::tls::add_root_certificates_blocking(reqwest::blocking::Client::builder());
+    match active().and_then(|p| p.reqwest_proxy()) {
+        Some(proxy) => builder.proxy(proxy),
+        None => builder,
+    }
+}
+
+/// The proxy URL to use for `target_url`, see [`ProxyConfig::proxy_for`].
+pub fn proxy_for(target_url: &str) -> Option<String> {
+    active().and_then(|p| p.proxy_for(target_url).map(str::to_string))
+}
+
+/// Proxy environment variables for child processes such as pip.
+pub fn subprocess_env() -> Vec<(&'static str, String)> {
+    active().map(|p| p.subprocess_env()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_proxy_config_validates_scheme() {
+        assert!(ProxyConfig::new("http://proxy.example.com:3128", None).is_ok());
+        assert!(ProxyConfig::new("socks5h://127.0.0.1:1080", None).is_ok());
+        assert_eq!(
+            ProxyConfig::new("proxy.example.com:3128", None).unwrap().url(),
+            "http://proxy.example.com:3128"
+        );
+        assert!(ProxyConfig::new("ftp://proxy.example.com", None).is_err());
+    }
+
+    #[test]
+    fn test_proxy_config_no_proxy_matching() {
+        let config = ProxyConfig::new(
+            "http://proxy.example.com:3128",
+            Some("localhost, .internal.example.com,dl.espressif.com:443"),
+        )
+        .unwrap();
+
+        assert_eq!(config.proxy_for("https://github.com/espressif/esp-idf"), Some("http://proxy.example.com:3128"));
+        assert_eq!(config.proxy_for("http://localhost:8080/tools.json"), None);
+        assert_eq!(config.proxy_for("https://git.internal.example.com/esp-idf.git"), None);
+        assert_eq!(config.proxy_for("https://dl.espressif.com/dl/esp-idf/"), None);
+        assert_eq!(config.proxy_for("https://notdl.espressif.com/"), Some("http://proxy.example.com:3128"));
+        assert_eq!(config.proxy_for("git@github.com:espressif/esp-idf.git"), None);
+
+        let all = ProxyConfig::new("socks5://127.0.0.1:1080", Some("*")).unwrap();
+        assert_eq!(all.proxy_for("https://github.com"), None);
+    }
+
+    #[test]
+    fn test_no_proxy_setting_adds_to_the_environment() {
+        let settings = Settings {
+            proxy: Some("http://proxy.example.com:3128".to_string()),
+            no_proxy: Some("localhost,git.example.com".to_string()),
+            ..Settings::default()
+        };
+        let env_no_proxy = Some("dl.example.com,localhost".to_string());
+
+        let config = ProxyConfig::from_settings_and_env(&settings, None, env_no_proxy.clone())
+            .unwrap()
+            .unwrap();
+        assert_eq!(config.no_proxy(), ["dl.example.com", "localhost", "git.example.com"]);
+
+        // The same hosts are bypassed when the proxy comes from the environment
+        let from_env = ProxyConfig::new("http://env-proxy.example.com:8080", env_no_proxy.as_deref()).unwrap();
+        let settings = Settings {
+            proxy: None,
+            ..settings
+        };
+        let config = ProxyConfig::from_settings_and_env(&settings, Some(from_env), env_no_proxy)
+            .unwrap()
+            .unwrap();
+        assert_eq!(config.url(), "http://env-proxy.example.com:8080");
+        assert_eq!(config.no_proxy(), ["dl.example.com", "localhost", "git.example.com"]);
+    }
+}
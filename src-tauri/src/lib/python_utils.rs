@@ -24,7 +24,7 @@ use std::{
 use vm::{builtins::PyStrRef, Interpreter};
 
 use crate::{
-    command_executor::{self, execute_command_direct}, download_file, ensure_path, replace_unescaped_spaces_posix, replace_unescaped_spaces_win, settings::VersionPaths, utils::{parse_cmake_version, remove_after_second_dot, GenericCheckResult}
+    command_executor::{self, execute_command_direct}, download_file, ensure_path, replace_unescaped_spaces_posix, replace_unescaped_spaces_win, settings::VersionPaths, utils::{parse_cmake_version, remove_after_second_dot, GenericCheckResult, MirrorFailover}
 };
 
 /// Identifies which Python sanity check a [`GenericCheckResult`] belongs to.
@@ -347,6 +347,10 @@ pub fn pip_install_requirements(
     };
 
     const ESPRESSIF_PYPI: &str = "https://dl.espressif.com/pypi/";
+    let proxy_env = crate::proxy::subprocess_env();
+    let mut env = vec![("VIRTUAL_ENV", venv_path.to_str().unwrap())];
+    env.extend(proxy_env.iter().map(|(key, value)| (*key, value.as_str())));
+    let ca_bundle = crate::tls::active_bundle_path();
 
     match std::env::consts::OS {
         "windows" => {
@@ -361,7 +365,7 @@ pub fn pip_install_requirements(
                 command_executor::execute_command_direct_with_env(
                     python_location.to_str().unwrap(),
                     &args,
-                    vec![("VIRTUAL_ENV", venv_path.to_str().unwrap())],
+                    env.clone(),
                 )
             } else {
                 let mut args = vec![
@@ -380,11 +384,15 @@ pub fn pip_install_requirements(
                   args.push("--index-url");
                   args.push(ESPRESSIF_PYPI);
                 }
+                if let Some(ca_bundle) = &ca_bundle {
+                  args.push("--cert");
+                  args.push(ca_bundle.to_str().unwrap());
+                }
 
                 command_executor::execute_command_direct_with_env(
                     python_location.to_str().unwrap(),
                     &args,
-                    vec![("VIRTUAL_ENV", venv_path.to_str().unwrap())],
+                    env.clone(),
                 )
             } {
                 Ok(out) => {
@@ -394,7 +402,7 @@ pub fn pip_install_requirements(
                     let error_msg = String::from_utf8_lossy(&out.stderr).to_string();
                     Err(std::io::Error::new(
                       std::io::ErrorKind::Other,
-                      error_msg,
+                      crate::tls::with_certificate_hint(error_msg),
                     ))
                   }
                 }
@@ -416,32 +424,38 @@ pub fn pip_install_requirements(
                           shlex::quote(wheel_dir.to_str().unwrap())
                       ),
                   ],
-                  vec![("VIRTUAL_ENV", venv_path.to_str().unwrap())],
+                  env.clone(),
                 )
             } else {
+                let cert_arg = ca_bundle
+                  .as_ref()
+                  .map(|p| format!(" --cert {}", shlex::quote(p.to_str().unwrap())))
+                  .unwrap_or_default();
                 let cmd = if let Some(mirror_url) = pypi_mirror {
                   format!(
-                    "{} -m pip install -r {} --upgrade --constraint {} --prefer-binary --index-url {} --extra-index-url {}",
+                    "{} -m pip install -r {} --upgrade --constraint {} --prefer-binary --index-url {} --extra-index-url {}{}",
                     shlex::quote(python_location.to_str().unwrap()),
                     shlex::quote(requirements_file.to_str().unwrap()),
                     shlex::quote(constrain_path),
                     shlex::quote(ESPRESSIF_PYPI),
                     shlex::quote(mirror_url),
+                    cert_arg,
                   )
                 } else {
                   format!(
-                    "{} -m pip install -r {} --upgrade --constraint {} --prefer-binary --index-url {}",
+                    "{} -m pip install -r {} --upgrade --constraint {} --prefer-binary --index-url {}{}",
                     shlex::quote(python_location.to_str().unwrap()),
                     shlex::quote(requirements_file.to_str().unwrap()),
                     shlex::quote(constrain_path),
                     shlex::quote(ESPRESSIF_PYPI),
+                    cert_arg,
                   )
                 };
 
                 command_executor::execute_command_direct_with_env(
                     "bash",
                     &vec!["-c", &cmd],
-                    vec![("VIRTUAL_ENV", venv_path.to_str().unwrap())],
+                    env.clone(),
                 )
             } {
                 Ok(out) => {
@@ -454,7 +468,9 @@ pub fn pip_install_requirements(
                     } else {
                         Err(std::io::Error::new(
                             std::io::ErrorKind::Other,
-                            std::str::from_utf8(&out.stderr).unwrap().to_string(),
+                            crate::tls::with_certificate_hint(
+                                std::str::from_utf8(&out.stderr).unwrap().to_string(),
+                            ),
                         ))
                     }
                 }
@@ -567,11 +583,15 @@ fn find_wheel_directory(offline_archive_dir: &Path, python_version: &str) -> Opt
 ///   These correspond to files like `requirements_esp_gh_action.txt`.
 /// * `offline_archive_dir` - Optional path to offline archive directory containing
 ///   pre-downloaded wheels and constraints files.
+/// * `pypi_mirror` - Optional PyPI mirror to install the packages from.
+/// * `pypi_failover` - Optional list of PyPI mirrors to move on to when installing a requirements
+///   file fails on the current mirror. Not used in offline mode.
 ///
 /// # Returns
 ///
-/// A `Result<(), String>` which is:
-/// - `Ok(())` if the Python environment was installed or updated successfully.
+/// A `Result<Option<String>, String>` which is:
+/// - `Ok(mirror)` if the Python environment was installed or updated successfully, with the
+///   PyPI mirror the packages were finally installed from.
 /// - `Err(String)` if any step of the installation process fails, containing
 ///   a descriptive error message.
 ///
@@ -590,8 +610,9 @@ pub async fn install_python_env(
     reinstall: bool,
     features: &[String],
     offline_archive_dir: Option<&Path>,
-    pypi_mirror: &Option<String>
-) -> Result<(), String> {
+    pypi_mirror: &Option<String>,
+    pypi_failover: Option<&MirrorFailover>,
+) -> Result<Option<String>, String> {
     let mut offline_mode = false;
     let venv_path = paths.python_venv_path.clone();
 
@@ -717,26 +738,45 @@ pub async fn install_python_env(
         None
     };
 
-    // install the requirements from files
+    // install the requirements from files, once a mirror fails the next one is used for the rest
+    let mut mirror = pypi_mirror.clone();
+    let mut tried: Vec<String> = Vec::new();
     for requirements_file in requirements_file_list {
-        match pip_install_requirements(&venv_path, &requirements_file, &constraint_file, &wheel_dir, pypi_mirror) {
-            Ok(_) => {
-                debug!("requirements installed: {}", requirements_file.display());
-            }
-            Err(e) => {
+        loop {
+            let error = match pip_install_requirements(&venv_path, &requirements_file, &constraint_file, &wheel_dir, &mirror) {
+                Ok(_) => {
+                    debug!("requirements installed: {}", requirements_file.display());
+                    break;
+                }
+                Err(e) => e,
+            };
+            tried.push(mirror.clone().unwrap_or_else(|| "https://pypi.org/simple".to_string()));
+            let next_mirror = match pypi_failover {
+                Some(failover) if !offline_mode => failover.next_mirror(&tried).await,
+                _ => None,
+            };
+            let Some(next_mirror) = next_mirror else {
                 error!(
                     "failed to install requirements from file {:?}: {}",
-                    requirements_file, e
+                    requirements_file, error
                 );
                 return Err(format!(
                     "failed to install requirements from file {:?}: {}",
-                    requirements_file, e
+                    requirements_file, error
                 ));
-            }
+            };
+            warn!(
+                "failed to install requirements from file {:?} using {:?}: {}. Trying mirror {}",
+                requirements_file, mirror, error, next_mirror
+            );
+            mirror = Some(next_mirror);
         }
     }
-    info!("Python environment installed successfully");
-    Ok(())
+    match &mirror {
+        Some(mirror) if !offline_mode => info!("Python environment installed successfully from {}", mirror),
+        _ => info!("Python environment installed successfully"),
+    }
+    Ok(if offline_mode { None } else { mirror })
 }
 
 /// Runs the IDF tools Python installation script.
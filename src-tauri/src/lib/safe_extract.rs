@@ -0,0 +1,355 @@
+//! Rules every extracted archive entry has to follow.
+//!
+//! Archives come from mirrors and from offline installers passed around by hand, so their entries
+//! are not trusted. An entry is rejected when its path is absolute or contains `..`, when it is a
+//! symlink or hard link pointing outside of the destination, or when a link target walks back up
+//! through a symlink extracted earlier. Rejected entries are skipped, the rest of the archive is
+//! still extracted, and the extraction then fails with [`DecompressionError::UnsafeEntries`]
+//! listing each of them. The total size of the extracted files is capped by
+//! [`MAX_EXTRACTED_SIZE`].
+
+use std::collections::HashSet;
+use std::fmt;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+use log::warn;
+use tar::Archive;
+
+use crate::DecompressionError;
+
+/// Upper limit for the total size of the files extracted from one archive (64 GiB). Offline
+/// installers are the largest archives and stay well below it.
+pub const MAX_EXTRACTED_SIZE: u64 = 64 * 1024 * 1024 * 1024;
+
+/// Why an entry was rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    /// The entry path contains a `..` component.
+    ParentTraversal,
+    /// The entry path is absolute.
+    AbsolutePath,
+    /// The symlink or hard link points outside of the destination.
+    LinkEscapes(String),
+    /// The link target walks up (`..`) through a symlink extracted earlier.
+    LinkThroughSymlink(String),
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::ParentTraversal => write!(f, "path contains '..'"),
+            Violation::AbsolutePath => write!(f, "absolute path"),
+            Violation::LinkEscapes(target) => {
+                write!(f, "link to '{}' points outside of the destination", target)
+            }
+            Violation::LinkThroughSymlink(target) => {
+                write!(f, "link to '{}' leaves a directory through a symlink", target)
+            }
+        }
+    }
+}
+
+/// An archive entry that was not extracted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnsafeEntry {
+    pub path: String,
+    pub violation: Violation,
+}
+
+impl fmt::Display for UnsafeEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.path, self.violation)
+    }
+}
+
+/// Tracks the entries of one archive while it is extracted.
+pub struct Guard {
+    max_size: u64,
+    extracted: u64,
+    symlinks: HashSet<PathBuf>,
+    rejected: Vec<UnsafeEntry>,
+}
+
+impl Guard {
+    pub fn new(max_size: u64) -> Self {
+        Self {
+            max_size,
+            extracted: 0,
+            symlinks: HashSet::new(),
+            rejected: Vec::new(),
+        }
+    }
+
+    /// Returns the entry path relative to the destination, or `None` if the entry must be
+    /// skipped.
+    pub fn check_path(&mut self, path: &Path) -> Option<PathBuf> {
+        match relative_path(path) {
+            Ok(relative) => Some(relative),
+            Err(violation) => {
+                self.reject(path, violation);
+                None
+            }
+        }
+    }
+
+    /// Returns true if the symlink at `path` (relative to the destination) pointing to `target`
+    /// stays inside the destination.
+    pub fn check_symlink(&mut self, path: &Path, target: &Path) -> bool {
+        let escapes = || Violation::LinkEscapes(target.display().to_string());
+        let mut resolved = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        for component in target.components() {
+            match component {
+                Component::CurDir => {}
+                Component::Normal(part) => resolved.push(part),
+                Component::ParentDir => {
+                    // `..` is only resolved lexically if no directory walked through so far is
+                    // a symlink, which may point anywhere in the destination
+                    if resolved.ancestors().any(|dir| self.symlinks.contains(dir)) {
+                        self.reject(
+                            path,
+                            Violation::LinkThroughSymlink(target.display().to_string()),
+                        );
+                        return false;
+                    }
+                    if !resolved.pop() {
+                        self.reject(path, escapes());
+                        return false;
+                    }
+                }
+                Component::RootDir | Component::Prefix(_) => {
+                    self.reject(path, escapes());
+                    return false;
+                }
+            }
+        }
+        self.symlinks.insert(path.to_path_buf());
+        true
+    }
+
+    /// Returns true if the hard link at `path` to `target` (both relative to the destination)
+    /// stays inside the destination.
+    pub fn check_hardlink(&mut self, path: &Path, target: &Path) -> bool {
+        if relative_path(target).is_err() {
+            self.reject(path, Violation::LinkEscapes(target.display().to_string()));
+            return false;
+        }
+        true
+    }
+
+    /// Accounts for `bytes` more extracted data, failing once the limit is exceeded.
+    pub fn add_size(&mut self, bytes: u64) -> Result<(), DecompressionError> {
+        self.extracted = self.extracted.saturating_add(bytes);
+        if self.extracted > self.max_size {
+            return Err(DecompressionError::SizeLimitExceeded(self.max_size));
+        }
+        Ok(())
+    }
+
+    /// How many bytes may still be extracted before the limit is exceeded.
+    pub fn remaining(&self) -> u64 {
+        self.max_size.saturating_sub(self.extracted)
+    }
+
+    /// Fails with the list of rejected entries, if there are any.
+    pub fn finish(self) -> Result<(), DecompressionError> {
+        if self.rejected.is_empty() {
+            Ok(())
+        } else {
+            Err(DecompressionError::UnsafeEntries(self.rejected))
+        }
+    }
+
+    fn reject(&mut self, path: &Path, violation: Violation) {
+        let entry = UnsafeEntry {
+            path: path.display().to_string(),
+            violation,
+        };
+        warn!("Refusing to extract archive entry {}", entry);
+        self.rejected.push(entry);
+    }
+}
+
+fn relative_path(path: &Path) -> Result<PathBuf, Violation> {
+    let mut relative = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::Normal(part) => relative.push(part),
+            Component::ParentDir => return Err(Violation::ParentTraversal),
+            Component::RootDir | Component::Prefix(_) => return Err(Violation::AbsolutePath),
+        }
+    }
+    Ok(relative)
+}
+
+/// Unpacks the tar archive read from `reader` into `destination`, see the module documentation
+/// for the rules applied to its entries.
+pub fn unpack_tar<R: Read>(reader: R, destination: &Path) -> Result<(), DecompressionError> {
+    unpack_tar_with_limit(reader, destination, MAX_EXTRACTED_SIZE)
+}
+
+/// [`unpack_tar`] with a custom limit for the total extracted size.
+pub fn unpack_tar_with_limit<R: Read>(
+    reader: R,
+    destination: &Path,
+    max_size: u64,
+) -> Result<(), DecompressionError> {
+    std::fs::create_dir_all(destination)?;
+    // Same as tar's own `Archive::unpack`, links are validated against the canonical path
+    let destination = destination
+        .canonicalize()
+        .unwrap_or_else(|_| destination.to_path_buf());
+    let mut guard = Guard::new(max_size);
+    let mut archive = Archive::new(reader);
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        let Some(relative) = guard.check_path(&path) else {
+            continue;
+        };
+
+        let entry_type = entry.header().entry_type();
+        if entry_type.is_symlink() || entry_type.is_hard_link() {
+            let target = entry
+                .link_name()?
+                .map(|target| target.into_owned())
+                .unwrap_or_default();
+            let accepted = if entry_type.is_symlink() {
+                guard.check_symlink(&relative, &target)
+            } else {
+                guard.check_hardlink(&relative, &target)
+            };
+            if !accepted {
+                continue;
+            }
+        }
+
+        guard.add_size(entry.size())?;
+        entry.unpack_in(&destination)?;
+    }
+
+    guard.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use crate::test_fixtures::crafted_tar;
+    use tar::EntryType;
+
+    fn rejected_paths(result: Result<(), DecompressionError>) -> Vec<String> {
+        match result {
+            Err(DecompressionError::UnsafeEntries(entries)) => {
+                entries.into_iter().map(|entry| entry.path).collect()
+            }
+            other => panic!("expected unsafe entries, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_tar_traversal_and_absolute_paths_are_rejected() {
+        let root = tempfile::TempDir::new().unwrap();
+        let destination = root.path().join("dest");
+        let archive = crafted_tar(&[
+            ("ok.txt", EntryType::Regular, "", b"ok"),
+            ("../evil.txt", EntryType::Regular, "", b"evil"),
+            ("sub/../../evil2.txt", EntryType::Regular, "", b"evil"),
+            ("/tmp/eim-absolute-evil.txt", EntryType::Regular, "", b"evil"),
+        ]);
+
+        let rejected = rejected_paths(unpack_tar(&archive[..], &destination));
+
+        assert_eq!(
+            rejected,
+            vec!["../evil.txt", "sub/../../evil2.txt", "/tmp/eim-absolute-evil.txt"]
+        );
+        assert_eq!(std::fs::read_to_string(destination.join("ok.txt")).unwrap(), "ok");
+        assert!(!root.path().join("evil.txt").exists());
+        assert!(!root.path().join("evil2.txt").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_tar_links_must_stay_inside_destination() {
+        let root = tempfile::TempDir::new().unwrap();
+        let destination = root.path().join("dest");
+        let archive = crafted_tar(&[
+            ("bin/tool", EntryType::Regular, "", b"tool"),
+            ("bin/alias", EntryType::Symlink, "tool", b""),
+            ("lib/up", EntryType::Symlink, "../bin/tool", b""),
+            ("escape", EntryType::Symlink, "../outside", b""),
+            ("absolute", EntryType::Symlink, "/etc/passwd", b""),
+            ("here", EntryType::Symlink, ".", b""),
+            ("sneaky", EntryType::Symlink, "here/..", b""),
+            ("hard", EntryType::Link, "../outside", b""),
+        ]);
+
+        let rejected = rejected_paths(unpack_tar(&archive[..], &destination));
+
+        assert_eq!(rejected, vec!["escape", "absolute", "sneaky", "hard"]);
+        assert_eq!(std::fs::read_to_string(destination.join("bin/alias")).unwrap(), "tool");
+        assert_eq!(std::fs::read_to_string(destination.join("lib/up")).unwrap(), "tool");
+        assert!(destination.join("here").is_symlink());
+        assert!(!destination.join("escape").exists());
+        assert!(!destination.join("sneaky").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_tar_link_cannot_walk_up_below_a_symlink() {
+        let root = tempfile::TempDir::new().unwrap();
+        let destination = root.path().join("dest");
+        // `x/x/x` resolves to the destination itself, so `..` leaves it
+        let archive = crafted_tar(&[
+            ("x", EntryType::Symlink, ".", b""),
+            ("y", EntryType::Symlink, "x/x/x/..", b""),
+        ]);
+
+        let rejected = rejected_paths(unpack_tar(&archive[..], &destination));
+
+        assert_eq!(rejected, vec!["y"]);
+        assert!(destination.join("x").is_symlink());
+        assert!(!destination.join("y").exists());
+    }
+
+    #[test]
+    fn test_tar_size_limit() {
+        let root = tempfile::TempDir::new().unwrap();
+        let archive = crafted_tar(&[
+            ("a.bin", EntryType::Regular, "", &[0u8; 600]),
+            ("b.bin", EntryType::Regular, "", &[0u8; 600]),
+        ]);
+
+        let result = unpack_tar_with_limit(&archive[..], root.path(), 1000);
+
+        assert!(matches!(result, Err(DecompressionError::SizeLimitExceeded(1000))));
+        assert!(!root.path().join("b.bin").exists());
+    }
+
+    #[test]
+    fn test_zip_traversal_is_rejected() {
+        let root = tempfile::TempDir::new().unwrap();
+        let zip_path = root.path().join("driver.zip");
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(&zip_path).unwrap());
+        let options = zip::write::FileOptions::<()>::default()
+            .compression_method(zip::CompressionMethod::Stored);
+        for name in ["driver.inf", "../evil.dll", "/abs/evil.dll"] {
+            zip.start_file(name, options).unwrap();
+            zip.write_all(b"content").unwrap();
+        }
+        zip.finish().unwrap();
+
+        let destination = root.path().join("dest");
+        let result = crate::decompress_archive(
+            zip_path.to_str().unwrap(),
+            destination.to_str().unwrap(),
+        );
+
+        assert_eq!(rejected_paths(result), vec!["../evil.dll", "/abs/evil.dll"]);
+        assert!(destination.join("driver.inf").exists());
+        assert!(!root.path().join("evil.dll").exists());
+    }
+}
@@ -55,6 +55,24 @@ pub struct Settings {
     pub activation_script_path_override: Option<String>, // Optional override for activation script path
     pub python_version_override: Option<String>, // Optional override for Python version to install when installing prerequisites
     pub create_bat_activation_script: Option<bool>, // Whether to create a .bat activation script on Windows
+    pub max_concurrent_downloads: Option<usize>, // How many tools are downloaded in parallel
+    pub download_cache_dir: Option<PathBuf>, // Shared content-addressed cache of tool archives
+    pub download_cache_max_size_mb: Option<u64>, // Size limit of the download cache, least recently used archives are pruned first
+    pub tool_store_dir: Option<PathBuf>, // Opt-in store of extracted tools shared by all installations
+    pub tool_store_link_mode: Option<String>, // How installations reference the tool store: "symlink" or "hardlink"
+    pub proxy: Option<String>, // Proxy for all network access (http, https, socks5 or socks5h URL), defaults to the proxy environment variables
+    pub no_proxy: Option<String>, // Comma separated hosts that are reached without the proxy
+    pub ca_bundle: Option<PathBuf>, // Extra PEM bundle of trusted root certificates, for networks that intercept TLS
+    pub max_download_rate: Option<u64>, // Download bandwidth limit in KiB/s shared by all transfers, unset or 0 means unlimited
+    pub stream_tool_extraction: Option<bool>, // Extract tool archives while they are downloaded instead of writing them to disk first
+    pub tool_versions: Option<HashMap<String, String>>, // Tool versions used instead of the ones tools.json recommends, e.g. after `eim tools upgrade`
+    pub tools_overlay: Option<PathBuf>, // File in the tools.json format whose tools are added to the IDF's tools.json or replace stock ones
+    pub tool_pins: Option<HashMap<String, String>>, // Tool versions pinned by the user, taking precedence over tools.json and `tool_versions`
+    pub host_platform: Option<String>, // tools.json platform to prepare tools for instead of the running host, e.g. "linux-arm64"
+    pub git_object_store: Option<bool>, // Clone ESP-IDF with a bare repository in the eim data dir as git alternate, shared by all clones
+    pub idf_blobless_clone: Option<bool>, // Partial shallow clone of ESP-IDF (filter=blob:none): file contents are fetched on checkout only
+    pub idf_sparse_exclude: Option<Vec<String>>, // Directories of ESP-IDF left out of the checkout, e.g. ["docs", "examples"]
+    pub idf_patches: Option<Vec<String>>, // Patch files or directories of patches applied to the ESP-IDF checkout, in order
 }
 
 #[derive(Debug, Clone)]
@@ -133,6 +151,24 @@ impl Default for Settings {
             activation_script_path_override: Some(default_activation_script_path_override),
             python_version_override: Some(PYTHON_NAME_TO_INSTALL.to_string()),
             create_bat_activation_script: Some(false),
+            max_concurrent_downloads: Some(crate::idf_tools::DEFAULT_MAX_CONCURRENT_DOWNLOADS),
+            download_cache_dir: None,
+            download_cache_max_size_mb: Some(crate::download_cache::DEFAULT_DOWNLOAD_CACHE_MAX_SIZE_MB),
+            tool_store_dir: None,
+            tool_store_link_mode: Some("symlink".to_string()),
+            proxy: None,
+            no_proxy: None,
+            ca_bundle: None,
+            max_download_rate: None,
+            stream_tool_extraction: Some(false),
+            tool_versions: None,
+            tools_overlay: None,
+            tool_pins: None,
+            host_platform: None,
+            git_object_store: Some(false),
+            idf_blobless_clone: Some(false),
+            idf_sparse_exclude: None,
+            idf_patches: None,
         }
     }
 }
@@ -240,7 +276,24 @@ impl Settings {
             use_local_archive,
             activation_script_path_override,
             python_version_override,
-            create_bat_activation_script
+            create_bat_activation_script,
+            max_concurrent_downloads,
+            download_cache_dir,
+            download_cache_max_size_mb,
+            tool_store_dir,
+            tool_store_link_mode,
+            proxy,
+            no_proxy,
+            ca_bundle,
+            max_download_rate,
+            stream_tool_extraction,
+            tools_overlay,
+            tool_pins,
+            host_platform,
+            git_object_store,
+            idf_blobless_clone,
+            idf_sparse_exclude,
+            idf_patches
           );
 
           if cli_overrides.contains("idf_tools") {
@@ -286,7 +339,21 @@ impl Settings {
 
     pub fn load(&mut self, config_path: &str) -> Result<()> {
         let config_string = std::fs::read_to_string(config_path)?;
-        let loaded_settings = toml::from_str::<Settings>(&config_string)?;
+        let mut loaded_settings = toml::from_str::<Settings>(&config_string)?;
+        // Patches and the tools overlay listed in a config file are relative to the file, not to
+        // the working directory. They are stored with the installation, so they are made absolute
+        // for `eim update`, `eim fix` and `eim tools` to find them too.
+        let config_dir = Path::new(config_path).parent().unwrap_or(Path::new(""));
+        if let Some(patches) = loaded_settings.idf_patches.as_mut() {
+            for entry in patches.iter_mut().filter(|entry| Path::new(entry.as_str()).is_relative()) {
+                *entry = std::path::absolute(config_dir.join(entry.as_str()))?
+                    .to_string_lossy()
+                    .into_owned();
+            }
+        }
+        if let Some(overlay) = loaded_settings.tools_overlay.as_mut().filter(|overlay| overlay.is_relative()) {
+            *overlay = std::path::absolute(config_dir.join(&*overlay))?;
+        }
         self.merge_from(loaded_settings);
 
         Ok(())
@@ -325,7 +392,25 @@ impl Settings {
             use_local_archive,
             activation_script_path_override,
             python_version_override,
-            create_bat_activation_script
+            create_bat_activation_script,
+            max_concurrent_downloads,
+            download_cache_dir,
+            download_cache_max_size_mb,
+            tool_store_dir,
+            tool_store_link_mode,
+            proxy,
+            no_proxy,
+            ca_bundle,
+            max_download_rate,
+            stream_tool_extraction,
+            tool_versions,
+            tools_overlay,
+            tool_pins,
+            host_platform,
+            git_object_store,
+            idf_blobless_clone,
+            idf_sparse_exclude,
+            idf_patches
         );
     }
 
@@ -360,6 +445,16 @@ impl Settings {
                                 return val == def;
                             }
                         }
+                        if let Some(val) = value.downcast_ref::<Option<usize>>() {
+                            if let Some(def) = default_value.downcast_ref::<Option<usize>>() {
+                                return val == def;
+                            }
+                        }
+                        if let Some(val) = value.downcast_ref::<Option<u64>>() {
+                            if let Some(def) = default_value.downcast_ref::<Option<u64>>() {
+                                return val == def;
+                            }
+                        }
                         false // Return false if types don't match or can't be compared
                     })
                     .unwrap_or(false)
@@ -367,12 +462,39 @@ impl Settings {
             .unwrap_or(false)
     }
 
+    /// Encodes the settings as the `installation_config` of an installation in eim_idf.json.
+    ///
+    /// They are stored as JSON, so versions of eim with more or fewer settings can read each
+    /// other's installations; settings missing from the data take their defaults.
+    pub fn to_installation_config(&self) -> Result<Base64Bytes> {
+        let json = serde_json::to_vec(self)
+            .map_err(|e| anyhow!("Failed to serialize settings: {}", e))?;
+        Ok(Base64Bytes::new(json))
+    }
+
+    /// Decodes the `installation_config` of an installation, see
+    /// [`Settings::to_installation_config`].
+    ///
+    /// Installations made before the settings were stored as JSON hold them as bincode, which has
+    /// no field names: the settings added since are simply missing at the end of the data. All of
+    /// them are optional and bincode encodes `None` as a single zero byte, so the data is padded
+    /// with one zero byte per setting to read them as unset.
+    pub fn from_installation_config(bytes: &[u8]) -> Result<Self> {
+        // bincode data starts with the tag of the first optional setting, 0 or 1
+        if bytes.first() == Some(&b'{') {
+            return serde_json::from_slice(bytes)
+                .map_err(|e| anyhow!("Failed to read the stored settings: {}", e));
+        }
+        let mut padded = bytes.to_vec();
+        padded.resize(bytes.len() + Settings::default().iter().count(), 0);
+        bincode::deserialize(&padded)
+            .map_err(|e| anyhow!("Failed to read the stored settings: {}", e))
+    }
+
     pub fn save_esp_ide_json(&self) -> Result<()> {
         let mut idf_installations = Vec::new();
 
-        // Serialize the Settings itself to binary using bincode for installation_config
-        let settings_binary = bincode::serialize(self)
-            .map_err(|e| anyhow!("Failed to serialize settings: {}", e))?;
+        let installation_config = self.to_installation_config()?;
 
         if let Some(versions) = &self.idf_versions {
 
@@ -387,7 +509,9 @@ impl Settings {
                 python: paths.python_path.to_string_lossy().into_owned(),
                 idf_tools_path: paths.tool_install_directory.to_string_lossy().into_owned(),
                 activation_script: paths.activation_script.to_string_lossy().into_owned(),
-                installation_config: Some(Base64Bytes::new(settings_binary.clone())),
+                installation_config: Some(installation_config.clone()),
+                idf_commit: crate::git_tools::head_commit_id(&paths.idf_path),
+                idf_source: crate::idf_source::is_idf_source(version).then(|| version.clone()),
               });
             }
         }
@@ -461,7 +585,7 @@ impl Settings {
         // New installation
         let actual_version = match self.version_name {
           Some(ref name) => name.to_string(),
-          None => version.to_string(),
+          None => crate::idf_source::installation_name(version),
         };
         let version_installation_path = base_path.join(&actual_version);
         let idf_path = version_installation_path.join("esp-idf");
@@ -527,6 +651,14 @@ impl Settings {
     pub fn get_features_for_version(&self, version: &str) -> Vec<String> {
         self.get_features_for_version_if_set(version).unwrap_or_default()
     }
+    /// Tool versions to use instead of the ones tools.json recommends: the versions recorded by
+    /// `eim tools upgrade`, overridden by the user's pins.
+    pub fn chosen_tool_versions(&self) -> HashMap<String, String> {
+        let mut versions = self.tool_versions.clone().unwrap_or_default();
+        versions.extend(self.tool_pins.clone().unwrap_or_default());
+        versions
+    }
+
     /// Get tools for a version only if explicitly set (doesn't fall back to global)
     /// Used to check if we need to prompt for selection
     pub fn get_tools_for_version_if_set(&self, version: &str) -> Option<Vec<String>> {
@@ -551,3 +683,90 @@ impl Settings {
         self.get_tools_for_version_if_set(version).unwrap_or_default()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_installation_config_round_trip() {
+        let mut settings = Settings::default();
+        settings.idf_versions = Some(vec!["v5.5".to_string()]);
+        settings.tool_versions = Some(HashMap::from([("cmake".to_string(), "3.30.2".to_string())]));
+
+        let stored = settings.to_installation_config().unwrap();
+        let read = Settings::from_installation_config(stored.as_slice()).unwrap();
+
+        assert_eq!(read.idf_versions, settings.idf_versions);
+        assert_eq!(read.tool_versions, settings.tool_versions);
+        assert_eq!(read.max_concurrent_downloads, settings.max_concurrent_downloads);
+    }
+
+    #[test]
+    fn test_installation_config_of_released_eim_is_read() {
+        let mut released = Settings::default();
+        released.idf_versions = Some(vec!["v5.4".to_string()]);
+        released.mirror = Some("https://dl.espressif.cn/github_assets".to_string());
+        released.create_bat_activation_script = Some(true);
+        let blob = crate::test_fixtures::released_settings_blob(&released);
+
+        let read = Settings::from_installation_config(&blob).unwrap();
+
+        assert_eq!(read.idf_versions, released.idf_versions);
+        assert_eq!(read.mirror, released.mirror);
+        assert_eq!(read.create_bat_activation_script, Some(true));
+        // Settings added since are unset
+        assert_eq!(read.max_concurrent_downloads, None);
+        assert_eq!(read.tool_versions, None);
+        assert_eq!(read.idf_patches, None);
+
+        assert!(Settings::from_installation_config(b"\x07 not settings").is_err());
+    }
+
+    #[test]
+    fn test_tools_overlay_in_config_file_is_relative_to_it() {
+        let root = tempfile::TempDir::new().unwrap();
+        let config_path = root.path().join("project").join("eim_config.toml");
+        fs::create_dir_all(config_path.parent().unwrap()).unwrap();
+        fs::write(&config_path, "tools_overlay = \"overlay/tools.json\"\n").unwrap();
+
+        let mut settings = Settings::default();
+        settings.load(&config_path.to_string_lossy()).unwrap();
+
+        assert_eq!(
+            settings.tools_overlay,
+            Some(root.path().join("project").join("overlay").join("tools.json"))
+        );
+
+        let absolute = root.path().join("shared_overlay.json");
+        fs::write(&config_path, format!("tools_overlay = {:?}\n", absolute)).unwrap();
+        let mut settings = Settings::default();
+        settings.load(&config_path.to_string_lossy()).unwrap();
+
+        assert_eq!(settings.tools_overlay, Some(absolute));
+    }
+
+    #[test]
+    fn test_patches_in_config_file_are_relative_to_it() {
+        let root = tempfile::TempDir::new().unwrap();
+        let config_path = root.path().join("project").join("eim_config.toml");
+        fs::create_dir_all(config_path.parent().unwrap()).unwrap();
+        let absolute = root.path().join("shared.patch").to_string_lossy().to_string();
+        fs::write(
+            &config_path,
+            format!("idf_patches = [\"patches\", {:?}]\n", absolute),
+        )
+        .unwrap();
+
+        let mut settings = Settings::default();
+        settings.load(&config_path.to_string_lossy()).unwrap();
+
+        assert_eq!(
+            settings.idf_patches,
+            Some(vec![
+                root.path().join("project").join("patches").to_string_lossy().to_string(),
+                absolute,
+            ])
+        );
+    }
+}
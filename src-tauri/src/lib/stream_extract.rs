@@ -0,0 +1,253 @@
+//! Extraction of tool archives while they are being downloaded.
+//!
+//! Instead of writing the archive to `dist` and reading it back, the HTTP body is hashed and fed
+//! straight into the tar decoder, so neither the second pass over the archive nor the disk space
+//! for it is needed. The sha256 is checked once the whole body has been received; if it does not
+//! match, or anything else fails, the extracted directory is removed again.
+//!
+//! Only tar based archives can be streamed. zip archives keep their central directory at the end
+//! of the file, they are always downloaded first and extracted afterwards.
+
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::mpsc::Sender;
+
+use anyhow::{anyhow, Result};
+use log::warn;
+use sha2::{Digest, Sha256};
+use tokio::sync::mpsc;
+
+use crate::{DecompressionError, DownloadProgress};
+
+/// Number of chunks buffered between the download and the extraction.
+const PIPE_CAPACITY: usize = 32;
+
+const STREAMABLE_EXTENSIONS: [&str; 6] = [".tar", ".tar.gz", ".tgz", ".tar.xz", ".tar.bz2", ".tar.zst"];
+
+/// Returns true if the archive `filename` can be extracted while it is downloaded.
+pub fn supports_streaming(filename: &str) -> bool {
+    let name = filename.to_ascii_lowercase();
+    STREAMABLE_EXTENSIONS.iter().any(|ext| name.ends_with(ext))
+}
+
+/// Reading end of an in-memory pipe, fed with chunks by another task or thread.
+pub struct PipeReader {
+    rx: mpsc::Receiver<Vec<u8>>,
+    chunk: Vec<u8>,
+    pos: usize,
+}
+
+impl Read for PipeReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos >= self.chunk.len() {
+            match self.rx.blocking_recv() {
+                Some(chunk) => {
+                    self.chunk = chunk;
+                    self.pos = 0;
+                }
+                None => return Ok(0),
+            }
+        }
+        let read = buf.len().min(self.chunk.len() - self.pos);
+        buf[..read].copy_from_slice(&self.chunk[self.pos..self.pos + read]);
+        self.pos += read;
+        Ok(read)
+    }
+}
+
+fn pipe() -> (mpsc::Sender<Vec<u8>>, PipeReader) {
+    let (tx, rx) = mpsc::channel(PIPE_CAPACITY);
+    (
+        tx,
+        PipeReader {
+            rx,
+            chunk: Vec::new(),
+            pos: 0,
+        },
+    )
+}
+
+/// Unpacks the archive read from `reader` into `destination`. The format is detected by
+/// [`crate::archive_formats`], `filename` is only consulted when no signature matches.
+pub fn unpack_tar_stream<R: Read + Send + 'static>(
+    reader: R,
+    filename: &str,
+    destination: &Path,
+) -> Result<(), DecompressionError> {
+    std::fs::create_dir_all(destination)?;
+    crate::archive_formats::unpack_reader(Box::new(reader), filename, destination)
+}
+
+/// Downloads `url` and extracts it into `destination` while it is received, verifying the body
+/// against `sha256`.
+///
+/// Progress is reported on `progress_sender` the same way `download_file` does. When the
+/// download, the extraction or the checksum fails, `destination` is removed.
+pub async fn download_and_extract(
+    url: &str,
+    sha256: &str,
+    destination: &Path,
+    progress_sender: Option<Sender<DownloadProgress>>,
+) -> Result<()> {
+    let result = stream_into(url, sha256, destination, progress_sender.as_ref()).await;
+    if result.is_err() && destination.exists() {
+        if let Err(e) = std::fs::remove_dir_all(destination) {
+            warn!("Failed to roll back {}: {}", destination.display(), e);
+        }
+    }
+    result
+}
+
+async fn stream_into(
+    url: &str,
+    sha256: &str,
+    destination: &Path,
+    progress_sender: Option<&Sender<DownloadProgress>>,
+) -> Result<()> {
+    let filename = url.rsplit('/').next().unwrap_or(url).to_string();
+    if !supports_streaming(&filename) {
+        return Err(anyhow!("{} can not be extracted while it is downloaded", filename));
+    }
+
+    let client = crate::proxy::client_builder().build()?;
+    let mut response = client
+        .get(url)
+        .send()
+        .await
+        .map_err(|e| anyhow!(crate::tls::describe_error(&e)))?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Failed to download {}: HTTP {}", url, response.status()));
+    }
+    let total = response.content_length();
+
+    let (tx, reader) = pipe();
+    let target = destination.to_path_buf();
+    let mut extraction =
+        tokio::task::spawn_blocking(move || unpack_tar_stream(reader, &filename, &target));
+    let mut extraction_done = false;
+
+    let mut hasher = Sha256::new();
+    let mut sender = Some(tx);
+    let received = async {
+        let mut downloaded: u64 = 0;
+        while let Some(chunk) = response.chunk().await? {
+            crate::rate_limit::throttle(chunk.len()).await;
+            hasher.update(&chunk);
+            downloaded += chunk.len() as u64;
+            if let Some(progress) = progress_sender {
+                let _ = progress.send(match total {
+                    Some(total) => DownloadProgress::Progress(downloaded, total),
+                    None => DownloadProgress::Indeterminate(downloaded),
+                });
+            }
+            let Some(tx) = &sender else {
+                continue;
+            };
+            if tx.send(chunk.to_vec()).await.is_err() {
+                // The extraction stopped reading. Either it failed, or the archive ended and
+                // only padding is left, which still has to be hashed.
+                sender = None;
+                extraction_done = true;
+                (&mut extraction)
+                    .await?
+                    .map_err(|e| anyhow!("Failed to extract {}: {}", url, e))?;
+            }
+        }
+        Ok::<(), anyhow::Error>(())
+    }
+    .await;
+
+    // Closing the pipe ends the extraction, which has to stop writing before the caller rolls
+    // back the destination, also when the download failed
+    drop(sender);
+    let extracted = if extraction_done {
+        Ok(Ok(()))
+    } else {
+        extraction.await
+    };
+    received?;
+    extracted?.map_err(|e| anyhow!("Failed to extract {}: {}", url, e))?;
+
+    let digest = format!("{:x}", hasher.finalize());
+    if !digest.eq_ignore_ascii_case(sha256) {
+        return Err(anyhow!(
+            "Checksum mismatch for {}: expected {}, got {}",
+            url,
+            sha256,
+            digest
+        ));
+    }
+
+    if let Some(progress) = progress_sender {
+        let _ = progress.send(DownloadProgress::Complete);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::tar_gz_with;
+    use std::io::Write;
+    use std::net::TcpListener;
+
+    /// Serves `body` once per connection on a local port and returns the base URL. The response
+    /// announces `content_length` bytes, more than `body` holds for a connection dropped early.
+    fn serve(body: Vec<u8>, content_length: usize) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut request = [0u8; 4096];
+                let _ = stream.read(&mut request);
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    content_length
+                );
+                let _ = stream.write_all(&body);
+            }
+        });
+        format!("http://{}", address)
+    }
+
+    #[tokio::test]
+    async fn test_download_and_extract_verifies_checksum() {
+        let archive = tar_gz_with("tool/bin/tool", b"#!/bin/sh\necho tool\n");
+        let sha256 = format!("{:x}", Sha256::digest(&archive));
+        let url = format!("{}/tool-1.0.tar.gz", serve(archive.clone(), archive.len()));
+        let dir = tempfile::TempDir::new().unwrap();
+
+        let destination = dir.path().join("ok");
+        download_and_extract(&url, &sha256, &destination, None).await.unwrap();
+        assert_eq!(
+            std::fs::read_to_string(destination.join("tool/bin/tool")).unwrap(),
+            "#!/bin/sh\necho tool\n"
+        );
+
+        let rolled_back = dir.path().join("bad");
+        let wrong_sha256 = "0".repeat(64);
+        let error = download_and_extract(&url, &wrong_sha256, &rolled_back, None)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("Checksum mismatch"));
+        assert!(!rolled_back.exists());
+    }
+
+    #[tokio::test]
+    async fn test_interrupted_download_is_rolled_back_after_the_extraction_stopped() {
+        let content: Vec<u8> = (0..4 * 1024 * 1024u32).map(|i| (i % 251) as u8).collect();
+        let archive = tar_gz_with("tool/bin/tool", &content);
+        let sha256 = format!("{:x}", Sha256::digest(&archive));
+        let truncated = archive[..archive.len() / 2].to_vec();
+        let url = format!("{}/tool-1.0.tar.gz", serve(truncated, archive.len()));
+        let dir = tempfile::TempDir::new().unwrap();
+
+        let destination = dir.path().join("tool");
+        assert!(download_and_extract(&url, &sha256, &destination, None).await.is_err());
+        assert!(!destination.exists());
+        // Nothing writes into the destination anymore
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        assert!(!destination.exists());
+    }
+}
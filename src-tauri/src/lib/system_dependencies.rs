@@ -8,6 +8,19 @@ use crate::{command_executor, decompress_archive, download_file_and_rename, util
 
 pub const PYTHON_NAME_TO_INSTALL: &str = "python313";
 
+/// Directory of the tools directory the portable Git for Windows is extracted to.
+pub const GIT_INSTALL_DIR_NAME: &str = "git";
+
+/// Whether `dir`, a directory directly inside a tools directory, holds the Git or the standalone
+/// Python eim installs there as prerequisites on Windows.
+///
+/// The standalone Python archive extracts to a folder of its own (`python`), recognized by the
+/// `python.exe` at its root.
+pub fn is_prerequisite_install_dir(dir: &std::path::Path) -> bool {
+    dir.file_name().is_some_and(|name| name == GIT_INSTALL_DIR_NAME)
+        || dir.join("python.exe").is_file()
+}
+
 /// Result of a prerequisites check operation.
 ///
 /// This struct provides detailed information about the outcome of checking
@@ -938,14 +951,14 @@ pub async fn install_git_from_downloaded(
             // Extract the archive
             decompress_archive(
                 &git_archive.to_string_lossy(),
-                &tools_dir.join("git").to_string_lossy(),
+                &tools_dir.join(GIT_INSTALL_DIR_NAME).to_string_lossy(),
             )
             .map_err(|e| anyhow!("Failed to extract Git archive: {}", e))?;
 
-            let expected_git = tools_dir.join("git").join("bin").join("git.exe");
+            let expected_git = tools_dir.join(GIT_INSTALL_DIR_NAME).join("bin").join("git.exe");
             // Find the actual Git directory (the archive extracts to a subdirectory like Git-2.54.0-64-bit/)
             let git_install_dir = if expected_git.exists() {
-                tools_dir.join("git")
+                tools_dir.join(GIT_INSTALL_DIR_NAME)
             } else {
                 find_git_install_dir(&tools_dir)?.parent().unwrap().to_path_buf()
             };
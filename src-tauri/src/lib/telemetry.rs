@@ -1,20 +1,21 @@
 use chrono::Utc;
-use once_cell::sync::Lazy;
 use serde::Serialize;
 use sysinfo::System;
 use std::time::Duration;
 
 static CONNECTION_STRING: Option<&str> = option_env!("APP_INSIGHTS_CONNECTION_STRING");
 
-static CLIENT: Lazy<Option<reqwest::Client>> = Lazy::new(|| {
+/// Builds the client for one event. It is not cached: the first events are sent before the
+/// proxy and CA bundle settings are applied, and later ones must use them.
+fn client() -> Option<reqwest::Client> {
     if CONNECTION_STRING.is_none() {
         return None;
     }
-    reqwest::Client::builder()
+    crate::proxy::client_builder()
         .timeout(Duration::from_secs(5))
         .build()
         .ok()
-});
+}
 
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -27,9 +28,8 @@ struct TelemetryItem {
 }
 
 pub async fn track_event(name: &str, properties: serde_json::Value) {
-    let client: &reqwest::Client = match &*CLIENT {
-        Some(c) => c,
-        None => return,
+    let Some(client) = client() else {
+        return;
     };
     // Parse the connection string only once.
     let (i_key, ingest_base) = parse_connection_string();
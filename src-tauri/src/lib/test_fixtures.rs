@@ -0,0 +1,159 @@
+//! Fixtures shared by the tests of several modules.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use tar::{EntryType, Header};
+
+use crate::command_executor::execute_command_with_dir;
+use crate::settings::Settings;
+
+/// Writes `tools/tools.json` of the ESP-IDF at `idf_path` with one tool per `(name, version)`.
+/// Every tool is installed always, exports its `bin` directory and has a single recommended
+/// version downloadable for any platform.
+pub fn write_tools_json(idf_path: &Path, tools: &[(&str, &str)]) {
+    let tools: Vec<_> = tools
+        .iter()
+        .map(|(name, version)| {
+            serde_json::json!({
+                "description": "tool",
+                "export_paths": [["bin"]],
+                "export_vars": {},
+                "info_url": "https://example.com",
+                "install": "always",
+                "name": name,
+                "version_cmd": [],
+                "version_regex": "",
+                "versions": [{
+                    "name": version,
+                    "status": "recommended",
+                    "any": {
+                        "sha256": "00",
+                        "size": 1,
+                        "url": format!("https://example.com/{}-{}.tar.gz", name, version)
+                    }
+                }]
+            })
+        })
+        .collect();
+    let tools_json = serde_json::json!({ "version": 3, "tools": tools });
+    std::fs::create_dir_all(idf_path.join("tools")).unwrap();
+    std::fs::write(idf_path.join("tools").join("tools.json"), tools_json.to_string()).unwrap();
+}
+
+/// Runs git in `dir` and returns its trimmed standard output, failing the test on errors.
+pub fn run_git(dir: &Path, args: &[&str]) -> String {
+    let output = execute_command_with_dir("git", args, &dir.to_string_lossy()).unwrap();
+    assert!(
+        output.status.success(),
+        "git {:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8_lossy(&output.stdout).trim().to_string()
+}
+
+/// Builds a tar archive from `(path, type, link target, content)` entries. Paths and link
+/// targets are written as given, bypassing the checks of `tar::Builder`.
+pub fn crafted_tar(entries: &[(&str, EntryType, &str, &[u8])]) -> Vec<u8> {
+    let mut builder = tar::Builder::new(Vec::new());
+    for (path, entry_type, link, content) in entries {
+        let mut header = Header::new_gnu();
+        let gnu = header.as_gnu_mut().unwrap();
+        gnu.name[..path.len()].copy_from_slice(path.as_bytes());
+        gnu.linkname[..link.len()].copy_from_slice(link.as_bytes());
+        header.set_entry_type(*entry_type);
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append(&header, *content).unwrap();
+    }
+    builder.into_inner().unwrap()
+}
+
+/// A tar archive holding a single regular file.
+pub fn tar_with(name: &str, content: &[u8]) -> Vec<u8> {
+    crafted_tar(&[(name, EntryType::Regular, "", content)])
+}
+
+/// A gzip compressed tar archive holding a single regular file.
+pub fn tar_gz_with(name: &str, content: &[u8]) -> Vec<u8> {
+    use std::io::Write;
+
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(&tar_with(name, content)).unwrap();
+    encoder.finish().unwrap()
+}
+
+/// `settings` as eim releases stored them in eim_idf.json before the installation settings were
+/// written as JSON: bincode of the settings known back then, in their order.
+pub fn released_settings_blob(settings: &Settings) -> Vec<u8> {
+    #[derive(Serialize)]
+    struct ReleasedSettings<'a> {
+        path: &'a Option<PathBuf>,
+        idf_path: &'a Option<PathBuf>,
+        esp_idf_json_path: &'a Option<String>,
+        tool_download_folder_name: &'a Option<String>,
+        tool_install_folder_name: &'a Option<String>,
+        target: &'a Option<Vec<String>>,
+        idf_versions: &'a Option<Vec<String>>,
+        tools_json_file: &'a Option<String>,
+        config_file: &'a Option<PathBuf>,
+        config_file_save_path: &'a Option<PathBuf>,
+        non_interactive: &'a Option<bool>,
+        wizard_all_questions: &'a Option<bool>,
+        mirror: &'a Option<String>,
+        idf_mirror: &'a Option<String>,
+        pypi_mirror: &'a Option<String>,
+        recurse_submodules: &'a Option<bool>,
+        install_all_prerequisites: &'a Option<bool>,
+        idf_features: &'a Option<Vec<String>>,
+        idf_features_per_version: &'a Option<HashMap<String, Vec<String>>>,
+        idf_tools: &'a Option<Vec<String>>,
+        idf_tools_per_version: &'a Option<HashMap<String, Vec<String>>>,
+        repo_stub: &'a Option<String>,
+        skip_prerequisites_check: &'a Option<bool>,
+        version_name: &'a Option<String>,
+        cleanup: &'a Option<bool>,
+        python_env_folder_name: &'a Option<String>,
+        use_local_archive: &'a Option<PathBuf>,
+        activation_script_path_override: &'a Option<String>,
+        python_version_override: &'a Option<String>,
+        create_bat_activation_script: &'a Option<bool>,
+    }
+
+    bincode::serialize(&ReleasedSettings {
+        path: &settings.path,
+        idf_path: &settings.idf_path,
+        esp_idf_json_path: &settings.esp_idf_json_path,
+        tool_download_folder_name: &settings.tool_download_folder_name,
+        tool_install_folder_name: &settings.tool_install_folder_name,
+        target: &settings.target,
+        idf_versions: &settings.idf_versions,
+        tools_json_file: &settings.tools_json_file,
+        config_file: &settings.config_file,
+        config_file_save_path: &settings.config_file_save_path,
+        non_interactive: &settings.non_interactive,
+        wizard_all_questions: &settings.wizard_all_questions,
+        mirror: &settings.mirror,
+        idf_mirror: &settings.idf_mirror,
+        pypi_mirror: &settings.pypi_mirror,
+        recurse_submodules: &settings.recurse_submodules,
+        install_all_prerequisites: &settings.install_all_prerequisites,
+        idf_features: &settings.idf_features,
+        idf_features_per_version: &settings.idf_features_per_version,
+        idf_tools: &settings.idf_tools,
+        idf_tools_per_version: &settings.idf_tools_per_version,
+        repo_stub: &settings.repo_stub,
+        skip_prerequisites_check: &settings.skip_prerequisites_check,
+        version_name: &settings.version_name,
+        cleanup: &settings.cleanup,
+        python_env_folder_name: &settings.python_env_folder_name,
+        use_local_archive: &settings.use_local_archive,
+        activation_script_path_override: &settings.activation_script_path_override,
+        python_version_override: &settings.python_version_override,
+        create_bat_activation_script: &settings.create_bat_activation_script,
+    })
+    .unwrap()
+}
@@ -0,0 +1,219 @@
+//! Extra CA certificates for networks that intercept TLS traffic.
+//!
+//! Corporate proxies that inspect TLS re-sign every connection with a root certificate of their
+//! own, which the bundled rustls/native-tls roots reject. The PEM bundle given by the
+//! `ca_bundle` setting is added to the trusted roots of every reqwest client built by
+//! [`crate::proxy::client_builder`], and is handed to gix (`http.sslCAInfo`), to the `git`
+//! command line fallbacks (`-c http.sslCAInfo=...`) and to pip (`--cert`).
+//!
+//! git and pip use the bundle *instead of* their default roots, so on networks where only some
+//! hosts are intercepted the bundle should also contain the public roots those hosts need.
+
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+use anyhow::{anyhow, Context, Result};
+use log::debug;
+use once_cell::sync::Lazy;
+
+use crate::settings::Settings;
+
+/// Appended to certificate errors so the user knows how to get past TLS interception.
+pub const INTERCEPTION_HINT: &str = "The server certificate is not trusted. This usually means that a proxy or firewall on your network inspects TLS traffic; pass the root certificate of your organisation with --ca-bundle (ca_bundle in the configuration file).";
+
+/// Fragments of the messages rustls, native-tls, libcurl (git) and Python (pip) produce when a
+/// certificate chain can not be verified.
+const CERTIFICATE_ERROR_MARKERS: [&str; 9] = [
+    "invalid peer certificate",
+    "unknownissuer",
+    "unknown issuer",
+    "certificate verify failed",
+    "certificate_verify_failed",
+    "self signed certificate",
+    "self-signed certificate",
+    "unable to get local issuer certificate",
+    "ssl certificate problem",
+];
+
+static ACTIVE_BUNDLE: Lazy<RwLock<Option<CaBundle>>> = Lazy::new(|| RwLock::new(None));
+
+/// A PEM bundle of additional root certificates.
+#[derive(Debug, Clone)]
+pub struct CaBundle {
+    path: PathBuf,
+    certificates: Vec<reqwest::Certificate>,
+}
+
+impl CaBundle {
+    /// Reads and parses the PEM bundle at `path`. Fails if the file holds no certificate.
+    pub fn load(path: &Path) -> Result<Self> {
+        let pem = std::fs::read(path)
+            .with_context(|| format!("Failed to read CA bundle {}", path.display()))?;
+        let certificates = reqwest::Certificate::from_pem_bundle(&pem)
+            .with_context(|| format!("Failed to parse CA bundle {}", path.display()))?;
+        if certificates.is_empty() {
+            return Err(anyhow!("CA bundle {} contains no PEM certificate", path.display()));
+        }
+        // git and pip open the file themselves, possibly from another working directory.
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        Ok(Self { path, certificates })
+    }
+
+    /// Path of the bundle.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Number of certificates in the bundle.
+    pub fn len(&self) -> usize {
+        self.certificates.len()
+    }
+
+    /// Returns true if the bundle holds no certificate.
+    pub fn is_empty(&self) -> bool {
+        self.certificates.is_empty()
+    }
+}
+
+/// Makes the CA bundle from `settings` trusted by all network access, or clears it when the
+/// setting is not present.
+pub fn configure(settings: &Settings) -> Result<()> {
+    let bundle = match &settings.ca_bundle {
+        Some(path) => {
+            let bundle = CaBundle::load(path)?;
+            debug!("Trusting {} certificates from {}", bundle.len(), bundle.path.display());
+            Some(bundle)
+        }
+        None => None,
+    };
+    match ACTIVE_BUNDLE.write() {
+        Ok(mut active) => *active = bundle,
+        Err(e) => *e.into_inner() = bundle,
+    }
+    Ok(())
+}
+
+/// The CA bundle currently in use, if any.
+pub fn active() -> Option<CaBundle> {
+    match ACTIVE_BUNDLE.read() {
+        Ok(active) => active.clone(),
+        Err(e) => e.into_inner().clone(),
+    }
+}
+
+/// Path of the CA bundle currently in use, if any.
+pub fn active_bundle_path() -> Option<PathBuf> {
+    active().map(|bundle| bundle.path)
+}
+
+/// Adds the certificates of the active CA bundle to the roots trusted by `builder`.
+pub fn add_root_certificates(builder: reqwest::ClientBuilder) -> reqwest::ClientBuilder {
+    active()
+        .map(|bundle| bundle.certificates)
+        .unwrap_or_default()
+        .into_iter()
+        .fold(builder, |builder, cert| builder.add_root_certificate(cert))
+}
+
+/// Blocking counterpart of [`add_root_certificates`].
+pub fn add_root_certificates_blocking(
+    builder: reqwest::blocking::ClientBuilder,
+) -> reqwest::blocking::ClientBuilder {
+    active()
+        .map(|bundle| bundle.certificates)
+        .unwrap_or_default()
+        .into_iter()
+        .fold(builder, |builder, cert| builder.add_root_certificate(cert))
+}
+
+/// Global `git` options making git trust the active CA bundle.
+pub fn git_cli_args() -> Vec<String> {
+    match active_bundle_path() {
+        Some(path) => vec!["-c".to_string(), format!("http.sslCAInfo={}", path.display())],
+        None => Vec::new(),
+    }
+}
+
+/// In-memory gix configuration overrides making gix trust the active CA bundle.
+pub fn gix_config_overrides() -> Vec<String> {
+    active_bundle_path()
+        .map(|path| vec![format!("http.sslCAInfo={}", path.display())])
+        .unwrap_or_default()
+}
+
+/// Returns true if `message` reads like a failed certificate verification.
+pub fn looks_like_certificate_error(message: &str) -> bool {
+    let message = message.to_ascii_lowercase();
+    CERTIFICATE_ERROR_MARKERS
+        .iter()
+        .any(|marker| message.contains(marker))
+}
+
+/// Returns true if `err`, or any error in its source chain, is a certificate failure.
+pub fn is_certificate_error(err: &(dyn std::error::Error + 'static)) -> bool {
+    let mut current = Some(err);
+    while let Some(e) = current {
+        if looks_like_certificate_error(&e.to_string()) {
+            return true;
+        }
+        current = e.source();
+    }
+    false
+}
+
+/// Formats `err` for the user. Certificate failures get their whole source chain (the top level
+/// message of reqwest does not say what went wrong) followed by [`INTERCEPTION_HINT`].
+pub fn describe_error(err: &(dyn std::error::Error + 'static)) -> String {
+    if !is_certificate_error(err) {
+        return err.to_string();
+    }
+    let mut messages = vec![err.to_string()];
+    let mut current = err.source();
+    while let Some(e) = current {
+        messages.push(e.to_string());
+        current = e.source();
+    }
+    format!("{}. {}", messages.join(": "), INTERCEPTION_HINT)
+}
+
+/// Appends [`INTERCEPTION_HINT`] to `message` (e.g. the stderr of git or pip) when it reports a
+/// certificate failure.
+pub fn with_certificate_hint(message: String) -> String {
+    if looks_like_certificate_error(&message) {
+        format!("{}\n{}", message.trim_end(), INTERCEPTION_HINT)
+    } else {
+        message
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_certificate_errors_get_interception_hint() {
+        let git_stderr = "fatal: unable to access 'https://github.com/espressif/esp-idf.git/': SSL certificate problem: unable to get local issuer certificate".to_string();
+        assert!(with_certificate_hint(git_stderr).ends_with(INTERCEPTION_HINT));
+
+        let pip_stderr = "Could not fetch URL https://dl.espressif.com/pypi/: [SSL: CERTIFICATE_VERIFY_FAILED] certificate verify failed".to_string();
+        assert!(with_certificate_hint(pip_stderr).ends_with(INTERCEPTION_HINT));
+
+        let unrelated = "fatal: repository not found".to_string();
+        assert_eq!(with_certificate_hint(unrelated.clone()), unrelated);
+
+        let wrapped = std::io::Error::new(
+            std::io::ErrorKind::Other,
+            "invalid peer certificate: UnknownIssuer",
+        );
+        assert!(describe_error(&wrapped).ends_with(INTERCEPTION_HINT));
+    }
+
+    #[test]
+    fn test_ca_bundle_rejects_file_without_certificates() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("empty.pem");
+        std::fs::write(&path, "not a certificate\n").unwrap();
+        assert!(CaBundle::load(&path).is_err());
+        assert!(CaBundle::load(&dir.path().join("missing.pem")).is_err());
+    }
+}
@@ -0,0 +1,847 @@
+//! Changes to the tools of an existing installation.
+//!
+//! The installer only sets up tools while installing or fixing a whole ESP-IDF version. The
+//! functions here change single tools of an installation recorded in `eim_idf.json` in place:
+//! they install the required archives with [`setup_tools`] into the installation's tools
+//! directory, record the change in the installation's stored settings and regenerate its
+//! activation scripts so that `PATH` and the tool variables point at the tools actually in use.
+//!
+//! Tool versions other than the ones tools.json recommends are recorded in
+//! [`Settings::tool_versions`], which every later regeneration of the activation scripts honours.
+//! Versions the user pinned in [`Settings::tool_pins`] take precedence and are never upgraded.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::idf_config::{IdfConfig, IdfInstallation};
+use crate::idf_tools::{
+    apply_tool_versions, filter_tools_by_target, get_platform_identification,
+    get_tools_export_paths_from_list, get_tools_export_vars_from_list, read_and_parse_tools_file,
+    setup_tools, Download, Tool, ToolSetupOptions, ToolsFile,
+};
+use crate::settings::Settings;
+use crate::tool_store::ToolStore;
+use crate::version_manager::{
+    find_installation, get_default_config_path, is_newer_semver, list_idf_tools, semver_order,
+    ToolListIdfContext,
+};
+use crate::DownloadProgress;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolUpgrade {
+    pub name: String,
+    pub from: String,
+    pub to: String,
+    /// Superseded versions removed after the upgrade
+    #[serde(default)]
+    pub removed: Vec<String>,
+}
+
+/// Result of [`upgrade_tools`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolUpgradeReport {
+    pub idf: ToolListIdfContext,
+    pub upgraded: Vec<ToolUpgrade>,
+    /// Requested tools that already have the newest version available for this platform
+    pub up_to_date: Vec<String>,
+    /// Requested tools left at the version they are pinned to
+    #[serde(default)]
+    pub pinned: BTreeMap<String, String>,
+}
+
+/// Result of [`add_tool`] and [`remove_tool`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolChange {
+    pub idf: ToolListIdfContext,
+    pub tool: String,
+    /// Versions installed or removed
+    pub versions: Vec<String>,
+    /// Versions left in place: the already installed one when adding, the ones other
+    /// installations sharing the tools directory still use when removing
+    #[serde(default)]
+    pub kept: Vec<String>,
+}
+
+/// An installation together with the settings it was installed with and its tools.json.
+pub(crate) struct InstallationContext {
+    pub config_path: PathBuf,
+    pub installation: IdfInstallation,
+    pub settings: Settings,
+    pub tools_file: ToolsFile,
+}
+
+impl InstallationContext {
+    pub fn load(identifier: &str, config_path: Option<&PathBuf>) -> Result<Self, String> {
+        let config_path = config_path.cloned().unwrap_or_else(get_default_config_path);
+        let ide_config = IdfConfig::from_file(&config_path)
+            .map_err(|e| format!("Failed to read eim_idf.json: {}", e))?;
+        let installation = find_installation(&ide_config, identifier)?.clone();
+        Self::from_installation(config_path, installation)
+    }
+
+    pub fn from_installation(config_path: PathBuf, installation: IdfInstallation) -> Result<Self, String> {
+        let settings = stored_settings(&installation);
+        let tools_json = Path::new(&installation.path).join("tools").join("tools.json");
+        let tools_file = read_and_parse_tools_file(&tools_json.to_string_lossy(), settings.tools_overlay.as_deref())
+            .map_err(|e| format!("Failed to read {}: {}", tools_json.display(), e))?;
+        Ok(Self {
+            config_path,
+            installation,
+            settings,
+            tools_file,
+        })
+    }
+
+    pub fn tools_dir(&self) -> PathBuf {
+        PathBuf::from(&self.installation.idf_tools_path)
+    }
+
+    pub fn idf_context(&self) -> ToolListIdfContext {
+        ToolListIdfContext {
+            id: self.installation.id.clone(),
+            name: self.installation.name.clone(),
+            path: self.installation.path.clone(),
+        }
+    }
+
+    /// The tool store the installation was set up with, referencing links as this installation.
+    fn tool_store(&self) -> Option<ToolStore> {
+        ToolStore::from_settings(&self.settings).map(|store| store.with_owner(self.installation.path.clone()))
+    }
+
+    /// Download folder names are relative to the version folder unless they are absolute.
+    pub fn download_dir(&self) -> PathBuf {
+        let version_dir = Path::new(&self.installation.path)
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        version_dir.join(
+            self.settings
+                .tool_download_folder_name
+                .clone()
+                .or(Settings::default().tool_download_folder_name)
+                .unwrap_or_default(),
+        )
+    }
+
+    /// Whether `tool` belongs to the installation: required tools and the optional tools that
+    /// were selected for it, limited to its targets.
+    pub fn is_selected(&self, tool: &Tool) -> bool {
+        let selected = match self.settings.get_tools_for_version_if_set(&self.installation.name) {
+            Some(names) => is_required(tool) || names.contains(&tool.name),
+            None => tool.install != "never",
+        };
+        let targets = self.settings.target.clone().unwrap_or_else(|| vec!["all".to_string()]);
+        selected && !filter_tools_by_target(vec![tool.clone()], &targets).is_empty()
+    }
+
+    /// Installs the recommended versions of the tools in `tools_file` into the tools directory.
+    pub async fn install(
+        &self,
+        tools_file: &ToolsFile,
+        progress_callback: impl Fn(DownloadProgress) + Clone + Send + 'static,
+    ) -> Result<HashMap<String, (String, Download)>, String> {
+        let download_dir = self.download_dir();
+        crate::ensure_path(&download_dir.to_string_lossy()).map_err(|e| e.to_string())?;
+        setup_tools(
+            tools_file,
+            vec!["all".to_string()],
+            &download_dir,
+            &self.tools_dir(),
+            &ToolSetupOptions::from_settings(&self.settings, self.installation.path.clone()),
+            progress_callback,
+        )
+        .await
+        .map(|(installed, _)| installed)
+        .map_err(|e| e.to_string())
+    }
+
+    /// The version of every selected tool present in the tools directory: the pinned one, the one
+    /// chosen in [`Settings::tool_versions`] or the one recommended by tools.json if it is
+    /// installed, the newest installed one otherwise.
+    pub fn installed_versions(&self) -> HashMap<String, (String, Download)> {
+        let tools_file = apply_tool_versions(self.tools_file.clone(), &self.settings.chosen_tool_versions());
+        let platform = get_platform_identification().ok();
+        let tools_dir = self.tools_dir();
+
+        let mut installed = HashMap::new();
+        for tool in tools_file.tools.iter().filter(|t| self.is_selected(t)) {
+            let on_disk: Vec<_> = tool
+                .versions
+                .iter()
+                .filter(|v| tools_dir.join(&tool.name).join(&v.name).exists())
+                .collect();
+            let version = on_disk
+                .iter()
+                .find(|v| v.status == "recommended")
+                .or_else(|| on_disk.iter().max_by(|a, b| semver_order(&a.name, &b.name)));
+            let Some(version) = version else {
+                continue;
+            };
+            let download = platform
+                .as_ref()
+                .and_then(|p| version.downloads.get(p))
+                .or_else(|| version.downloads.get("any"));
+            if let Some(download) = download {
+                installed.insert(tool.name.clone(), (version.name.clone(), download.clone()));
+            }
+        }
+        installed
+    }
+
+    /// Names of the tools selected for the installation. Installations made without an explicit
+    /// selection are taken to use the tools they have installed.
+    fn selected_tool_names(&self) -> Vec<String> {
+        if let Some(names) = self.settings.get_tools_for_version_if_set(&self.installation.name) {
+            return names;
+        }
+        let installed = self.installed_versions();
+        self.tools_file
+            .tools
+            .iter()
+            .filter(|tool| installed.contains_key(&tool.name))
+            .map(|tool| tool.name.clone())
+            .collect()
+    }
+
+    /// Records `names` as the tool selection of the installation, like the wizard does.
+    fn select_tools(&mut self, names: Vec<String>) {
+        self.settings
+            .idf_tools_per_version
+            .get_or_insert_with(HashMap::new)
+            .insert(self.installation.name.clone(), names);
+    }
+
+    /// Tool versions in use by the other installations sharing the tools directory, `None` if
+    /// the tools of one of them are unknown.
+    pub fn versions_used_elsewhere(&self) -> Option<HashSet<(String, String)>> {
+        let ide_config = IdfConfig::from_file(&self.config_path).ok()?;
+        let tools_dir = self.tools_dir();
+        let mut used = HashSet::new();
+        for installation in ide_config
+            .idf_installed
+            .into_iter()
+            .filter(|i| i.id != self.installation.id && Path::new(&i.idf_tools_path) == tools_dir)
+        {
+            let name = installation.name.clone();
+            match Self::from_installation(self.config_path.clone(), installation) {
+                Ok(other) => used.extend(
+                    other
+                        .installed_versions()
+                        .into_iter()
+                        .map(|(tool, (version, _))| (tool, version)),
+                ),
+                Err(e) => {
+                    warn!("The tools {} uses are unknown: {}", name, e);
+                    return None;
+                }
+            }
+        }
+        Some(used)
+    }
+
+    /// Removes a version of a tool from the tools directory unless another installation still
+    /// uses it, either through the tool store or according to `used_elsewhere`. Returns whether
+    /// the version was removed.
+    pub fn remove_version(
+        &self,
+        tool_name: &str,
+        version: &str,
+        used_elsewhere: Option<&HashSet<(String, String)>>,
+    ) -> std::io::Result<bool> {
+        let version_dir = self.tools_dir().join(tool_name).join(version);
+        if let Some(store) = self.tool_store() {
+            if store.release_link(&version_dir)? {
+                // The store keeps links other installations reference
+                return Ok(std::fs::symlink_metadata(&version_dir).is_err());
+            }
+        }
+        let in_use = match used_elsewhere {
+            Some(used) => used.contains(&(tool_name.to_string(), version.to_string())),
+            None => true,
+        };
+        if in_use {
+            debug!("Keeping {} {}, another installation uses it", tool_name, version);
+            return Ok(false);
+        }
+        remove_version_dir(&version_dir)?;
+        Ok(true)
+    }
+
+    /// Writes the settings and the checked out commit back into the installation's entry in
+    /// eim_idf.json.
+    pub fn save_settings(&mut self) -> Result<(), String> {
+        self.installation.installation_config = Some(
+            self.settings
+                .to_installation_config()
+                .map_err(|e| format!("Failed to serialize the installation settings: {}", e))?,
+        );
+
+        let mut ide_config = IdfConfig::from_file(&self.config_path)
+            .map_err(|e| format!("Failed to read eim_idf.json: {}", e))?;
+        let Some(entry) = ide_config
+            .idf_installed
+            .iter_mut()
+            .find(|i| i.id == self.installation.id)
+        else {
+            return Err(format!("Installation {} is no longer in eim_idf.json", self.installation.id));
+        };
+        check_settings_readable(entry)?;
+        entry.installation_config = self.installation.installation_config.clone();
+        entry.idf_commit = self.installation.idf_commit.clone();
+        ide_config
+            .to_file(&self.config_path, true, false)
+            .map_err(|e| format!("Failed to write eim_idf.json: {}", e))
+    }
+
+    /// Regenerates the activation scripts from the tools currently installed.
+    pub fn refresh_activation_scripts(&self, is_gui: bool) {
+        let tools_dir = self.installation.idf_tools_path.as_str();
+        let installed = self.installed_versions();
+        let export_paths = get_tools_export_paths_from_list(self.tools_file.clone(), installed.clone(), tools_dir)
+            .into_iter()
+            .map(|p| {
+                if std::env::consts::OS == "windows" {
+                    crate::replace_unescaped_spaces_win(&p)
+                } else {
+                    p
+                }
+            })
+            .collect();
+        let export_vars = get_tools_export_vars_from_list(self.tools_file.clone(), installed, tools_dir);
+
+        let python = Path::new(&self.installation.python);
+        // <venv>/bin/python or <venv>\Scripts\python.exe
+        let venv = python.parent().and_then(Path::parent).map(|p| p.to_string_lossy().into_owned());
+        let script_dir = Path::new(&self.installation.activation_script)
+            .parent()
+            .map(|p| p.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        debug!("Regenerating the activation scripts of {} in {}", self.installation.name, script_dir);
+        crate::single_version_post_install(
+            &script_dir,
+            &self.installation.path,
+            &self.installation.name,
+            tools_dir,
+            export_paths,
+            venv.as_deref(),
+            Some(export_vars),
+            &self.installation.python,
+            self.settings.create_bat_activation_script.unwrap_or(false),
+            false,
+            is_gui,
+        );
+    }
+}
+
+/// The settings the installation was created with, defaults if none were stored or they can
+/// not be read.
+pub(crate) fn stored_settings(installation: &IdfInstallation) -> Settings {
+    let Some(bytes) = installation.installation_config.as_ref() else {
+        return Settings::default();
+    };
+    Settings::from_installation_config(bytes.as_slice()).unwrap_or_else(|e| {
+        warn!("Using default settings for {}, its stored settings can not be read: {}", installation.name, e);
+        Settings::default()
+    })
+}
+
+/// Fails when the installation has stored settings this version of eim can not read, for
+/// example damaged ones. Saving the defaults used in their place would lose them, so
+/// installations with such settings are not changed.
+fn check_settings_readable(installation: &IdfInstallation) -> Result<(), String> {
+    match &installation.installation_config {
+        Some(bytes) if Settings::from_installation_config(bytes.as_slice()).is_err() => Err(format!(
+            "The settings stored with {} can not be read by this version of eim and are left unchanged; reinstall it to change it",
+            installation.name
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// The settings stored with the installation matching `identifier`, e.g. to apply its network
+/// configuration before changing its tools.
+pub fn installation_settings(identifier: &str, config_path: Option<&PathBuf>) -> Result<Settings, String> {
+    let config_path = config_path.cloned().unwrap_or_else(get_default_config_path);
+    let ide_config = IdfConfig::from_file(&config_path)
+        .map_err(|e| format!("Failed to read eim_idf.json: {}", e))?;
+    find_installation(&ide_config, identifier).map(stored_settings)
+}
+
+/// Required tools are installed whatever the selection; the wizard adds clang and ninja to them
+/// because the IDEs and builds depend on them.
+fn is_required(tool: &Tool) -> bool {
+    tool.install == "always" || tool.name.contains("clang") || tool.name.contains("ninja")
+}
+
+/// Removes a tool version directory: only the link for tools linked from somewhere else, only
+/// the files of its manifest otherwise.
+fn remove_version_dir(version_dir: &Path) -> std::io::Result<()> {
+    let metadata = std::fs::symlink_metadata(version_dir)?;
+    if metadata.file_type().is_symlink() {
+        // Directory symlinks are removed with remove_dir on Windows
+        return std::fs::remove_file(version_dir).or_else(|_| std::fs::remove_dir(version_dir));
+    }
+    for kept in crate::tool_manifest::uninstall(version_dir)? {
+        warn!("Kept {}, it was not installed with the tool", kept.display());
+    }
+    Ok(())
+}
+
+/// Installs the newest version tools.json lists for every outdated tool of the installation,
+/// or only for the tools named in `tool_names`, and regenerates the activation scripts.
+///
+/// A tool is outdated when tools.json lists a newer version with a download for this platform
+/// than the newest one installed (see [`list_idf_tools`]). The new versions are recorded in the
+/// installation's `tool_versions`. With `remove_superseded`, the other installed versions of the
+/// upgraded tools are removed afterwards. Pinned tools are left alone.
+pub async fn upgrade_tools(
+    identifier: &str,
+    tool_names: &[String],
+    remove_superseded: bool,
+    config_path: Option<&PathBuf>,
+    is_gui: bool,
+    progress_callback: impl Fn(DownloadProgress) + Clone + Send + 'static,
+) -> Result<ToolUpgradeReport, String> {
+    let report = list_idf_tools(Some(identifier), true, config_path)?;
+    if let Some(unknown) = tool_names
+        .iter()
+        .find(|name| !report.tools.iter().any(|entry| &entry.tool.name == *name))
+    {
+        return Err(format!("Tool '{}' is not listed in {}", unknown, report.tools_json_path));
+    }
+
+    let mut upgrades = Vec::new();
+    let mut up_to_date = Vec::new();
+    let mut pinned = BTreeMap::new();
+    for entry in &report.tools {
+        let name = &entry.tool.name;
+        if !tool_names.is_empty() && !tool_names.contains(name) {
+            continue;
+        }
+        if let Some(pin) = &entry.pinned {
+            debug!("Not upgrading {}, it is pinned to {}", name, pin);
+            pinned.insert(name.clone(), pin.clone());
+            continue;
+        }
+        let Some(installed) = report.outdated.iter().find(|o| &o.name == name).map(|o| &o.installed) else {
+            if tool_names.contains(name) {
+                up_to_date.push(name.clone());
+            }
+            continue;
+        };
+        let newest = entry
+            .version_inspections
+            .iter()
+            .filter(|vi| vi.has_platform_download && vi.version.status != "deprecated")
+            .map(|vi| &vi.version)
+            .max_by(|a, b| semver_order(&a.name, &b.name));
+        match newest {
+            Some(version) if is_newer_semver(&version.name, Some(installed)) => {
+                let mut tool = entry.tool.clone();
+                tool.versions = vec![version.clone()];
+                tool.versions[0].status = "recommended".to_string();
+                upgrades.push((tool, installed.clone()));
+            }
+            _ => {
+                debug!("No newer version of {} is available for this platform", name);
+                up_to_date.push(name.clone());
+            }
+        }
+    }
+
+    let mut result = ToolUpgradeReport {
+        idf: report.idf,
+        upgraded: Vec::new(),
+        up_to_date,
+        pinned,
+    };
+    if upgrades.is_empty() {
+        return Ok(result);
+    }
+
+    let mut context = InstallationContext::load(identifier, config_path)?;
+    context.ensure_settings_readable()?;
+    let upgrade_file = ToolsFile {
+        tools: upgrades.iter().map(|(tool, _)| tool.clone()).collect(),
+        version: context.tools_file.version,
+    };
+    context.install(&upgrade_file, progress_callback).await?;
+
+    let tools_dir = context.tools_dir();
+    let used_elsewhere = if remove_superseded {
+        context.versions_used_elsewhere()
+    } else {
+        None
+    };
+    let mut tool_versions = context.settings.tool_versions.clone().unwrap_or_default();
+    for (tool, from) in upgrades {
+        let to = tool.versions[0].name.clone();
+        let mut removed = Vec::new();
+        if remove_superseded {
+            let stale = versions_on_disk(&tools_dir, &tool.name)
+                .into_iter()
+                .filter(|version| *version != to);
+            for version in stale {
+                match context.remove_version(&tool.name, &version, used_elsewhere.as_ref()) {
+                    Ok(true) => removed.push(version),
+                    Ok(false) => {}
+                    Err(e) => warn!("Failed to remove {} {}: {}", tool.name, version, e),
+                }
+            }
+        }
+        info!("Upgraded {} from {} to {}", tool.name, from, to);
+        tool_versions.insert(tool.name.clone(), to.clone());
+        result.upgraded.push(ToolUpgrade {
+            name: tool.name,
+            from,
+            to,
+            removed,
+        });
+    }
+
+    context.settings.tool_versions = Some(tool_versions);
+    context.save_settings()?;
+    context.refresh_activation_scripts(is_gui);
+    Ok(result)
+}
+
+/// Installs `tool_name` into an installation and adds it to the installation's tool selection,
+/// so that its activation scripts, `eim fix` and later changes include it.
+///
+/// The version tools.json recommends (or the one chosen with [`upgrade_tools`]) is installed
+/// unless it already is. The tool has to support the installation's targets and provide a
+/// download for this platform.
+pub async fn add_tool(
+    identifier: &str,
+    tool_name: &str,
+    config_path: Option<&PathBuf>,
+    is_gui: bool,
+    progress_callback: impl Fn(DownloadProgress) + Clone + Send + 'static,
+) -> Result<ToolChange, String> {
+    let mut context = InstallationContext::load(identifier, config_path)?;
+    context.ensure_settings_readable()?;
+    let tools_file = apply_tool_versions(context.tools_file.clone(), &context.settings.chosen_tool_versions());
+    let Some(tool) = tools_file.tools.iter().find(|t| t.name == tool_name).cloned() else {
+        return Err(format!("Tool '{}' is not listed in tools.json of {}", tool_name, context.installation.name));
+    };
+
+    let mut selection = context.selected_tool_names();
+    if !selection.contains(&tool.name) {
+        selection.push(tool.name.clone());
+    }
+    context.select_tools(selection);
+    if !context.is_selected(&tool) {
+        return Err(format!(
+            "Tool '{}' does not support the targets of {}",
+            tool.name, context.installation.name
+        ));
+    }
+
+    let platform = get_platform_identification()?;
+    let Some(version) = tool
+        .versions
+        .iter()
+        .find(|v| v.status == "recommended")
+        .filter(|v| v.downloads.contains_key(&platform) || v.downloads.contains_key("any"))
+    else {
+        return Err(format!("Tool '{}' has no download for {}", tool.name, platform));
+    };
+    if tool.name.contains("qemu") {
+        match crate::system_dependencies::check_qemu_prerequisites() {
+            Ok(missing) if !missing.is_empty() => {
+                return Err(format!("QEMU needs these packages installed first: {}", missing.join(", ")))
+            }
+            Err(e) => return Err(format!("Failed to check the QEMU prerequisites: {}", e)),
+            Ok(_) => {}
+        }
+    }
+
+    let mut change = ToolChange {
+        idf: context.idf_context(),
+        tool: tool.name.clone(),
+        versions: Vec::new(),
+        kept: Vec::new(),
+    };
+    if context.installed_versions().get(&tool.name).is_some_and(|(v, _)| *v == version.name) {
+        debug!("{} {} is already installed", tool.name, version.name);
+        change.kept.push(version.name.clone());
+    } else {
+        let tool_file = ToolsFile {
+            tools: vec![tool.clone()],
+            version: context.tools_file.version,
+        };
+        context.install(&tool_file, progress_callback).await?;
+        info!("Installed {} {} for {}", tool.name, version.name, context.installation.name);
+        change.versions.push(version.name.clone());
+    }
+
+    context.save_settings()?;
+    context.refresh_activation_scripts(is_gui);
+    Ok(change)
+}
+
+/// Removes `tool_name` from an installation and from its tool selection, and regenerates the
+/// activation scripts without it. Versions another installation sharing the tools directory
+/// still uses are kept. Required tools can not be removed.
+pub fn remove_tool(
+    identifier: &str,
+    tool_name: &str,
+    config_path: Option<&PathBuf>,
+    is_gui: bool,
+) -> Result<ToolChange, String> {
+    let mut context = InstallationContext::load(identifier, config_path)?;
+    context.ensure_settings_readable()?;
+    let Some(tool) = context.tools_file.tools.iter().find(|t| t.name == tool_name).cloned() else {
+        return Err(format!("Tool '{}' is not listed in tools.json of {}", tool_name, context.installation.name));
+    };
+    if is_required(&tool) {
+        return Err(format!("Tool '{}' is required by ESP-IDF and can not be removed", tool.name));
+    }
+
+    let mut selection = context.selected_tool_names();
+    selection.retain(|name| *name != tool.name);
+    context.select_tools(selection);
+
+    let mut change = ToolChange {
+        idf: context.idf_context(),
+        tool: tool.name.clone(),
+        versions: Vec::new(),
+        kept: Vec::new(),
+    };
+    let tools_dir = context.tools_dir();
+    let used_elsewhere = context.versions_used_elsewhere();
+    for version in versions_on_disk(&tools_dir, &tool.name) {
+        match context.remove_version(&tool.name, &version, used_elsewhere.as_ref()) {
+            Ok(true) => change.versions.push(version),
+            Ok(false) => change.kept.push(version),
+            Err(e) => return Err(format!("Failed to remove {} {}: {}", tool.name, version, e)),
+        }
+    }
+    // Only succeeds once no version is left
+    let _ = std::fs::remove_dir(tools_dir.join(&tool.name));
+    info!("Removed {} from {}", tool.name, context.installation.name);
+
+    if let Some(tool_versions) = context.settings.tool_versions.as_mut() {
+        tool_versions.remove(&tool.name);
+    }
+    context.save_settings()?;
+    context.refresh_activation_scripts(is_gui);
+    Ok(change)
+}
+
+/// Names of the version directories of `tool_name` in `tools_dir`.
+pub(crate) fn versions_on_disk(tools_dir: &Path, tool_name: &str) -> Vec<String> {
+    match std::fs::read_dir(tools_dir.join(tool_name)) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .filter(|e| e.path().is_dir())
+            .map(|e| e.file_name().to_string_lossy().into_owned())
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::idf_config::Base64Bytes;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn tool(name: &str, versions: &[(&str, &str)]) -> serde_json::Value {
+        let versions: Vec<_> = versions
+            .iter()
+            .map(|(version, status)| {
+                serde_json::json!({
+                    "name": version,
+                    "status": status,
+                    "any": {
+                        "sha256": "00",
+                        "size": 1,
+                        "url": format!("https://example.com/{}-{}.tar.gz", name, version)
+                    }
+                })
+            })
+            .collect();
+        serde_json::json!({
+            "description": "tool",
+            "export_paths": [["bin"]],
+            "export_vars": {},
+            "info_url": "https://example.com",
+            "install": "always",
+            "name": name,
+            "version_cmd": [],
+            "version_regex": "",
+            "versions": versions
+        })
+    }
+
+    /// An installation in `root` with cmake 3.24.0 and 3.30.2 and ninja 1.12.1 installed.
+    fn context(root: &Path) -> InstallationContext {
+        let tools_dir = root.join("tools");
+        for dir in ["cmake/3.24.0", "cmake/3.30.2", "ninja/1.12.1"] {
+            fs::create_dir_all(tools_dir.join(dir)).unwrap();
+        }
+        let tools_file: ToolsFile = serde_json::from_value(serde_json::json!({
+            "version": 3,
+            "tools": [
+                tool("cmake", &[("3.24.0", "recommended"), ("3.30.2", "supported")]),
+                tool("ninja", &[("1.11.1", "recommended"), ("1.12.1", "supported")]),
+                tool("openocd", &[("0.12.0", "recommended")]),
+            ]
+        }))
+        .unwrap();
+        InstallationContext {
+            config_path: root.join("eim_idf.json"),
+            installation: IdfInstallation {
+                activation_script: String::new(),
+                id: "v5.5".to_string(),
+                idf_tools_path: tools_dir.to_string_lossy().into_owned(),
+                name: "v5.5".to_string(),
+                path: root.join("v5.5").join("esp-idf").to_string_lossy().into_owned(),
+                python: String::new(),
+                installation_config: None,
+                idf_commit: None,
+                idf_source: None,
+            },
+            settings: Settings::default(),
+            tools_file,
+        }
+    }
+
+    #[test]
+    fn test_installed_versions_prefers_chosen_version() {
+        let root = TempDir::new().unwrap();
+        let mut context = context(root.path());
+
+        let version_of = |context: &InstallationContext, name: &str| {
+            context.installed_versions().get(name).map(|(version, _)| version.clone())
+        };
+        assert_eq!(version_of(&context, "cmake"), Some("3.24.0".to_string()));
+        // The recommended version is not installed, the newest installed one is used
+        assert_eq!(version_of(&context, "ninja"), Some("1.12.1".to_string()));
+        assert_eq!(version_of(&context, "openocd"), None);
+
+        context.settings.tool_versions = Some(HashMap::from([("cmake".to_string(), "3.30.2".to_string())]));
+        assert_eq!(version_of(&context, "cmake"), Some("3.30.2".to_string()));
+    }
+
+    #[test]
+    fn test_remove_version_keeps_versions_used_elsewhere() {
+        let root = TempDir::new().unwrap();
+        let context = context(root.path());
+        let tools_dir = context.tools_dir();
+        let used = HashSet::from([("cmake".to_string(), "3.24.0".to_string())]);
+
+        assert!(!context.remove_version("cmake", "3.24.0", Some(&used)).unwrap());
+        assert!(tools_dir.join("cmake").join("3.24.0").exists());
+        assert!(context.remove_version("cmake", "3.30.2", Some(&used)).unwrap());
+        assert!(!tools_dir.join("cmake").join("3.30.2").exists());
+        // Without knowing what the other installations use, nothing is removed
+        assert!(!context.remove_version("ninja", "1.12.1", None).unwrap());
+        assert_eq!(versions_on_disk(&tools_dir, "ninja"), vec!["1.12.1".to_string()]);
+    }
+
+    /// Stored settings that are neither JSON nor bincode: 7 is no valid tag of an optional
+    /// setting.
+    fn unreadable_settings_blob() -> Vec<u8> {
+        b"\x07 not settings".to_vec()
+    }
+
+    /// Records the installation of `context` in its eim_idf.json.
+    fn write_ide_config(context: &InstallationContext) {
+        let mut ide_config = IdfConfig {
+            git_path: String::new(),
+            idf_installed: vec![context.installation.clone()],
+            idf_selected_id: context.installation.id.clone(),
+            eim_path: None,
+            version: None,
+        };
+        ide_config.to_file(&context.config_path, true, false).unwrap();
+    }
+
+    #[test]
+    fn test_unreadable_stored_settings_are_not_replaced() {
+        let root = TempDir::new().unwrap();
+        let mut context = context(root.path());
+        let unreadable = unreadable_settings_blob();
+        context.installation.installation_config = Some(Base64Bytes::new(unreadable.clone()));
+        write_ide_config(&context);
+
+        assert_eq!(stored_settings(&context.installation).tool_versions, None);
+        assert!(context.ensure_settings_readable().is_err());
+        context.settings.tool_versions = Some(HashMap::from([("cmake".to_string(), "3.30.2".to_string())]));
+        assert!(context.save_settings().is_err());
+
+        let stored = IdfConfig::from_file(&context.config_path).unwrap();
+        let config = stored.idf_installed[0].installation_config.as_ref().unwrap();
+        assert_eq!(config.as_slice(), unreadable.as_slice());
+
+        // Readable settings are saved as usual
+        context.installation.installation_config = None;
+        write_ide_config(&context);
+        context.save_settings().unwrap();
+        let saved = IdfConfig::from_file(&context.config_path).unwrap();
+        assert_eq!(
+            stored_settings(&saved.idf_installed[0]).tool_versions,
+            context.settings.tool_versions
+        );
+    }
+
+    #[test]
+    fn test_tool_changes_keep_unreadable_stored_settings() {
+        let root = TempDir::new().unwrap();
+        let mut context = context(root.path());
+        let tools_json = Path::new(&context.installation.path).join("tools").join("tools.json");
+        fs::create_dir_all(tools_json.parent().unwrap()).unwrap();
+        fs::write(&tools_json, serde_json::to_vec(&context.tools_file).unwrap()).unwrap();
+        let unreadable = unreadable_settings_blob();
+        context.installation.installation_config = Some(Base64Bytes::new(unreadable.clone()));
+        write_ide_config(&context);
+        let before = fs::read(&context.config_path).unwrap();
+
+        let result = remove_tool("v5.5", "cmake", Some(&context.config_path), false);
+
+        assert!(result.unwrap_err().contains("can not be read"));
+        assert_eq!(fs::read(&context.config_path).unwrap(), before);
+        assert_eq!(versions_on_disk(&context.tools_dir(), "cmake").len(), 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_tool_changes_work_on_installations_of_released_eim() {
+        let root = TempDir::new().unwrap();
+        let mut context = context(root.path());
+        context.installation.activation_script =
+            root.path().join("activate_idf_v5.5.sh").to_string_lossy().into_owned();
+        for tool in context.tools_file.tools.iter_mut().filter(|t| t.name == "cmake") {
+            tool.install = "on_request".to_string();
+        }
+        let tools_json = Path::new(&context.installation.path).join("tools").join("tools.json");
+        fs::create_dir_all(tools_json.parent().unwrap()).unwrap();
+        fs::write(&tools_json, serde_json::to_vec(&context.tools_file).unwrap()).unwrap();
+        let released = Settings {
+            mirror: Some("https://dl.espressif.cn/github_assets".to_string()),
+            ..Settings::default()
+        };
+        context.installation.installation_config = Some(Base64Bytes::new(
+            crate::test_fixtures::released_settings_blob(&released),
+        ));
+        write_ide_config(&context);
+
+        let mut change = remove_tool("v5.5", "cmake", Some(&context.config_path), false).unwrap();
+
+        change.versions.sort();
+        assert_eq!(change.versions, vec!["3.24.0".to_string(), "3.30.2".to_string()]);
+        assert!(versions_on_disk(&context.tools_dir(), "cmake").is_empty());
+        // The settings are written back in the current format, keeping the stored ones
+        let saved = IdfConfig::from_file(&context.config_path).unwrap();
+        let config = saved.idf_installed[0].installation_config.as_ref().unwrap();
+        assert_eq!(config.as_slice().first(), Some(&b'{'));
+        assert_eq!(stored_settings(&saved.idf_installed[0]).mirror, released.mirror);
+    }
+}
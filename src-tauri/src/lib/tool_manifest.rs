@@ -0,0 +1,445 @@
+//! Manifests of the files a tool installation consists of.
+//!
+//! Once a tool archive is extracted, [`write`] records every file below the tool's version
+//! directory with its size, modification time and sha256 (symlinks with their target) in
+//! [`MANIFEST_FILE_NAME`] inside that directory. [`check`] compares the directory with its
+//! manifest to find files that were modified or removed since, and [`uninstall`] removes exactly
+//! the recorded files, leaving anything added afterwards in place. [`check_quick`] trusts files
+//! whose size and modification time still match and only hashes the others; it is meant for
+//! the checks done on every installation, `verify` and `fix` use the full [`check`].
+//!
+//! Tools installed before manifests were written have none; every function treats them as
+//! unknown rather than damaged.
+//!
+//! Entries whose path leads outside of the tool's version directory are never followed: the
+//! checks report them as modified and [`uninstall`] refuses the whole manifest.
+
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+pub const MANIFEST_FILE_NAME: &str = ".eim_manifest.json";
+
+/// Version of the manifest format, bumped on incompatible changes.
+const MANIFEST_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// Path relative to the tool's version directory, with `/` separators
+    pub path: String,
+    pub size: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// Modification time in nanoseconds since the Unix epoch, missing in older manifests
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified: Option<u64>,
+    /// Target of a symlink, which is recorded instead of being followed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ToolManifest {
+    pub version: u32,
+    pub files: Vec<ManifestEntry>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    Missing,
+    Modified,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ManifestChange {
+    pub path: String,
+    pub kind: ChangeKind,
+}
+
+/// Records the content of `tool_dir` in its manifest, replacing an older one.
+pub fn write(tool_dir: &Path) -> io::Result<ToolManifest> {
+    let manifest = create(tool_dir)?;
+    let json = serde_json::to_vec_pretty(&manifest).map_err(io::Error::other)?;
+    fs::write(tool_dir.join(MANIFEST_FILE_NAME), json)?;
+    debug!(
+        "Wrote manifest of {} files for {}",
+        manifest.files.len(),
+        tool_dir.display()
+    );
+    Ok(manifest)
+}
+
+/// Reads the manifest of `tool_dir`, `Ok(None)` if the tool has none.
+pub fn read(tool_dir: &Path) -> io::Result<Option<ToolManifest>> {
+    let content = match fs::read(tool_dir.join(MANIFEST_FILE_NAME)) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let manifest: ToolManifest = serde_json::from_slice(&content)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    if manifest.version != MANIFEST_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unsupported manifest version {}", manifest.version),
+        ));
+    }
+    Ok(Some(manifest))
+}
+
+/// Builds the manifest of `tool_dir` without writing it.
+pub fn create(tool_dir: &Path) -> io::Result<ToolManifest> {
+    let mut files = Vec::new();
+    collect_entries(tool_dir, tool_dir, &mut files)?;
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(ToolManifest {
+        version: MANIFEST_VERSION,
+        files,
+    })
+}
+
+fn collect_entries(root: &Path, dir: &Path, files: &mut Vec<ManifestEntry>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let file_type = entry.file_type()?;
+        let relative = relative_path(root, &path);
+        if relative == MANIFEST_FILE_NAME {
+            continue;
+        }
+        if file_type.is_symlink() {
+            let target = fs::read_link(&path)?;
+            files.push(ManifestEntry {
+                path: relative,
+                size: 0,
+                sha256: None,
+                modified: None,
+                link: Some(target.to_string_lossy().replace('\\', "/")),
+            });
+        } else if file_type.is_dir() {
+            collect_entries(root, &path, files)?;
+        } else {
+            let metadata = entry.metadata()?;
+            files.push(ManifestEntry {
+                path: relative,
+                size: metadata.len(),
+                sha256: Some(sha256_of(&path)?),
+                modified: modified_nanos(&metadata),
+                link: None,
+            });
+        }
+    }
+    Ok(())
+}
+
+fn relative_path(root: &Path, path: &Path) -> String {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// The path of `entry` below `tool_dir`. Manifests are plain files next to the tool, so entries
+/// that are not purely relative, e.g. `../other` or an absolute path, are rejected instead of
+/// reaching outside of the tool.
+fn entry_path(tool_dir: &Path, entry: &ManifestEntry) -> io::Result<PathBuf> {
+    let relative = Path::new(&entry.path);
+    let normal = relative.components().next().is_some()
+        && relative.components().all(|c| matches!(c, Component::Normal(_)));
+    if !normal {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("manifest entry '{}' is not a path inside the tool", entry.path),
+        ));
+    }
+    Ok(tool_dir.join(relative))
+}
+
+fn modified_nanos(metadata: &fs::Metadata) -> Option<u64> {
+    let since_epoch = metadata.modified().ok()?.duration_since(std::time::UNIX_EPOCH).ok()?;
+    u64::try_from(since_epoch.as_nanos()).ok()
+}
+
+fn sha256_of(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = [0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Compares `tool_dir` with `manifest`. Files added since the manifest was written are not
+/// reported, tools create caches and compiled Python files next to their own files.
+pub fn check(tool_dir: &Path, manifest: &ToolManifest) -> Vec<ManifestChange> {
+    check_with(tool_dir, manifest, false)
+}
+
+/// Like [`check`], but files whose size and modification time match the manifest are taken as
+/// unchanged without hashing them.
+pub fn check_quick(tool_dir: &Path, manifest: &ToolManifest) -> Vec<ManifestChange> {
+    check_with(tool_dir, manifest, true)
+}
+
+fn check_with(tool_dir: &Path, manifest: &ToolManifest, quick: bool) -> Vec<ManifestChange> {
+    manifest
+        .files
+        .iter()
+        .filter_map(|entry| {
+            let kind = entry_change(tool_dir, entry, quick)?;
+            Some(ManifestChange {
+                path: entry.path.clone(),
+                kind,
+            })
+        })
+        .collect()
+}
+
+fn entry_change(tool_dir: &Path, entry: &ManifestEntry, quick: bool) -> Option<ChangeKind> {
+    let path = match entry_path(tool_dir, entry) {
+        Ok(path) => path,
+        Err(e) => {
+            warn!("{} in the manifest of {}", e, tool_dir.display());
+            return Some(ChangeKind::Modified);
+        }
+    };
+    let metadata = match fs::symlink_metadata(&path) {
+        Ok(metadata) => metadata,
+        Err(_) => return Some(ChangeKind::Missing),
+    };
+    if let Some(link) = &entry.link {
+        return match fs::read_link(&path) {
+            Ok(target) if target.to_string_lossy().replace('\\', "/") == *link => None,
+            _ => Some(ChangeKind::Modified),
+        };
+    }
+    if !metadata.is_file() || metadata.len() != entry.size {
+        return Some(ChangeKind::Modified);
+    }
+    if quick && entry.modified.is_some() && entry.modified == modified_nanos(&metadata) {
+        return None;
+    }
+    match (&entry.sha256, sha256_of(&path)) {
+        (Some(expected), Ok(actual)) if *expected == actual => None,
+        (None, Ok(_)) => None,
+        _ => Some(ChangeKind::Modified),
+    }
+}
+
+/// Checks `tool_dir` against its manifest. `None` when the tool has no readable manifest.
+pub fn check_dir(tool_dir: &Path) -> Option<Vec<ManifestChange>> {
+    check_dir_with(tool_dir, false)
+}
+
+/// [`check_dir`] with the shortcut of [`check_quick`].
+pub fn check_dir_quick(tool_dir: &Path) -> Option<Vec<ManifestChange>> {
+    check_dir_with(tool_dir, true)
+}
+
+fn check_dir_with(tool_dir: &Path, quick: bool) -> Option<Vec<ManifestChange>> {
+    match read(tool_dir) {
+        Ok(manifest) => manifest.map(|manifest| check_with(tool_dir, &manifest, quick)),
+        Err(e) => {
+            warn!("Ignoring manifest of {}: {}", tool_dir.display(), e);
+            None
+        }
+    }
+}
+
+/// Removes the files recorded in the manifest of `tool_dir`, the manifest itself and the
+/// directories left empty, and returns the files that were kept because the manifest does not
+/// list them. A tool without a manifest is removed as a whole.
+pub fn uninstall(tool_dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let Some(manifest) = read(tool_dir).unwrap_or(None) else {
+        fs::remove_dir_all(tool_dir)?;
+        return Ok(Vec::new());
+    };
+
+    // Nothing is removed if any of the entries is rejected
+    let paths = manifest
+        .files
+        .iter()
+        .map(|entry| entry_path(tool_dir, entry))
+        .collect::<io::Result<Vec<_>>>()?;
+
+    let mut directories = HashSet::new();
+    for (entry, path) in manifest.files.iter().zip(paths) {
+        match fs::remove_file(&path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            // Directory symlinks are removed with remove_dir on Windows
+            Err(_) if entry.link.is_some() => fs::remove_dir(&path)?,
+            Err(e) => return Err(e),
+        }
+        let mut parent = path.parent();
+        while let Some(dir) = parent.filter(|dir| dir.starts_with(tool_dir) && *dir != tool_dir) {
+            directories.insert(dir.to_path_buf());
+            parent = dir.parent();
+        }
+    }
+    fs::remove_file(tool_dir.join(MANIFEST_FILE_NAME))?;
+
+    // Deepest directories first, so parents are empty by the time they are reached
+    let mut directories: Vec<PathBuf> = directories.into_iter().collect();
+    directories.sort_by_key(|dir| std::cmp::Reverse(dir.components().count()));
+    directories.push(tool_dir.to_path_buf());
+    for dir in directories {
+        if fs::read_dir(&dir).is_ok_and(|mut entries| entries.next().is_none()) {
+            fs::remove_dir(&dir)?;
+        }
+    }
+
+    let mut kept = Vec::new();
+    if tool_dir.exists() {
+        collect_remaining(tool_dir, &mut kept)?;
+    }
+    Ok(kept)
+}
+
+fn collect_remaining(dir: &Path, kept: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            collect_remaining(&entry.path(), kept)?;
+        } else {
+            kept.push(entry.path());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn tool_dir(root: &Path) -> PathBuf {
+        let dir = root.join("cmake").join("3.30.2");
+        fs::create_dir_all(dir.join("bin")).unwrap();
+        fs::create_dir_all(dir.join("share").join("cmake")).unwrap();
+        fs::write(dir.join("bin").join("cmake"), b"#!/bin/sh\n").unwrap();
+        fs::write(dir.join("share").join("cmake").join("Modules.txt"), b"modules").unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_check_reports_modified_and_missing_files() {
+        let root = TempDir::new().unwrap();
+        let dir = tool_dir(root.path());
+        let manifest = write(&dir).unwrap();
+        assert_eq!(manifest.files.len(), 2);
+        assert_eq!(check_dir(&dir), Some(vec![]));
+
+        // Same size, different content
+        fs::write(dir.join("bin").join("cmake"), b"#!/bin/zz\n").unwrap();
+        fs::remove_file(dir.join("share").join("cmake").join("Modules.txt")).unwrap();
+        fs::write(dir.join("bin").join("cache"), b"added later").unwrap();
+
+        assert_eq!(
+            check_dir(&dir),
+            Some(vec![
+                ManifestChange {
+                    path: "bin/cmake".to_string(),
+                    kind: ChangeKind::Modified
+                },
+                ManifestChange {
+                    path: "share/cmake/Modules.txt".to_string(),
+                    kind: ChangeKind::Missing
+                },
+            ])
+        );
+        assert_eq!(check_dir(root.path()), None);
+    }
+
+    #[test]
+    fn test_quick_check_trusts_unchanged_size_and_time() {
+        let root = TempDir::new().unwrap();
+        let dir = tool_dir(root.path());
+        let manifest = write(&dir).unwrap();
+        let cmake = dir.join("bin").join("cmake");
+        let recorded = fs::metadata(&cmake).unwrap().modified().unwrap();
+
+        // Same size and content changed, but the modification time put back: only the full
+        // check notices
+        fs::write(&cmake, b"#!/bin/zz\n").unwrap();
+        File::options().write(true).open(&cmake).unwrap().set_modified(recorded).unwrap();
+        assert!(check_quick(&dir, &manifest).is_empty());
+        assert_eq!(check(&dir, &manifest).len(), 1);
+
+        // A newer modification time makes the quick check hash the file
+        let later = recorded + std::time::Duration::from_secs(10);
+        File::options().write(true).open(&cmake).unwrap().set_modified(later).unwrap();
+        assert_eq!(
+            check_dir_quick(&dir),
+            Some(vec![ManifestChange {
+                path: "bin/cmake".to_string(),
+                kind: ChangeKind::Modified
+            }])
+        );
+
+        // Touching a file without changing it is not a change
+        fs::write(&cmake, b"#!/bin/sh\n").unwrap();
+        File::options().write(true).open(&cmake).unwrap().set_modified(later).unwrap();
+        assert!(check_quick(&dir, &manifest).is_empty());
+    }
+
+    #[test]
+    fn test_uninstall_keeps_unlisted_files() {
+        let root = TempDir::new().unwrap();
+        let dir = tool_dir(root.path());
+        write(&dir).unwrap();
+        fs::write(dir.join("bin").join("user.cfg"), b"mine").unwrap();
+
+        let kept = uninstall(&dir).unwrap();
+        assert_eq!(kept, vec![dir.join("bin").join("user.cfg")]);
+        assert!(!dir.join("share").exists());
+        assert!(!dir.join(MANIFEST_FILE_NAME).exists());
+
+        let other = tool_dir(&root.path().join("other"));
+        write(&other).unwrap();
+        assert!(uninstall(&other).unwrap().is_empty());
+        assert!(!other.exists());
+    }
+
+    #[test]
+    fn test_entries_outside_of_the_tool_are_rejected() {
+        let root = TempDir::new().unwrap();
+        let dir = tool_dir(root.path());
+        let outside = root.path().join("cmake").join("outside.txt");
+        fs::write(&outside, b"not part of the tool").unwrap();
+        let mut manifest = write(&dir).unwrap();
+        manifest.files.push(ManifestEntry {
+            path: "../outside.txt".to_string(),
+            size: 20,
+            sha256: None,
+            modified: None,
+            link: None,
+        });
+        fs::write(dir.join(MANIFEST_FILE_NAME), serde_json::to_vec(&manifest).unwrap()).unwrap();
+
+        assert_eq!(
+            check_dir(&dir),
+            Some(vec![ManifestChange {
+                path: "../outside.txt".to_string(),
+                kind: ChangeKind::Modified
+            }])
+        );
+        let err = uninstall(&dir).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(outside.exists());
+        assert!(dir.join("bin").join("cmake").exists());
+    }
+}
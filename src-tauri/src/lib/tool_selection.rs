@@ -55,7 +55,9 @@ pub fn get_tools_json_url(
 
 /// Fetch tools.json from URL (blocking)
 pub fn fetch_tools_file(url: &str) -> Result<ToolsFile, ToolSelectionError> {
-    let response: reqwest::blocking::Response = reqwest::blocking::get(url)
+    let response: reqwest::blocking::Response = crate::proxy::blocking_client_builder()
+        .build()
+        .and_then(|client| client.get(url).send())
         .map_err(|e: reqwest::Error| ToolSelectionError::HttpError(e.to_string()))?;
 
     let text = response
@@ -72,7 +74,11 @@ pub fn fetch_tools_file(url: &str) -> Result<ToolsFile, ToolSelectionError> {
 
 /// Fetch tools.json from URL (async)
 pub async fn fetch_tools_file_async(url: &str) -> Result<ToolsFile, ToolSelectionError> {
-    let response:reqwest::Response = reqwest::get(url)
+    let client = crate::proxy::client_builder()
+        .build()
+        .map_err(|e: reqwest::Error| ToolSelectionError::HttpError(e.to_string()))?;
+    let response:reqwest::Response = client.get(url)
+        .send()
         .await
         .map_err(|e: reqwest::Error| ToolSelectionError::HttpError(e.to_string()))?;
 
@@ -0,0 +1,654 @@
+//! Shared store of extracted tools, deduplicated across IDF installations.
+//!
+//! When enabled, every tool version is extracted once into
+//! `<store>/<tool name>/<version>/<platform>` and each installation's tool directory links to it,
+//! either with a directory symlink or with a tree of hard links. The store keeps a reference
+//! list in `refs.json`: for every entry, the IDF installations (identified by their ESP-IDF
+//! path) using it and the links created for them. An entry is only deleted once no
+//! installation references it anymore.
+//!
+//! Several eim processes (the CLI and the GUI, or parallel CI jobs) may use one store, so
+//! `refs.json` is only changed while holding an exclusive lock on a file in `.locks`, and every
+//! entry has a lock file of its own which is held while the entry is extracted or deleted.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+
+pub const TOOL_STORE_REFS_FILE: &str = "refs.json";
+/// Written into an entry once extraction finished, entries without it are never linked.
+const COMPLETE_MARKER: &str = ".eim_store_complete";
+
+/// Directory of the store holding the lock files.
+const LOCKS_DIR: &str = ".locks";
+/// Lock file serializing read-modify-write cycles of `refs.json` across threads and processes.
+const REFS_LOCK_FILE: &str = "refs.lock";
+
+/// Exclusive lock on a store entry, released when dropped.
+#[derive(Debug)]
+pub struct EntryLock {
+    _file: File,
+}
+
+/// How an installation's tool directory points into the store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkMode {
+    Symlink,
+    Hardlink,
+}
+
+impl LinkMode {
+    /// Parses the `tool_store_link_mode` setting, anything unknown means symlinks.
+    pub fn from_setting(value: Option<&str>) -> Self {
+        match value.map(|v| v.to_ascii_lowercase()) {
+            Some(v) if v == "hardlink" => LinkMode::Hardlink,
+            _ => LinkMode::Symlink,
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct StoreRefs {
+    #[serde(default)]
+    entries: BTreeMap<String, EntryRefs>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct EntryRefs {
+    /// Owner (ESP-IDF path of the installation) -> links created for it
+    #[serde(default)]
+    owners: BTreeMap<String, Vec<PathBuf>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ToolStore {
+    root: PathBuf,
+    link_mode: LinkMode,
+    owner: Option<String>,
+}
+
+impl ToolStore {
+    pub fn new(root: impl Into<PathBuf>, link_mode: LinkMode) -> Self {
+        Self {
+            root: root.into(),
+            link_mode,
+            owner: None,
+        }
+    }
+
+    /// Builds the store configured in `settings`, or `None` when the store is not enabled.
+    pub fn from_settings(settings: &crate::settings::Settings) -> Option<Self> {
+        let root = settings.tool_store_dir.clone()?;
+        if root.as_os_str().is_empty() {
+            return None;
+        }
+        Some(Self::new(
+            root,
+            LinkMode::from_setting(settings.tool_store_link_mode.as_deref()),
+        ))
+    }
+
+    /// Returns a copy of the store which records its links as references of `owner`,
+    /// the ESP-IDF path of the installation being set up.
+    pub fn with_owner(mut self, owner: impl Into<String>) -> Self {
+        self.owner = Some(owner.into());
+        self
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn entry_key(tool_name: &str, version: &str, platform: &str) -> String {
+        format!("{}/{}/{}", tool_name, version, platform)
+    }
+
+    /// Directory holding the extracted tool.
+    pub fn entry_dir(&self, tool_name: &str, version: &str, platform: &str) -> PathBuf {
+        self.root.join(tool_name).join(version).join(platform)
+    }
+
+    /// Whether the tool was fully extracted into the store.
+    pub fn is_complete(&self, tool_name: &str, version: &str, platform: &str) -> bool {
+        self.entry_dir(tool_name, version, platform)
+            .join(COMPLETE_MARKER)
+            .exists()
+    }
+
+    fn lock_path(&self, name: &str) -> PathBuf {
+        self.root.join(LOCKS_DIR).join(name)
+    }
+
+    fn entry_lock_path(&self, tool_name: &str, version: &str, platform: &str) -> PathBuf {
+        self.lock_path(&format!("{}@{}@{}.lock", tool_name, version, platform))
+    }
+
+    /// Waits for the exclusive lock on the entry. Hold it while the entry is checked, prepared
+    /// and extracted, so no other thread or process deletes it in the meantime.
+    pub fn lock_entry(&self, tool_name: &str, version: &str, platform: &str) -> io::Result<EntryLock> {
+        let file = open_lock_file(&self.entry_lock_path(tool_name, version, platform))?;
+        file.lock()?;
+        Ok(EntryLock { _file: file })
+    }
+
+    /// Takes the lock on the entry if nobody holds it, `None` while it is in use.
+    fn try_lock_entry(&self, key: &str) -> io::Result<Option<EntryLock>> {
+        let file = open_lock_file(&self.lock_path(&format!("{}.lock", key.replace('/', "@"))))?;
+        match file.try_lock() {
+            Ok(()) => Ok(Some(EntryLock { _file: file })),
+            Err(fs::TryLockError::WouldBlock) => Ok(None),
+            Err(fs::TryLockError::Error(e)) => Err(e),
+        }
+    }
+
+    /// Waits for the lock on `refs.json`, released when the returned file is dropped.
+    fn lock_refs(&self) -> io::Result<File> {
+        let file = open_lock_file(&self.lock_path(REFS_LOCK_FILE))?;
+        file.lock()?;
+        Ok(file)
+    }
+
+    /// Returns an empty entry directory to extract the tool into, dropping leftovers of an
+    /// extraction that never completed. The caller holds the [`ToolStore::lock_entry`] lock.
+    pub fn prepare_entry(&self, tool_name: &str, version: &str, platform: &str) -> io::Result<PathBuf> {
+        let dir = self.entry_dir(tool_name, version, platform);
+        if dir.exists() {
+            debug!("Removing incomplete tool store entry {}", dir.display());
+            fs::remove_dir_all(&dir)?;
+        }
+        fs::create_dir_all(&dir)?;
+        Ok(dir)
+    }
+
+    pub fn mark_complete(&self, tool_name: &str, version: &str, platform: &str) -> io::Result<()> {
+        fs::write(
+            self.entry_dir(tool_name, version, platform).join(COMPLETE_MARKER),
+            b"",
+        )
+    }
+
+    /// Whether [`ToolStore::link`] may replace whatever is at `link_path`: nothing, a symlink, or
+    /// a directory of hard links the store created. A directory that is not a store link, e.g. a
+    /// tool extracted before the store was enabled, is never replaced.
+    pub fn can_link(&self, link_path: &Path) -> bool {
+        match fs::symlink_metadata(link_path) {
+            Err(_) => true,
+            Ok(metadata) if metadata.file_type().is_symlink() => true,
+            Ok(_) => self
+                .lock_refs()
+                .and_then(|_guard| self.read_refs())
+                .map(|refs| is_recorded_link(&refs, link_path))
+                .unwrap_or(false),
+        }
+    }
+
+    /// Makes `link_path` point to the store entry and records the reference of the current owner.
+    ///
+    /// Fails when `link_path` is a directory the store did not create, see [`ToolStore::can_link`].
+    pub fn link(&self, tool_name: &str, version: &str, platform: &str, link_path: &Path) -> io::Result<()> {
+        let owner = self.owner.clone().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "Tool store has no owner to reference the tool")
+        })?;
+        let entry_dir = self.entry_dir(tool_name, version, platform);
+
+        let _guard = self.lock_refs()?;
+        let mut refs = self.read_refs()?;
+        if !points_to(link_path, &entry_dir) {
+            let is_directory = fs::symlink_metadata(link_path)
+                .map(|m| !m.file_type().is_symlink())
+                .unwrap_or(false);
+            if is_directory && !is_recorded_link(&refs, link_path) {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!(
+                        "{} already exists and is not a tool store link, remove it to use the tool store",
+                        link_path.display()
+                    ),
+                ));
+            }
+            remove_link(link_path)?;
+            if let Some(parent) = link_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            create_link(&entry_dir, link_path, self.link_mode)?;
+            debug!("Linked {} -> {}", link_path.display(), entry_dir.display());
+        }
+
+        let links = refs
+            .entries
+            .entry(Self::entry_key(tool_name, version, platform))
+            .or_default()
+            .owners
+            .entry(owner)
+            .or_default();
+        if !links.iter().any(|l| l == link_path) {
+            links.push(link_path.to_path_buf());
+        }
+        self.write_refs(&refs)
+    }
+
+    /// Number of installations referencing the entry.
+    pub fn reference_count(&self, tool_name: &str, version: &str, platform: &str) -> usize {
+        let Ok(_guard) = self.lock_refs() else {
+            return 0;
+        };
+        self.read_refs()
+            .ok()
+            .and_then(|refs| {
+                refs.entries
+                    .get(&Self::entry_key(tool_name, version, platform))
+                    .map(|e| e.owners.len())
+            })
+            .unwrap_or(0)
+    }
+
+    /// Drops every reference held by `owner`, as well as references of owners missing from
+    /// `live_owners` (installations that no longer exist). Links no longer used by anyone are
+    /// removed, and so are entries without references.
+    ///
+    /// Returns the keys (`tool/version/platform`) of the removed entries.
+    pub fn release_owner(&self, owner: &str, live_owners: &[String]) -> io::Result<Vec<String>> {
+        let _guard = self.lock_refs()?;
+        let mut refs = self.read_refs()?;
+
+        let mut released_links: Vec<PathBuf> = Vec::new();
+        for entry in refs.entries.values_mut() {
+            let dropped: Vec<String> = entry
+                .owners
+                .keys()
+                .filter(|o| o.as_str() == owner || !live_owners.contains(o))
+                .cloned()
+                .collect();
+            for o in dropped {
+                if let Some(links) = entry.owners.remove(&o) {
+                    released_links.extend(links);
+                }
+            }
+        }
+
+        // Several installations may share one tools directory and so the same link
+        for link in released_links {
+            let still_used = refs
+                .entries
+                .values()
+                .any(|e| e.owners.values().any(|links| links.contains(&link)));
+            if !still_used {
+                if let Err(e) = remove_link(&link) {
+                    warn!("Failed to remove tool link {}: {}", link.display(), e);
+                }
+            }
+        }
+
+        let unused = self.remove_unused_entries(&mut refs)?;
+        self.write_refs(&refs)?;
+        Ok(unused)
+    }
+
+    /// Drops the reference the current owner holds through `link_path`, e.g. when a single tool
+    /// is removed from an installation. The link is removed unless another owner shares it, and
+    /// the entry once nothing references it anymore.
+    ///
+    /// Returns whether the owner referenced an entry through `link_path`.
+    pub fn release_link(&self, link_path: &Path) -> io::Result<bool> {
+        let Some(owner) = self.owner.as_deref() else {
+            return Ok(false);
+        };
+        let _guard = self.lock_refs()?;
+        let mut refs = self.read_refs()?;
+
+        let mut released = false;
+        for entry in refs.entries.values_mut() {
+            if let Some(links) = entry.owners.get_mut(owner) {
+                let count = links.len();
+                links.retain(|l| l != link_path);
+                released |= links.len() != count;
+                if links.is_empty() {
+                    entry.owners.remove(owner);
+                }
+            }
+        }
+        if !released {
+            return Ok(false);
+        }
+
+        let still_used = refs
+            .entries
+            .values()
+            .any(|e| e.owners.values().any(|links| links.iter().any(|l| l == link_path)));
+        if !still_used {
+            remove_link(link_path)?;
+        }
+        self.remove_unused_entries(&mut refs)?;
+        self.write_refs(&refs)?;
+        Ok(true)
+    }
+
+    /// Drops the references every owner holds through `link_path`, e.g. when gc removes a tool
+    /// version no installation uses anymore. The link is removed, and so are the entries nothing
+    /// references afterwards.
+    ///
+    /// Returns whether any owner referenced an entry through `link_path`.
+    pub fn forget_link(&self, link_path: &Path) -> io::Result<bool> {
+        let _guard = self.lock_refs()?;
+        let mut refs = self.read_refs()?;
+
+        let mut released = false;
+        for entry in refs.entries.values_mut() {
+            entry.owners.retain(|_, links| {
+                let count = links.len();
+                links.retain(|l| l != link_path);
+                released |= links.len() != count;
+                !links.is_empty()
+            });
+        }
+        if !released {
+            return Ok(false);
+        }
+
+        remove_link(link_path)?;
+        self.remove_unused_entries(&mut refs)?;
+        self.write_refs(&refs)?;
+        Ok(true)
+    }
+
+    /// Deletes the entries nobody references from `refs` and from disk, returning their keys.
+    /// Entries another thread or process is extracting are left for a later cleanup.
+    fn remove_unused_entries(&self, refs: &mut StoreRefs) -> io::Result<Vec<String>> {
+        let candidates: Vec<String> = refs
+            .entries
+            .iter()
+            .filter(|(_, e)| e.owners.is_empty())
+            .map(|(k, _)| k.clone())
+            .collect();
+        let mut unused = Vec::new();
+        for key in candidates {
+            let Some(_entry_lock) = self.try_lock_entry(&key)? else {
+                debug!("Tool store entry {} is in use, not removing it", key);
+                continue;
+            };
+            refs.entries.remove(&key);
+            let dir = self.root.join(key);
+            if dir.exists() {
+                fs::remove_dir_all(&dir)?;
+                info!("Removed unused tool {} from the tool store", key);
+            }
+            // Clean up the now empty version and tool directories
+            let mut parent = dir.parent();
+            while let Some(p) = parent {
+                if p == self.root || fs::remove_dir(p).is_err() {
+                    break;
+                }
+                parent = p.parent();
+            }
+            unused.push(key);
+        }
+        Ok(unused)
+    }
+
+    fn read_refs(&self) -> io::Result<StoreRefs> {
+        let path = self.root.join(TOOL_STORE_REFS_FILE);
+        if !path.exists() {
+            return Ok(StoreRefs::default());
+        }
+        let content = fs::read_to_string(&path)?;
+        serde_json::from_str(&content).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    fn write_refs(&self, refs: &StoreRefs) -> io::Result<()> {
+        fs::create_dir_all(&self.root)?;
+        let content = serde_json::to_string_pretty(refs)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let tmp = self.root.join(format!("{}.tmp", TOOL_STORE_REFS_FILE));
+        fs::write(&tmp, content)?;
+        fs::rename(tmp, self.root.join(TOOL_STORE_REFS_FILE))
+    }
+}
+
+fn open_lock_file(path: &Path) -> io::Result<File> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    OpenOptions::new().create(true).truncate(false).write(true).open(path)
+}
+
+/// Whether some owner references an entry through `link_path`.
+fn is_recorded_link(refs: &StoreRefs, link_path: &Path) -> bool {
+    refs.entries
+        .values()
+        .any(|e| e.owners.values().any(|links| links.iter().any(|l| l == link_path)))
+}
+
+fn points_to(link_path: &Path, target: &Path) -> bool {
+    match fs::read_link(link_path) {
+        Ok(current) => current == target,
+        Err(_) => false,
+    }
+}
+
+/// Removes a link created by the store: a symlink, or a directory of hard links.
+fn remove_link(link_path: &Path) -> io::Result<()> {
+    let metadata = match fs::symlink_metadata(link_path) {
+        Ok(m) => m,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    if metadata.file_type().is_symlink() {
+        // Directory symlinks are removed with remove_dir on Windows
+        fs::remove_file(link_path).or_else(|_| fs::remove_dir(link_path))
+    } else if metadata.is_dir() {
+        fs::remove_dir_all(link_path)
+    } else {
+        fs::remove_file(link_path)
+    }
+}
+
+fn create_link(entry_dir: &Path, link_path: &Path, mode: LinkMode) -> io::Result<()> {
+    match mode {
+        LinkMode::Symlink => match symlink_dir(entry_dir, link_path) {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                // Windows without developer mode can not create symlinks
+                warn!(
+                    "Failed to symlink {} ({}), falling back to hard links",
+                    link_path.display(),
+                    e
+                );
+                hardlink_tree(entry_dir, link_path)
+            }
+        },
+        LinkMode::Hardlink => hardlink_tree(entry_dir, link_path),
+    }
+}
+
+#[cfg(unix)]
+fn symlink_dir(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn symlink_dir(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_dir(target, link)
+}
+
+/// Recreates the directory tree of `src` at `dst` with every file hard linked.
+fn hardlink_tree(src: &Path, dst: &Path) -> io::Result<()> {
+    fs::create_dir_all(dst)?;
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let from = entry.path();
+        let to = dst.join(entry.file_name());
+        let file_type = entry.file_type()?;
+        if entry.file_name() == COMPLETE_MARKER {
+            continue;
+        }
+        if file_type.is_dir() {
+            hardlink_tree(&from, &to)?;
+        } else if file_type.is_symlink() {
+            // Toolchains contain relative symlinks, copy them as they are
+            let target = fs::read_link(&from)?;
+            #[cfg(unix)]
+            std::os::unix::fs::symlink(&target, &to)?;
+            #[cfg(windows)]
+            {
+                if from.is_dir() {
+                    std::os::windows::fs::symlink_dir(&target, &to)?;
+                } else {
+                    std::os::windows::fs::symlink_file(&target, &to)?;
+                }
+            }
+        } else {
+            fs::hard_link(&from, &to)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn populate(store: &ToolStore, tool: &str, version: &str) -> PathBuf {
+        let dir = store.prepare_entry(tool, version, "linux-amd64").unwrap();
+        fs::create_dir_all(dir.join("bin")).unwrap();
+        fs::write(dir.join("bin").join(tool), b"binary").unwrap();
+        store.mark_complete(tool, version, "linux-amd64").unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_entry_is_removed_only_with_last_reference() {
+        for mode in [LinkMode::Symlink, LinkMode::Hardlink] {
+            let store_dir = TempDir::new().unwrap();
+            let installs = TempDir::new().unwrap();
+            let store = ToolStore::new(store_dir.path(), mode);
+            let entry = populate(&store, "cmake", "3.30.2");
+
+            let link_a = installs.path().join("a").join("cmake").join("3.30.2");
+            let link_b = installs.path().join("b").join("cmake").join("3.30.2");
+            store.clone().with_owner("/idf/a").link("cmake", "3.30.2", "linux-amd64", &link_a).unwrap();
+            store.clone().with_owner("/idf/b").link("cmake", "3.30.2", "linux-amd64", &link_b).unwrap();
+
+            assert_eq!(store.reference_count("cmake", "3.30.2", "linux-amd64"), 2);
+            assert_eq!(fs::read(link_a.join("bin").join("cmake")).unwrap(), b"binary");
+
+            let live = vec!["/idf/a".to_string(), "/idf/b".to_string()];
+            let removed = store.release_owner("/idf/a", &live).unwrap();
+            assert!(removed.is_empty());
+            assert!(fs::symlink_metadata(&link_a).is_err());
+            assert!(entry.exists());
+            assert_eq!(fs::read(link_b.join("bin").join("cmake")).unwrap(), b"binary");
+
+            let removed = store.release_owner("/idf/b", &["/idf/b".to_string()]).unwrap();
+            assert_eq!(removed, vec!["cmake/3.30.2/linux-amd64".to_string()]);
+            assert!(!entry.exists());
+            assert!(fs::symlink_metadata(&link_b).is_err());
+        }
+    }
+
+    #[test]
+    fn test_shared_link_is_kept_while_referenced() {
+        let store_dir = TempDir::new().unwrap();
+        let installs = TempDir::new().unwrap();
+        let store = ToolStore::new(store_dir.path(), LinkMode::Symlink);
+        populate(&store, "ninja", "1.12.1");
+
+        // Both installations use the same tools directory
+        let link = installs.path().join("ninja").join("1.12.1");
+        store.clone().with_owner("/idf/a").link("ninja", "1.12.1", "linux-amd64", &link).unwrap();
+        store.clone().with_owner("/idf/b").link("ninja", "1.12.1", "linux-amd64", &link).unwrap();
+
+        let live = vec!["/idf/a".to_string(), "/idf/b".to_string()];
+        store.release_owner("/idf/a", &live).unwrap();
+        assert!(link.join("bin").join("ninja").exists());
+    }
+
+    #[test]
+    fn test_release_link_drops_only_that_reference() {
+        let store_dir = TempDir::new().unwrap();
+        let installs = TempDir::new().unwrap();
+        let store = ToolStore::new(store_dir.path(), LinkMode::Symlink);
+        let qemu = populate(&store, "qemu-xtensa", "9.2.2");
+        let cmake = populate(&store, "cmake", "3.30.2");
+
+        let owner = store.clone().with_owner("/idf/a");
+        let qemu_link = installs.path().join("qemu-xtensa").join("9.2.2");
+        let cmake_link = installs.path().join("cmake").join("3.30.2");
+        owner.link("qemu-xtensa", "9.2.2", "linux-amd64", &qemu_link).unwrap();
+        owner.link("cmake", "3.30.2", "linux-amd64", &cmake_link).unwrap();
+
+        assert!(owner.release_link(&qemu_link).unwrap());
+        assert!(fs::symlink_metadata(&qemu_link).is_err());
+        assert!(!qemu.exists());
+        assert!(cmake.exists());
+        assert_eq!(store.reference_count("cmake", "3.30.2", "linux-amd64"), 1);
+        assert!(!owner.release_link(&qemu_link).unwrap());
+    }
+
+    #[test]
+    fn test_link_refuses_to_replace_a_directory_it_did_not_create() {
+        let store_dir = TempDir::new().unwrap();
+        let installs = TempDir::new().unwrap();
+        let store = ToolStore::new(store_dir.path(), LinkMode::Symlink);
+        populate(&store, "cmake", "3.30.2");
+
+        let existing = installs.path().join("cmake").join("3.30.2");
+        fs::create_dir_all(existing.join("bin")).unwrap();
+        fs::write(existing.join("bin").join("cmake"), b"user build").unwrap();
+
+        let owner = store.clone().with_owner("/idf/a");
+        assert!(!owner.can_link(&existing));
+        assert!(owner.link("cmake", "3.30.2", "linux-amd64", &existing).is_err());
+        assert_eq!(fs::read(existing.join("bin").join("cmake")).unwrap(), b"user build");
+        assert_eq!(store.reference_count("cmake", "3.30.2", "linux-amd64"), 0);
+
+        // Hard link trees the store created are recorded and may be replaced
+        let hardlinks = ToolStore::new(store_dir.path(), LinkMode::Hardlink).with_owner("/idf/b");
+        let linked = installs.path().join("b").join("cmake").join("3.30.2");
+        hardlinks.link("cmake", "3.30.2", "linux-amd64", &linked).unwrap();
+        assert!(hardlinks.can_link(&linked));
+        hardlinks.link("cmake", "3.30.2", "linux-amd64", &linked).unwrap();
+    }
+
+    #[test]
+    fn test_entry_in_use_is_not_removed() {
+        let store_dir = TempDir::new().unwrap();
+        let installs = TempDir::new().unwrap();
+        let store = ToolStore::new(store_dir.path(), LinkMode::Symlink);
+        let entry = populate(&store, "ninja", "1.12.1");
+        let link = installs.path().join("ninja").join("1.12.1");
+        store.clone().with_owner("/idf/a").link("ninja", "1.12.1", "linux-amd64", &link).unwrap();
+
+        // Another process holds the entry, e.g. while extracting it again
+        let lock = store.lock_entry("ninja", "1.12.1", "linux-amd64").unwrap();
+        let removed = store.release_owner("/idf/a", &[]).unwrap();
+        assert!(removed.is_empty());
+        assert!(entry.exists());
+
+        drop(lock);
+        let removed = store.release_owner("/idf/a", &[]).unwrap();
+        assert_eq!(removed, vec!["ninja/1.12.1/linux-amd64".to_string()]);
+        assert!(!entry.exists());
+    }
+
+    #[test]
+    fn test_stale_owners_are_dropped() {
+        let store_dir = TempDir::new().unwrap();
+        let installs = TempDir::new().unwrap();
+        let store = ToolStore::new(store_dir.path(), LinkMode::Symlink);
+        let entry = populate(&store, "ccache", "4.10.2");
+
+        let link = installs.path().join("ccache").join("4.10.2");
+        store.clone().with_owner("/idf/gone").link("ccache", "4.10.2", "linux-amd64", &link).unwrap();
+        store.clone().with_owner("/idf/a").link("ccache", "4.10.2", "linux-amd64", &link).unwrap();
+
+        // "/idf/gone" is no longer in eim_idf.json, so removing "/idf/a" frees the entry
+        let removed = store.release_owner("/idf/a", &[]).unwrap();
+        assert_eq!(removed.len(), 1);
+        assert!(!entry.exists());
+    }
+}
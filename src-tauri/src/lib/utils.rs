@@ -7,7 +7,6 @@ use anyhow::{anyhow, Result, Error};
 use log::{debug, error, info, warn};
 use rust_search::SearchBuilder;
 use serde::{Deserialize, Serialize};
-use tar::Archive;
 use zstd::{decode_all, Decoder};
 use filetime::{FileTime, set_file_mtime, set_symlink_file_times};
 #[cfg(not(windows))]
@@ -294,6 +293,7 @@ pub fn find_by_name_and_extension(path: &Path, name: &str, extension: &str) -> V
 /// # Purpose
 ///
 /// This function verifies if the specified directory contains a valid ESP-IDF setup by checking for the existence of the "tools.json" file in the "tools" subdirectory.
+/// Nothing else is required, so a sparse checkout leaving out e.g. `docs` or `examples` is accepted.
 ///
 /// # Parameters
 ///
@@ -309,7 +309,7 @@ pub fn is_valid_idf_directory(path: &str) -> bool {
     if !tools_json_path.exists() {
         return false;
     }
-    match read_and_parse_tools_file(tools_json_path.to_str().unwrap()) {
+    match read_and_parse_tools_file(tools_json_path.to_str().unwrap(), None) {
         Ok(_) => {
             true
         }
@@ -647,6 +647,7 @@ pub fn parse_tool_set_config(config_path: &str, idf_json_path: Option<&PathBuf>)
             false,
         );
 
+        let idf_commit = crate::git_tools::head_commit_id(Path::new(&tool_set.idf_location));
         let installation = IdfInstallation {
             id: tool_set.id.to_string(),
             activation_script: paths.activation_script.to_string_lossy().into_owned(),
@@ -655,6 +656,8 @@ pub fn parse_tool_set_config(config_path: &str, idf_json_path: Option<&PathBuf>)
             python: paths.python_path.to_string_lossy().into_owned(),
             idf_tools_path: new_idf_tools_path,
             installation_config: None,
+            idf_commit,
+            idf_source: None,
         };
 
         current_config.idf_installed.push(installation);
@@ -869,20 +872,18 @@ pub fn extract_zst_archive_with_buffer_size(
     let decoder = Decoder::new(buf_reader)?;
 
     info!("Extracting tar archive...");
-    // Create tar archive from the streaming decoder
-    let mut archive = Archive::new(decoder);
-
-    // Extract the archive directly from the stream
-    archive.unpack(extract_to)?;
+    // Extract the archive directly from the stream, entries escaping extract_to are rejected
+    crate::safe_extract::unpack_tar(decoder, extract_to)?;
 
     info!("Archive extracted successfully to: {:?}", extract_to);
     Ok(())
 }
 
-/// Convenience function that replaces the original with better defaults
+/// Extracts an offline archive. Kept for callers of the former zstd-only extractor, the format
+/// is detected by [`crate::decompress_archive`] like for every other archive.
 pub fn extract_zst_archive(archive_path: &Path, extract_to: &Path) -> Result<(), Box<dyn std::error::Error>> {
-    // Use the streaming version with a reasonable default buffer size (64KB)
-    extract_zst_archive_with_buffer_size(archive_path, extract_to, 64 * 1024)
+    crate::decompress_archive(&archive_path.to_string_lossy(), &extract_to.to_string_lossy())?;
+    Ok(())
 }
 
 pub fn copy_dir_contents(src: &Path, dst: &Path) -> io::Result<()> {
@@ -1084,7 +1085,7 @@ pub async fn measure_url_score_head(url: &str, timeout: Duration) -> Result<u32,
     let base_url = get_base_url(url);
 
     // Build the HTTP client
-    let client = reqwest::Client::builder()
+    let client = crate::proxy::client_builder()
         .timeout(timeout)
         .redirect(reqwest::redirect::Policy::limited(5))
         .build()
@@ -1114,7 +1115,7 @@ pub async fn measure_url_score_get(url: &str, timeout: Duration) -> Option<u32>
     let base_url = get_base_url(url)?;
 
     // Build the HTTP client
-    let client = reqwest::Client::builder()
+    let client = crate::proxy::client_builder()
         .timeout(timeout)
         .redirect(reqwest::redirect::Policy::limited(5))
         .build()
@@ -1185,6 +1186,72 @@ pub async fn calculate_mirrors_latency(mirrors: &[&str]) -> Vec<MirrorEntry> {
     mirror_entries
 }
 
+/// Orders mirrors for failover: `preferred` first, then the remaining entries by latency,
+/// unreachable mirrors last.
+pub fn mirror_failover_order(preferred: Option<&str>, entries: &[MirrorEntry]) -> Vec<String> {
+    let mut sorted = entries.to_vec();
+    sorted.sort();
+    let mut order: Vec<String> = preferred.map(|p| vec![p.to_string()]).unwrap_or_default();
+    for entry in sorted {
+        if !order.contains(&entry.url) {
+            order.push(entry.url);
+        }
+    }
+    order
+}
+
+/// The mirrors to move on to when a download or clone fails on the selected one.
+///
+/// The selected mirror always comes first, the other candidates follow in the order computed by
+/// [`calculate_mirrors_latency`]. Latency is measured only once a fallback is actually needed,
+/// and at most once for an instance and all its clones.
+#[derive(Debug, Clone)]
+pub struct MirrorFailover {
+    preferred: Option<String>,
+    candidates: Vec<String>,
+    ranked: std::sync::Arc<tokio::sync::OnceCell<Vec<MirrorEntry>>>,
+}
+
+impl MirrorFailover {
+    pub fn new(preferred: Option<&str>, candidates: &[&str]) -> Self {
+        Self {
+            preferred: preferred.map(str::to_string),
+            candidates: candidates.iter().map(|c| c.to_string()).collect(),
+            ranked: std::sync::Arc::new(tokio::sync::OnceCell::new()),
+        }
+    }
+
+    /// Uses latency entries measured earlier instead of measuring the candidates again.
+    pub fn with_latency_entries(preferred: Option<&str>, entries: Vec<MirrorEntry>) -> Self {
+        Self {
+            preferred: preferred.map(str::to_string),
+            candidates: entries.iter().map(|e| e.url.clone()).collect(),
+            ranked: std::sync::Arc::new(tokio::sync::OnceCell::new_with(Some(entries))),
+        }
+    }
+
+    pub fn preferred(&self) -> Option<&str> {
+        self.preferred.as_deref()
+    }
+
+    /// All mirrors in the order they should be tried.
+    pub async fn mirrors(&self) -> Vec<String> {
+        let entries = self
+            .ranked
+            .get_or_init(|| async {
+                let candidates: Vec<&str> = self.candidates.iter().map(String::as_str).collect();
+                calculate_mirrors_latency(&candidates).await
+            })
+            .await;
+        mirror_failover_order(self.preferred(), entries)
+    }
+
+    /// The best mirror not in `tried`, or `None` once all of them failed.
+    pub async fn next_mirror(&self, tried: &[String]) -> Option<String> {
+        self.mirrors().await.into_iter().find(|m| !tried.contains(m))
+    }
+}
+
 #[cfg(target_os = "windows")]
 pub fn is_running_elevated() -> bool {
     unsafe {
@@ -1793,4 +1860,24 @@ set(IDF_VERSION_MAJOR 5)
             assert_eq!(map.iter().find(|e| e.url == *m).unwrap().latency, None);
         }
     }
+
+    #[tokio::test]
+    async fn test_mirror_failover_tries_preferred_then_by_latency() {
+        let entries = vec![
+            MirrorEntry { url: "https://slow".to_string(), latency: Some(300) },
+            MirrorEntry { url: "https://down".to_string(), latency: None },
+            MirrorEntry { url: "https://fast".to_string(), latency: Some(20) },
+            MirrorEntry { url: "https://chosen".to_string(), latency: Some(100) },
+        ];
+        let failover = MirrorFailover::with_latency_entries(Some("https://chosen"), entries);
+
+        assert_eq!(
+            failover.mirrors().await,
+            vec!["https://chosen", "https://fast", "https://slow", "https://down"]
+        );
+        let tried = vec!["https://chosen".to_string(), "https://fast".to_string()];
+        assert_eq!(failover.next_mirror(&tried).await.as_deref(), Some("https://slow"));
+        let tried = failover.mirrors().await;
+        assert_eq!(failover.next_mirror(&tried).await, None);
+    }
 }
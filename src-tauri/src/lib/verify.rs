@@ -0,0 +1,509 @@
+//! Integrity check of an installed ESP-IDF.
+//!
+//! Installations break in ways the installer never notices: a tool directory removed by hand,
+//! a Python environment rebuilt with missing packages, a checkout moved to another commit. This
+//! module re-checks everything an installation recorded in `eim_idf.json` relies on and reports
+//! every check as passed, failed or skipped:
+//!
+//! - every tool from `tools.json` the installation needs runs its `version_cmd` and reports the
+//!   version `tools.json` recommends, its files match the manifest written when it was extracted
+//!   (see [`crate::tool_manifest`]), and each of its export paths exists
+//! - the Python interpreter of the virtual environment starts, and every package named in the
+//!   requirements files of the installed features is present
+//! - the activation script exists
+//! - the ESP-IDF checkout is still at the commit recorded when it was installed, and the patches
+//!   of its patch queue are applied and unchanged (see [`crate::idf_patches`])
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+use crate::command_executor::execute_command;
+use crate::idf_config::{IdfConfig, IdfInstallation};
+use crate::idf_tools::{
+    apply_tool_versions, filter_tools_by_target, get_platform_identification,
+    read_and_parse_tools_file, verify_tool_installation, Tool, ToolStatus,
+};
+use crate::tool_management::stored_settings;
+use crate::version_manager::{find_installation, get_default_config_path, list_idf_features};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckKind {
+    /// A tool from tools.json answers its version command with the expected version
+    Tool,
+    /// The files of an installed tool against the manifest written when it was extracted
+    ToolFiles,
+    /// One of the export paths of an installed tool
+    ExportPath,
+    /// The interpreter of the Python virtual environment
+    PythonInterpreter,
+    /// The packages listed in one requirements file
+    PythonPackages,
+    ActivationScript,
+    /// The ESP-IDF checkout against the commit recorded at install time
+    GitCheckout,
+    /// A patch of the installation's patch queue applied to the checkout
+    Patch,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Pass,
+    Fail,
+    /// The check could not be run, e.g. because what it depends on already failed
+    Skipped,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Check {
+    pub kind: CheckKind,
+    /// Tool name, path or requirements file the check is about
+    pub subject: String,
+    pub status: CheckStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct VerifyReport {
+    pub id: String,
+    pub name: String,
+    pub path: String,
+    /// True when no check failed
+    pub passed: bool,
+    pub checks: Vec<Check>,
+}
+
+impl VerifyReport {
+    /// The checks that failed.
+    pub fn failures(&self) -> impl Iterator<Item = &Check> {
+        self.checks.iter().filter(|c| c.status == CheckStatus::Fail)
+    }
+}
+
+impl Check {
+    fn new(kind: CheckKind, subject: impl Into<String>, status: CheckStatus, detail: Option<String>) -> Self {
+        Self {
+            kind,
+            subject: subject.into(),
+            status,
+            detail,
+        }
+    }
+
+    fn pass(kind: CheckKind, subject: impl Into<String>) -> Self {
+        Self::new(kind, subject, CheckStatus::Pass, None)
+    }
+
+    fn fail(kind: CheckKind, subject: impl Into<String>, detail: impl Into<String>) -> Self {
+        Self::new(kind, subject, CheckStatus::Fail, Some(detail.into()))
+    }
+
+    fn skipped(kind: CheckKind, subject: impl Into<String>, detail: impl Into<String>) -> Self {
+        Self::new(kind, subject, CheckStatus::Skipped, Some(detail.into()))
+    }
+}
+
+/// Verifies the installation matching `identifier` (id, name or path) in the eim_idf.json at
+/// `config_path`, or the default one.
+pub fn verify_installation(identifier: &str, config_path: Option<&PathBuf>) -> Result<VerifyReport, String> {
+    let config_path = config_path.cloned().unwrap_or_else(get_default_config_path);
+    let ide_config = IdfConfig::from_file(&config_path)
+        .map_err(|e| format!("Failed to read eim_idf.json: {}", e))?;
+    let installation = find_installation(&ide_config, identifier)?;
+
+    let mut report = verify(installation);
+
+    // Features are resolved through the same config file the installation was found in
+    let requirement_files = match list_idf_features(Some(identifier), Some(&config_path)) {
+        Ok(features) => features
+            .features
+            .into_iter()
+            .filter(|f| f.installed)
+            .map(|f| PathBuf::from(&installation.path).join(f.feature.requirement_path))
+            .collect(),
+        Err(e) => {
+            debug!("Falling back to the core requirements: {}", e);
+            vec![PathBuf::from(&installation.path)
+                .join("tools")
+                .join("requirements")
+                .join("requirements.core.txt")]
+        }
+    };
+    report.checks.extend(check_python_packages(installation, &requirement_files));
+    report.passed = report.failures().next().is_none();
+    Ok(report)
+}
+
+/// Runs every check that does not depend on the features of the installation.
+fn verify(installation: &IdfInstallation) -> VerifyReport {
+    let mut checks = check_tools(installation);
+    checks.push(check_python_interpreter(installation));
+    checks.push(check_activation_script(installation));
+    checks.push(check_git_checkout(installation));
+    checks.extend(check_patches(installation));
+
+    let mut report = VerifyReport {
+        id: installation.id.clone(),
+        name: installation.name.clone(),
+        path: installation.path.clone(),
+        passed: false,
+        checks,
+    };
+    report.passed = report.failures().next().is_none();
+    report
+}
+
+fn check_tools(installation: &IdfInstallation) -> Vec<Check> {
+    let tools_json = Path::new(&installation.path).join("tools").join("tools.json");
+    let settings = stored_settings(installation);
+    let tools_file = match read_and_parse_tools_file(&tools_json.to_string_lossy(), settings.tools_overlay.as_deref()) {
+        // Pinned and upgraded tools are expected in their chosen version
+        Ok(tools_file) => apply_tool_versions(tools_file, &settings.chosen_tool_versions()),
+        Err(e) => {
+            return vec![Check::fail(
+                CheckKind::Tool,
+                tools_json.to_string_lossy(),
+                format!("Failed to read tools.json: {}", e),
+            )]
+        }
+    };
+    let targets = settings.target.unwrap_or_else(|| vec!["all".to_string()]);
+    let platform = get_platform_identification().ok();
+    let tools_dir = PathBuf::from(&installation.idf_tools_path);
+
+    let mut checks = Vec::new();
+    for tool in &tools_file.tools {
+        let Some(version) = expected_version(tool, platform.as_deref()) else {
+            continue;
+        };
+        let version_dir = tools_dir.join(&tool.name).join(version);
+        let required = tool.install == "always"
+            && !filter_tools_by_target(vec![tool.clone()], &targets).is_empty();
+        // Optional tools are only checked when they were installed
+        if !required && !tools_dir.join(&tool.name).exists() {
+            continue;
+        }
+
+        checks.push(match verify_tool_installation(&tool.name, &tools_file, &tools_dir, version) {
+            Ok(ToolStatus::Correct { version }) => Check::new(
+                CheckKind::Tool,
+                &tool.name,
+                CheckStatus::Pass,
+                Some(version),
+            ),
+            Ok(ToolStatus::DifferentVersion { installed, expected }) => Check::fail(
+                CheckKind::Tool,
+                &tool.name,
+                format!("version {} found, {} expected", installed, expected),
+            ),
+            Ok(ToolStatus::Missing) => Check::fail(
+                CheckKind::Tool,
+                &tool.name,
+                format!("{} {} is not installed", tool.name, version),
+            ),
+            Err(e) => Check::fail(CheckKind::Tool, &tool.name, e.to_string()),
+        });
+
+        if version_dir.exists() {
+            checks.push(check_tool_files(&tool.name, &version_dir));
+        }
+        for export_path in &tool.export_paths {
+            let path = export_path.iter().fold(version_dir.clone(), |path, level| path.join(level));
+            checks.push(if path.is_dir() {
+                Check::pass(CheckKind::ExportPath, path.to_string_lossy())
+            } else {
+                Check::fail(
+                    CheckKind::ExportPath,
+                    path.to_string_lossy(),
+                    format!("export path of {} does not exist", tool.name),
+                )
+            });
+        }
+    }
+    checks
+}
+
+fn check_tool_files(tool_name: &str, version_dir: &Path) -> Check {
+    let subject = version_dir.to_string_lossy();
+    match crate::tool_manifest::check_dir(version_dir) {
+        None => Check::skipped(CheckKind::ToolFiles, subject, "no file manifest was written for this tool"),
+        Some(changes) if changes.is_empty() => Check::pass(CheckKind::ToolFiles, subject),
+        Some(changes) => {
+            let paths: Vec<&str> = changes.iter().take(5).map(|c| c.path.as_str()).collect();
+            let more = if changes.len() > paths.len() {
+                format!(" and {} more", changes.len() - paths.len())
+            } else {
+                String::new()
+            };
+            Check::fail(
+                CheckKind::ToolFiles,
+                subject,
+                format!("files of {} were modified or removed: {}{}", tool_name, paths.join(", "), more),
+            )
+        }
+    }
+}
+
+/// The version of `tool` an installation on this platform gets, the same one
+/// [`verify_tool_installation`] checks against. `None` for tools that are never installed here.
+fn expected_version<'a>(tool: &'a Tool, platform: Option<&str>) -> Option<&'a str> {
+    if tool.install == "never" {
+        return None;
+    }
+    let version = tool
+        .versions
+        .iter()
+        .find(|v| v.status == "recommended")
+        .or_else(|| tool.versions.first())?;
+    let has_download = version.downloads.contains_key("any")
+        || platform.is_some_and(|p| version.downloads.contains_key(p));
+    has_download.then_some(version.name.as_str())
+}
+
+fn check_python_interpreter(installation: &IdfInstallation) -> Check {
+    let python = &installation.python;
+    if !Path::new(python).exists() {
+        return Check::fail(CheckKind::PythonInterpreter, python, "interpreter does not exist");
+    }
+    match execute_command(python, &["--version"]) {
+        Ok(output) if output.status.success() => {
+            // Python 2 and early 3 releases print the version to stderr
+            let version = if output.stdout.is_empty() { &output.stderr } else { &output.stdout };
+            Check::new(
+                CheckKind::PythonInterpreter,
+                python,
+                CheckStatus::Pass,
+                Some(String::from_utf8_lossy(version).trim().to_string()),
+            )
+        }
+        Ok(output) => Check::fail(
+            CheckKind::PythonInterpreter,
+            python,
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ),
+        Err(e) => Check::fail(CheckKind::PythonInterpreter, python, e.to_string()),
+    }
+}
+
+#[derive(Deserialize)]
+struct PipPackage {
+    name: String,
+}
+
+/// One check per requirements file, comparing the packages it names with `pip list`.
+fn check_python_packages(installation: &IdfInstallation, requirement_files: &[PathBuf]) -> Vec<Check> {
+    let skip_all = |detail: &str| -> Vec<Check> {
+        requirement_files
+            .iter()
+            .map(|file| Check::skipped(CheckKind::PythonPackages, file.to_string_lossy(), detail))
+            .collect()
+    };
+    if !Path::new(&installation.python).exists() {
+        return skip_all("the Python interpreter is missing");
+    }
+    let installed: HashSet<String> = match execute_command(
+        &installation.python,
+        &["-m", "pip", "list", "--format=json", "--disable-pip-version-check"],
+    ) {
+        Ok(output) if output.status.success() => {
+            match serde_json::from_slice::<Vec<PipPackage>>(&output.stdout) {
+                Ok(packages) => packages.iter().map(|p| normalize_package_name(&p.name)).collect(),
+                Err(e) => return skip_all(&format!("unexpected output of pip list: {}", e)),
+            }
+        }
+        Ok(output) => return skip_all(String::from_utf8_lossy(&output.stderr).trim()),
+        Err(e) => return skip_all(&e.to_string()),
+    };
+
+    requirement_files
+        .iter()
+        .map(|file| {
+            let subject = file.to_string_lossy();
+            let content = match std::fs::read_to_string(file) {
+                Ok(content) => content,
+                Err(e) => {
+                    return Check::fail(CheckKind::PythonPackages, subject, format!("Failed to read: {}", e))
+                }
+            };
+            let missing: Vec<String> = content
+                .lines()
+                .filter_map(requirement_name)
+                .filter(|name| !installed.contains(name))
+                .collect();
+            if missing.is_empty() {
+                Check::pass(CheckKind::PythonPackages, subject)
+            } else {
+                Check::fail(
+                    CheckKind::PythonPackages,
+                    subject,
+                    format!("missing packages: {}", missing.join(", ")),
+                )
+            }
+        })
+        .collect()
+}
+
+/// Normalized name of the package a requirements line asks for.
+///
+/// Comments, pip options and requirements with environment markers (which only apply to some
+/// platforms) yield `None`.
+fn requirement_name(line: &str) -> Option<String> {
+    let line = line.split('#').next().unwrap_or_default().trim();
+    if line.is_empty() || line.starts_with('-') || line.contains(';') {
+        return None;
+    }
+    let end = line
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.'))
+        .unwrap_or(line.len());
+    let name = &line[..end];
+    (!name.is_empty()).then(|| normalize_package_name(name))
+}
+
+/// Package names as compared by pip: case insensitive, with `-`, `_` and `.` equivalent.
+fn normalize_package_name(name: &str) -> String {
+    name.to_ascii_lowercase().replace(['_', '.'], "-")
+}
+
+fn check_activation_script(installation: &IdfInstallation) -> Check {
+    let script = &installation.activation_script;
+    if Path::new(script).is_file() {
+        Check::pass(CheckKind::ActivationScript, script)
+    } else {
+        Check::fail(CheckKind::ActivationScript, script, "activation script does not exist")
+    }
+}
+
+fn check_git_checkout(installation: &IdfInstallation) -> Check {
+    let Some(recorded) = &installation.idf_commit else {
+        return Check::skipped(
+            CheckKind::GitCheckout,
+            &installation.path,
+            "no commit was recorded when the installation was made",
+        );
+    };
+    match crate::git_tools::head_commit_id(Path::new(&installation.path)) {
+        Some(head) if head == *recorded => Check::new(
+            CheckKind::GitCheckout,
+            &installation.path,
+            CheckStatus::Pass,
+            Some(head),
+        ),
+        Some(head) => Check::fail(
+            CheckKind::GitCheckout,
+            &installation.path,
+            format!("checked out at {}, installed at {}", head, recorded),
+        ),
+        None => Check::fail(
+            CheckKind::GitCheckout,
+            &installation.path,
+            "not a readable git checkout",
+        ),
+    }
+}
+
+/// Every applied patch passes while the checkout still has exactly the changes the patch queue
+/// made. Patches of the queue that are not applied fail; the queue is compared by file name,
+/// and not at all when its files are gone.
+fn check_patches(installation: &IdfInstallation) -> Vec<Check> {
+    let idf_path = Path::new(&installation.path);
+    let applied = crate::idf_patches::applied_patches(idf_path);
+    let mut checks: Vec<Check> = match crate::idf_patches::patches_intact(idf_path) {
+        Some(true) => applied.iter().map(|name| Check::pass(CheckKind::Patch, name)).collect(),
+        Some(false) => applied
+            .iter()
+            .map(|name| Check::fail(CheckKind::Patch, name, "the checkout was changed after the patches were applied"))
+            .collect(),
+        None => applied
+            .iter()
+            .map(|name| Check::skipped(CheckKind::Patch, name, "the checkout can not be compared"))
+            .collect(),
+    };
+
+    let queue = stored_settings(installation).idf_patches.unwrap_or_default();
+    match crate::idf_patches::patch_files(&queue) {
+        Ok(files) => checks.extend(
+            files
+                .iter()
+                .filter_map(|file| file.file_name().map(|name| name.to_string_lossy().into_owned()))
+                .filter(|name| !applied.contains(name))
+                .map(|name| Check::fail(CheckKind::Patch, name, "in the patch queue but not applied")),
+        ),
+        Err(e) => debug!("Not comparing the applied patches with the patch queue: {}", e),
+    }
+    checks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::write_tools_json;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_requirement_name() {
+        assert_eq!(requirement_name("esp-idf-kconfig>=2.5.0,<3.0.0"), Some("esp-idf-kconfig".to_string()));
+        assert_eq!(requirement_name("PyYAML ~= 6.0 # comment"), Some("pyyaml".to_string()));
+        assert_eq!(requirement_name("esp_coredump[extra]"), Some("esp-coredump".to_string()));
+        assert_eq!(requirement_name("zope.interface"), Some("zope-interface".to_string()));
+        assert_eq!(requirement_name("--only-binary cryptography"), None);
+        assert_eq!(requirement_name("windows-curses; sys_platform == 'win32'"), None);
+        assert_eq!(requirement_name("# comment"), None);
+    }
+
+    #[test]
+    fn test_verify_reports_broken_parts() {
+        let root = TempDir::new().unwrap();
+        let idf_path = root.path().join("v5.5").join("esp-idf");
+        write_tools_json(&idf_path, &[("cmake", "3.30.2"), ("ninja", "1.12.1")]);
+        let tools_dir = root.path().join("tools");
+        fs::create_dir_all(tools_dir.join("cmake").join("3.30.2").join("bin")).unwrap();
+        let activation_script = root.path().join("activate_idf_v5.5.sh");
+        fs::write(&activation_script, "").unwrap();
+
+        let installation = IdfInstallation {
+            activation_script: activation_script.to_string_lossy().into_owned(),
+            id: "v5.5".to_string(),
+            idf_tools_path: tools_dir.to_string_lossy().into_owned(),
+            name: "v5.5".to_string(),
+            path: idf_path.to_string_lossy().into_owned(),
+            python: tools_dir.join("python").join("bin").join("python").to_string_lossy().into_owned(),
+            installation_config: None,
+            idf_commit: None,
+            idf_source: None,
+        };
+
+        let report = verify(&installation);
+        let statuses: Vec<(CheckKind, &str, CheckStatus)> = report
+            .checks
+            .iter()
+            .map(|c| (c.kind, c.subject.as_str(), c.status))
+            .collect();
+        assert!(!report.passed);
+        assert!(statuses.contains(&(CheckKind::Tool, "cmake", CheckStatus::Pass)));
+        assert!(statuses.contains(&(CheckKind::Tool, "ninja", CheckStatus::Fail)));
+        assert!(statuses.contains(&(CheckKind::PythonInterpreter, installation.python.as_str(), CheckStatus::Fail)));
+        assert!(statuses.contains(&(CheckKind::ActivationScript, installation.activation_script.as_str(), CheckStatus::Pass)));
+        assert!(statuses.contains(&(CheckKind::GitCheckout, installation.path.as_str(), CheckStatus::Skipped)));
+        assert_eq!(
+            report.failures().filter(|c| c.kind == CheckKind::ExportPath).count(),
+            1
+        );
+        assert!(report.checks.iter().any(|c| c.kind == CheckKind::ToolFiles && c.status == CheckStatus::Skipped));
+
+        let cmake_dir = tools_dir.join("cmake").join("3.30.2");
+        fs::write(cmake_dir.join("bin").join("cmake"), "").unwrap();
+        crate::tool_manifest::write(&cmake_dir).unwrap();
+        fs::write(cmake_dir.join("bin").join("cmake"), "changed").unwrap();
+        let report = verify(&installation);
+        assert!(report.failures().any(|c| c.kind == CheckKind::ToolFiles));
+
+        let packages = check_python_packages(&installation, &[idf_path.join("requirements.core.txt")]);
+        assert_eq!(packages[0].status, CheckStatus::Skipped);
+    }
+}
@@ -21,6 +21,8 @@ use crate::{
     settings::Settings,
     idf_tools::{Tool, Version},
     idf_features::{FeatureInfo, RequirementsMetadata},
+    tool_store::ToolStore,
+    git_object_store::GitObjectStore,
 };
 use serde::{Deserialize, Serialize};
 
@@ -263,6 +265,54 @@ pub fn find_shortcut_by_profile(custom_profile_filename: &str) -> anyhow::Result
     Ok(None)
 }
 
+/// Releases the tool store references held by `installation`, removing store entries no
+/// installation in `live_owners` uses anymore.
+///
+/// The store location is taken from the settings the installation was created with, so
+/// installations made without the tool store are left alone.
+fn release_tool_store_references(installation: &IdfInstallation, live_owners: &[String]) {
+    let Some(store) = installation
+        .installation_config
+        .as_ref()
+        .and_then(|bytes| Settings::from_installation_config(bytes.as_slice()).ok())
+        .and_then(|settings| ToolStore::from_settings(&settings))
+    else {
+        return;
+    };
+    match store.release_owner(&installation.path, live_owners) {
+        Ok(removed) => {
+            for key in removed {
+                info!("Removed tool {} which is no longer used by any installation", key);
+            }
+        }
+        Err(e) => warn!(
+            "Failed to release tool store references of {}: {}",
+            installation.name, e
+        ),
+    }
+}
+
+/// Drops the refs the shared git object store keeps for the clone at `idf_path`. The objects stay
+/// in the store, the remaining clones may borrow them.
+fn release_git_object_store_references(idf_path: &Path) {
+    let Some(store) = GitObjectStore::existing() else {
+        return;
+    };
+    match store.release(idf_path) {
+        Ok(0) => {}
+        Ok(count) => debug!(
+            "Released {} refs of {} in the git object store",
+            count,
+            idf_path.display()
+        ),
+        Err(e) => warn!(
+            "Failed to release the git object store refs of {}: {}",
+            idf_path.display(),
+            e
+        ),
+    }
+}
+
 /// Removes a single ESP-IDF version from the configuration file and its associated directories.
 ///
 /// This function reads the ESP-IDF configuration from the default location, removes the installation
@@ -301,6 +351,9 @@ pub fn remove_single_idf_version(
             )
         })?;
         if !keep_idf_folder {
+            // Released while the path still resolves to the same store refs
+            release_git_object_store_references(&installation_folder_path);
+
             // First remove the installation folder itself (e.g., esp-idf)
             match remove_directory_all(&installation_folder_path) {
                 Ok(_) => {}
@@ -325,6 +378,16 @@ pub fn remove_single_idf_version(
                     warn!("Could not check or remove parent directory: {}", e);
                 }
             }
+
+            // Drop the installation's references to the shared tool store, tools still used by
+            // another installation stay in place
+            let live_owners: Vec<String> = ide_config
+                .idf_installed
+                .iter()
+                .filter(|install| install.id != installation.id)
+                .map(|install| install.path.clone())
+                .collect();
+            release_tool_store_references(&installation, &live_owners);
         }
 
         match remove_directory_all(installation.clone().activation_script) {
@@ -530,7 +593,7 @@ pub async fn prepare_settings_for_fix_idf_installation(
                     // Recover the settings the installation was originally created with
                     match &v.installation_config {
                         Some(config_bytes) => {
-                            match bincode::deserialize::<Settings>(config_bytes.as_slice()) {
+                            match Settings::from_installation_config(config_bytes.as_slice()) {
                                 Ok(settings) => {
                                     info!("Recovered original installation settings for {}", v.name);
                                     original_settings = Some(settings);
@@ -601,6 +664,9 @@ pub struct ToolListIdfContext {
 pub struct ToolListEntry {
     pub tool: Tool,
     pub version_inspections: Vec<ToolVersionInspection>,
+    /// Version the tool is pinned to for this installation, which is then never outdated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pinned: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -659,6 +725,7 @@ pub fn list_idf_tools(
         ));
     }
 
+    let settings = crate::tool_management::stored_settings(installation);
     let tools_file = tools_json_path
         .to_str()
         .ok_or_else(|| {
@@ -668,9 +735,12 @@ pub fn list_idf_tools(
             )
         })
         .and_then(|s| {
-            crate::idf_tools::read_and_parse_tools_file(s)
+            crate::idf_tools::read_and_parse_tools_file(s, settings.tools_overlay.as_deref())
                 .map_err(|e| format!("Failed to read tools.json: {}", e))
         })?;
+    // Pinned and upgraded tools are expected in their chosen version
+    let tools_file = crate::idf_tools::apply_tool_versions(tools_file, &settings.chosen_tool_versions());
+    let pins = settings.tool_pins.unwrap_or_default();
 
     let recommended_versions: std::collections::HashMap<String, String> = tools_file
         .tools
@@ -739,7 +809,8 @@ pub fn list_idf_tools(
                     .max_by(|a, b| semver_order(a, b))
             })
             .unwrap_or_default();
-        if let Some(bi) = &biggest_installed {
+        let pinned = pins.get(&tool.name).cloned();
+        if let (Some(bi), None) = (&biggest_installed, &pinned) {
             if !biggest_available.is_empty() && is_newer_semver(&biggest_available, Some(bi)) {
                 outdated.push(ToolListOutdatedEntry {
                     name: tool.name.clone(),
@@ -752,6 +823,7 @@ pub fn list_idf_tools(
         tools.push(ToolListEntry {
             tool,
             version_inspections,
+            pinned,
         });
     }
 
@@ -844,7 +916,7 @@ pub fn list_idf_features(
     let selected_optional_features: Vec<String> = installation
         .installation_config
         .as_ref()
-        .and_then(|bytes| bincode::deserialize::<Settings>(bytes.as_slice()).ok())
+        .and_then(|bytes| Settings::from_installation_config(bytes.as_slice()).ok())
         .map(|settings| {
             settings
                 .idf_features_per_version
@@ -904,7 +976,7 @@ fn enumerate_on_disk_versions(tool_dir: &std::path::Path) -> Vec<(String, String
 /// matches the rest of the ESP-IDF tooling, where tool versions can carry a
 /// non-semver suffix such as `v0.12.0-esp32-20260304`. When `current` is
 /// `None`, any non-empty candidate is treated as newer than nothing.
-fn is_newer_semver(candidate: &str, current: Option<&str>) -> bool {
+pub(crate) fn is_newer_semver(candidate: &str, current: Option<&str>) -> bool {
     let cand = semver::Version::parse(candidate);
     let curr = current.and_then(|c| semver::Version::parse(c).ok());
     match (cand, curr) {
@@ -920,7 +992,7 @@ fn is_newer_semver(candidate: &str, current: Option<&str>) -> bool {
 /// plain lexicographic string comparison as the final tiebreaker so that
 /// non-semver versions (e.g. `v0.12.0-esp32-20260304`) still order
 /// deterministically and consistently with `is_newer_semver`.
-fn semver_order(a: &str, b: &str) -> std::cmp::Ordering {
+pub(crate) fn semver_order(a: &str, b: &str) -> std::cmp::Ordering {
     match (semver::Version::parse(a), semver::Version::parse(b)) {
         (Ok(av), Ok(bv)) => av.cmp(&bv),
         _ => a.cmp(b),
@@ -929,7 +1001,7 @@ fn semver_order(a: &str, b: &str) -> std::cmp::Ordering {
 
 /// Finds the installation in `ide_config` matching `identifier` by id, then
 /// name, then normalized path. Returns an error if no match is found.
-fn find_installation<'a>(
+pub(crate) fn find_installation<'a>(
     ide_config: &'a IdfConfig,
     identifier: &str,
 ) -> Result<&'a IdfInstallation, String> {
@@ -1015,6 +1087,8 @@ mod tests {
             path: idf_path.to_string(),
             python: format!("{}/tools/python/bin/python3", tools_path),
             installation_config: None,
+            idf_commit: None,
+            idf_source: None,
         }
     }
 
@@ -1293,6 +1367,53 @@ mod tests {
         assert_eq!(report.outdated[0].available, "14.2.0");
     }
 
+    #[test]
+    fn test_list_idf_tools_pinned_tool_is_not_outdated() {
+        let temp = TempDir::new().unwrap();
+        let idf_path = temp.path().join("v5.4/esp-idf");
+        let tools_path = temp.path().join("v5.4/tools");
+        fs::create_dir_all(tools_path.join("xtensa-esp-elf").join("13.2.0")).unwrap();
+        let tool = make_tool(
+            "xtensa-esp-elf",
+            "Xtensa toolchain",
+            "always",
+            vec![
+                make_version("13.2.0", "supported", &["any"]),
+                make_version("14.2.0", "recommended", &["any"]),
+            ],
+        );
+        write_fake_tools_json(&idf_path, vec![tool]);
+
+        let mut settings = Settings::default();
+        settings.tool_pins = Some(std::collections::HashMap::from([(
+            "xtensa-esp-elf".to_string(),
+            "13.2.0".to_string(),
+        )]));
+        let mut installation =
+            make_idf_installation(idf_path.to_str().unwrap(), tools_path.to_str().unwrap());
+        installation.installation_config = Some(settings.to_installation_config().unwrap());
+        let mut config = IdfConfig {
+            git_path: "/usr/bin/git".to_string(),
+            idf_installed: vec![installation],
+            idf_selected_id: "esp-idf-test-id".to_string(),
+            eim_path: None,
+            version: Some("2.0".to_string()),
+        };
+        let config_path = temp.path().join("eim_idf.json");
+        config.to_file(&config_path, true, false).unwrap();
+
+        let report =
+            list_idf_tools(Some("esp-idf-test-id"), true, Some(&config_path)).unwrap();
+        assert!(report.outdated.is_empty());
+        assert_eq!(report.tools[0].pinned.as_deref(), Some("13.2.0"));
+        let installed = report.tools[0]
+            .version_inspections
+            .iter()
+            .find_map(|vi| vi.installed.as_ref())
+            .unwrap();
+        assert!(installed.is_recommended_match);
+    }
+
     #[test]
     fn test_list_idf_tools_outdated_empty_when_nothing_installed() {
         let temp = TempDir::new().unwrap();
@@ -8,7 +8,6 @@ use idf_im_lib::idf_tools::get_list_of_tools_to_download;
 use idf_im_lib::python_utils::download_constraints_file;
 use idf_im_lib::settings::Settings;
 use idf_im_lib::system_dependencies::get_latest_git_for_windows_url;
-use idf_im_lib::utils::extract_zst_archive;
 use idf_im_lib::utils::{parse_cmake_version};
 use idf_im_lib::verify_file_checksum;
 use idf_im_lib::offline_installer::merge_requirements_files;
@@ -288,6 +287,23 @@ pub fn update_progress_bar_number(pb: &ProgressBar, value: u64) {
     pb.set_position(value);
 }
 
+/// Environment of the pip and uv processes: the configured proxy, and the CA bundle for uv,
+/// which reads it from `SSL_CERT_FILE` as it has no `--cert` option.
+fn network_env() -> Vec<(&'static str, String)> {
+    let mut env = idf_im_lib::proxy::subprocess_env();
+    if let Some(bundle) = idf_im_lib::tls::active_bundle_path() {
+        env.push(("SSL_CERT_FILE", bundle.to_string_lossy().into_owned()));
+    }
+    env
+}
+
+/// `--cert` arguments making pip trust the configured CA bundle, as the online installation does.
+fn pip_cert_args() -> Vec<String> {
+    idf_im_lib::tls::active_bundle_path()
+        .map(|bundle| vec!["--cert".to_string(), bundle.to_string_lossy().into_owned()])
+        .unwrap_or_default()
+}
+
 /// Downloads wheels for multiple Python versions
 ///
 /// # Arguments
@@ -295,6 +311,8 @@ pub fn update_progress_bar_number(pb: &ProgressBar, value: u64) {
 /// * `requirements_path` - Path to the requirements file
 /// * `constraint_file` - Path to the constraints file
 /// * `python_versions` - List of Python versions to download wheels for
+/// * `pip_platforms` - pip platform tags of the target when it is not this machine, only
+///   prebuilt wheels are downloaded then
 ///
 /// # Returns
 /// `Vec<PythonVersionResult>` - Results for each Python version
@@ -303,15 +321,21 @@ async fn download_wheels_for_python_versions(
     requirements_path: &Path,
     constraint_file: &Path,
     python_versions: &[&str],
+    pip_platforms: Option<&[&str]>,
 ) -> Vec<PythonVersionResult> {
     info!("Downloading wheels for Python versions: {:?}", python_versions);
 
     let mut results = Vec::new();
+    let network_env = network_env();
+    let env: Vec<(&str, &str)> = network_env.iter().map(|(key, value)| (*key, value.as_str())).collect();
+    let cert_args = pip_cert_args();
+    let mut pip_env = env.clone();
+    pip_env.push(("PIP_MAX_ROUNDS", "300"));
 
     // First, ensure all Python versions are installed
     for python_version in python_versions {
         info!("Installing Python {}...", python_version);
-        match execute_command("uv", &["python", "install", python_version]) {
+        match execute_command_with_env("uv", &vec!["python", "install", *python_version], env.clone()) {
             Ok(output) => {
                 if output.status.success() {
                     info!("Python {} installed successfully.", python_version);
@@ -449,9 +473,12 @@ async fn download_wheels_for_python_versions(
 
         // Upgrade pip to latest version
         info!("Upgrading pip for Python {}...", python_version);
-        let _ = execute_command(
+        let mut upgrade_args = vec!["-m", "pip", "install", "--upgrade", "pip"];
+        upgrade_args.extend(cert_args.iter().map(String::as_str));
+        let _ = execute_command_with_env(
             python_executable.to_str().unwrap(),
-            &["-m", "pip", "install", "--upgrade", "pip"],
+            &upgrade_args,
+            env.clone(),
         );
 
         // Download wheels for this Python version - TWO STEP APPROACH
@@ -462,25 +489,35 @@ async fn download_wheels_for_python_versions(
 
         std::env::set_var("PIP_MAX_ROUNDS", "200");
 
+        let mut binary_args = vec![
+            "-m", "pip", "download",
+            "-r", requirements_path.to_str().unwrap(),
+            "-c", constraint_file.to_str().unwrap(),
+            "--dest", wheel_dir.to_str().unwrap(),
+            "--only-binary=:all:",
+            "--index-url", "https://dl.espressif.com/pypi/",
+            "--extra-index-url", "https://pypi.org/simple",
+        ];
+        for platform in pip_platforms.unwrap_or_default() {
+            binary_args.extend(["--platform", *platform]);
+        }
+        binary_args.extend(cert_args.iter().map(String::as_str));
         let binary_result = execute_command_with_env(
             python_executable.to_str().unwrap(),
-            &vec![
-                "-m", "pip", "download",
-                "-r", requirements_path.to_str().unwrap(),
-                "-c", constraint_file.to_str().unwrap(),
-                "--dest", wheel_dir.to_str().unwrap(),
-                "--only-binary=:all:",
-                "--index-url", "https://dl.espressif.com/pypi/",
-                "--extra-index-url", "https://pypi.org/simple",
-            ],
-            vec!(("PIP_MAX_ROUNDS", "300"))
+            &binary_args,
+            pip_env.clone(),
         );
 
         let mut source_built = Vec::new();
         let mut result = binary_result;
 
         // STEP 2: If binary-only failed, retry allowing source builds
-        if result.is_err() || !result.as_ref().unwrap().status.success() {
+        if pip_platforms.is_some() && (result.is_err() || !result.as_ref().unwrap().status.success()) {
+            warn!(
+                "Binary-only download failed for Python {}, packages cannot be built from source for another platform",
+                python_version
+            );
+        } else if result.is_err() || !result.as_ref().unwrap().status.success() {
             warn!("Binary-only download failed for Python {}", python_version);
 
             if let Ok(ref output) = result {
@@ -490,17 +527,19 @@ async fn download_wheels_for_python_versions(
 
             info!("STEP 2: Retrying with source builds allowed for Python {}...", python_version);
 
+            let mut source_args = vec!(
+                "-m", "pip", "download", "--verbose",
+                "-r", requirements_path.to_str().unwrap(),
+                "-c", constraint_file.to_str().unwrap(),
+                "--dest", wheel_dir.to_str().unwrap(),
+                "--index-url", "https://dl.espressif.com/pypi/",
+                "--extra-index-url", "https://pypi.org/simple",
+            );
+            source_args.extend(cert_args.iter().map(String::as_str));
             result = execute_command_with_env(
                 python_executable.to_str().unwrap(),
-                &vec!(
-                    "-m", "pip", "download", "--verbose",
-                    "-r", requirements_path.to_str().unwrap(),
-                    "-c", constraint_file.to_str().unwrap(),
-                    "--dest", wheel_dir.to_str().unwrap(),
-                    "--index-url", "https://dl.espressif.com/pypi/",
-                    "--extra-index-url", "https://pypi.org/simple",
-                ),
-                vec!(("PIP_MAX_ROUNDS", "300"))
+                &source_args,
+                pip_env.clone(),
             );
 
             // Parse output to find packages that were built from source
@@ -575,7 +614,10 @@ async fn download_wheels_for_python_versions(
     results
 }
 
-fn get_architecture() -> String {
+fn get_architecture(host_platform: Option<&str>) -> String {
+    if let Some(platform) = host_platform {
+        return platform.to_string();
+    }
     match (std::env::consts::OS, std::env::consts::ARCH) {
         ("linux", "x86_64") => "linux-x64".to_string(),
         ("linux", "aarch64") => "linux-aarch64".to_string(),
@@ -586,6 +628,23 @@ fn get_architecture() -> String {
     }
 }
 
+/// pip platform tags of the wheels that run on a tools.json `platform`. pip also accepts older
+/// macOS versions and the legacy manylinux tags compatible with the ones given.
+fn pip_platform_tags(platform: &str) -> Vec<&'static str> {
+    match platform {
+        "win32" => vec!["win32"],
+        "win64" => vec!["win_amd64"],
+        "macos" => vec!["macosx_11_0_x86_64"],
+        "macos-arm64" => vec!["macosx_11_0_arm64"],
+        "linux-i686" => vec!["manylinux_2_28_i686", "manylinux2014_i686"],
+        "linux-amd64" => vec!["manylinux_2_28_x86_64", "manylinux2014_x86_64"],
+        "linux-arm64" => vec!["manylinux_2_28_aarch64", "manylinux2014_aarch64"],
+        "linux-armel" => vec!["linux_armv6l"],
+        "linux-armhf" => vec!["manylinux_2_31_armv7l", "linux_armv7l"],
+        _ => vec![],
+    }
+}
+
 #[derive(Parser, Debug)]
 #[command(
     name = "offline_installer_builder",
@@ -644,6 +703,11 @@ struct Args {
     /// Custom log directory (default: system log directory)
     #[arg(long, value_name = "DIR")]
     log_dir: Option<PathBuf>,
+
+    /// Build the archive for another platform (e.g. "win64", "linux-arm64", "macos-arm64")
+    /// Tools, prerequisites and wheels are downloaded for that platform instead of this machine
+    #[arg(long, value_name = "PLATFORM", value_parser = idf_im_lib::idf_tools::parse_host_platform)]
+    host_platform: Option<String>,
 }
 
 #[tokio::main]
@@ -662,7 +726,19 @@ async fn main() {
         error!("Failed to initialize logging: {e}");
     }
 
-    let architecture = get_architecture();
+    if let Err(err) = idf_im_lib::idf_tools::set_host_platform(args.host_platform.as_deref()) {
+        error!("{}", err);
+        return;
+    }
+    let architecture = get_architecture(args.host_platform.as_deref());
+    let target_os = match args.host_platform.as_deref() {
+        Some(platform) if platform.starts_with("win") => "windows",
+        Some(platform) if platform.starts_with("macos") => "macos",
+        Some(_) => "linux",
+        None => std::env::consts::OS,
+    };
+    // Wheels of another platform can only be downloaded prebuilt, pip cannot build them here
+    let pip_platforms = args.host_platform.as_deref().map(pip_platform_tags);
 
     if args.create_from_config.is_some() {
         info!(
@@ -695,6 +771,11 @@ async fn main() {
                 return;
             }
         };
+        // Downloads, git and the pip and uv processes go through the configured proxy and CA bundle
+        if let Err(err) = idf_im_lib::apply_network_settings(&settings) {
+            error!("{}", err);
+            return;
+        }
         let archive_dir = TempDir::new().expect("Failed to create temporary directory");
         let global_python_version = args.python_version.unwrap_or_else(|| PYTHON_VERSION.to_string());
         info!("Using Python version: {}", global_python_version);
@@ -741,7 +822,7 @@ async fn main() {
         }
 
         // DOWNLOAD SHARED PREREQUISITES ONCE (Windows only)
-        let shared_prereq_dir: Option<TempDir> = if std::env::consts::OS == "windows" {
+        let shared_prereq_dir: Option<TempDir> = if target_os == "windows" {
             let temp_shared = TempDir::new().expect("Failed to create shared prereq temp dir");
             let prereq_path = temp_shared.path();
             ensure_path(prereq_path.to_str().unwrap()).expect("Failed to create prereq dir");
@@ -776,11 +857,11 @@ async fn main() {
 
             info!("Shared Windows prerequisites downloaded to: {:?}", temp_shared.path());
             Some(temp_shared)
-        } else if std::env::consts::OS == "linux" || std::env::consts::OS == "macos" {
+        } else if target_os == "linux" || target_os == "macos" {
             info!("Detected Unix-like OS, prerequisites installation not implemented — skipping.");
             None
         } else {
-            error!("Unsupported OS: {}", std::env::consts::OS);
+            error!("Unsupported OS: {}", target_os);
             return;
         };
 
@@ -846,6 +927,9 @@ async fn main() {
                 &idf_version,
                 settings.idf_mirror.as_deref(),
                 true,
+                None, // archives must not depend on a local object store
+                &idf_im_lib::git_tools::IdfCloneMode::default(), // archives carry the full tree
+                &[], // archives carry stock ESP-IDF
                 tx,
             ) {
                 Ok(_) => info!("ESP-IDF version {} downloaded successfully.", idf_version),
@@ -892,15 +976,17 @@ async fn main() {
 
             // Install idf-component-manager using uv pip
             info!("Installing idf-component-manager...");
-            match execute_command(
+            let network_env = network_env();
+            match execute_command_with_env(
                 "uv",
-                &[
+                &vec![
                     "pip",
                     "install",
                     "--python",
                     compote_env.to_str().unwrap(),
                     "idf-component-manager",
                 ],
+                network_env.iter().map(|(key, value)| (*key, value.as_str())).collect(),
             ) {
                 Ok(output) => {
                     if !output.status.success() {
@@ -989,7 +1075,7 @@ async fn main() {
                 .expect("Failed to convert tools json path")
                 .to_string();
 
-            let tools = match idf_im_lib::idf_tools::read_and_parse_tools_file(&tools_json_file) {
+            let tools = match idf_im_lib::idf_tools::read_and_parse_tools_file(&tools_json_file, settings.tools_overlay.as_deref()) {
                 Ok(tools) => tools,
                 Err(err) => {
                     error!("Failed to read tools json file: {}", err);
@@ -1066,6 +1152,7 @@ async fn main() {
                 &requirements_file,
                 &constraint_file,
                 &wheel_versions,
+                pip_platforms.as_deref(),
             ).await;
 
             summary.python_versions = python_results;
@@ -1181,7 +1268,10 @@ async fn main() {
             .unwrap_or_else(|| Path::new("."))
             .join(format!("{}_extracted", archive_stem));
 
-        match extract_zst_archive(&archive_path, &extract_dir) {
+        match idf_im_lib::decompress_archive(
+            &archive_path.to_string_lossy(),
+            &extract_dir.to_string_lossy(),
+        ) {
             Ok(_) => {
                 info!("Successfully extracted archive to: {:?}", extract_dir);
                 info!("You can now examine the contents for debugging purposes.");